@@ -1,187 +1,528 @@
-use quote::quote;
-use syn::{ItemFn, parse_macro_input};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{
+    Error, FnArg, GenericArgument, ItemFn, LitBool, LitStr, Pat, PatIdent, PatType, PathArguments,
+    ReturnType, Type, TypePath, Visibility, meta::ParseNestedMeta, parse_macro_input,
+    spanned::Spanned,
+};
 
-#[proc_macro_attribute]
-pub fn extract_fn(
-    _attr: proc_macro::TokenStream,
-    item: proc_macro::TokenStream,
-) -> proc_macro::TokenStream {
-    let mut function = parse_macro_input!(item as ItemFn);
+/// The kinds of handler the macros export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handler {
+    Extract,
+    Transform,
+    Listener,
+}
 
-    if !matches!(function.vis, syn::Visibility::Public(..)) {
-        panic!("extract_fn expects a public function");
+impl Handler {
+    fn macro_name(self) -> &'static str {
+        match self {
+            Self::Extract => "extract_fn",
+            Self::Transform => "transform_fn",
+            Self::Listener => "listener_fn",
+        }
     }
 
-    let name = &function.sig.ident;
-    let generics = &function.sig.generics;
-    let inputs = &mut function.sig.inputs;
-    let output = &mut function.sig.output;
-    let block = &function.block;
+    /// The return type the handler must have, as shown in errors
+    fn output(self) -> &'static str {
+        match self {
+            Self::Extract => "FnResult<Option<ExtractResponse>> or FnResult<Option<ExtractReport>>",
+            Self::Transform | Self::Listener => "FnResult<TransformResponse<T>>",
+        }
+    }
 
-    if name == "main" {
-        panic!(
-            "extract_fn must not be applied to a `main` function. To fix, rename this to something other than `main`."
-        );
+    /// The last path segments accepted at each level of the return type
+    fn output_idents(self) -> &'static [&'static [&'static str]] {
+        match self {
+            Self::Extract => &[
+                &["FnResult"],
+                &["Option"],
+                &["ExtractResponse", "ExtractReport"],
+            ],
+            Self::Transform | Self::Listener => &[&["FnResult"], &["TransformResponse"]],
+        }
     }
 
-    if !generics.params.is_empty() {
-        panic!("extract_fn expects a function with no generics");
+    /// The input type records are filtered on with `record_type`, as shown in errors
+    fn records_input(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::Extract => None,
+            Self::Transform => Some(("Transform", "Transform<T, J, M>")),
+            Self::Listener => Some(("RecordChange", "RecordChange<R>")),
+        }
     }
 
-    let err_message = "extract_fn expects a function that accepts one parameter";
-    let (input_name, input_ty) = if inputs.len() != 1 {
-        panic!("{}", err_message);
-    } else {
-        match inputs.first().unwrap() {
-            syn::FnArg::Typed(syn::PatType { pat, ty, .. }) => match pat.as_ref() {
-                syn::Pat::Ident(syn::PatIdent { ident, .. }) => (ident, ty),
-                _ => panic!("{}", err_message),
+    /// Has the compiler check the return type of `inner`, which sees through aliases.
+    /// Errors point at `output_ty`, as an alias may hide a different type.
+    fn check_output(self, input_ty: &Type, output_ty: &Type) -> TokenStream {
+        let span = output_ty.span();
+        match self {
+            Self::Extract => quote_spanned! {span=>
+                fn check_output<O: contour_rust_pdk::response::ExtractOutput>(
+                    _: fn(#input_ty) -> contour_rust_pdk::extism_pdk::FnResult<::core::option::Option<O>>,
+                ) {
+                }
+                check_output(inner);
+            },
+            Self::Transform | Self::Listener => quote_spanned! {span=>
+                let _: fn(#input_ty) -> contour_rust_pdk::extism_pdk::FnResult<
+                    contour_rust_pdk::response::TransformResponse<_>
+                > = inner;
             },
-            _ => panic!("{}", err_message),
         }
-    };
+    }
+}
 
-    let err_message =
-        "extract_fn expects a function that returns FnResult<Option<ExtractResponse>>";
-    match output {
-        syn::ReturnType::Type(_, t) => {
-            if let syn::Type::Path(p) = t.as_ref() {
-                if let Some(t) = p.path.segments.last() {
-                    if t.ident != "FnResult" {
-                        panic!("{}", err_message);
-                    } else if let syn::PathArguments::AngleBracketed(args) = &t.arguments {
-                        if args.args.len() == 1 {
-                            if let Some(syn::GenericArgument::Type(typ)) = args.args.first() {
-                                let ty_string = quote!(#typ).to_string().replace(' ', "");
-                                if !ty_string.contains("Option<")
-                                    || !ty_string.contains("ExtractResponse")
-                                {
-                                    panic!("{}", err_message);
-                                }
-                            } else {
-                                panic!("{}", err_message);
-                            }
-                        } else {
-                            panic!("{}", err_message);
-                        }
-                    }
-                } else {
-                    panic!("{}", err_message);
-                }
-            }
+/// Arguments of the macros, such as
+/// `#[transform_fn(name = "transform", record_type = "Transaction", validate = true)]`
+#[derive(Default)]
+struct Args {
+    /// The exported symbol, when it should differ from the function name
+    name: Option<LitStr>,
+    /// Only records of this type are passed to a transform or listener
+    record_type: Option<LitStr>,
+    /// Validates and logs the `EntryInput` a transform or listener returns. An invalid
+    /// entry is reported as `InvalidInput`.
+    validate: bool,
+}
+
+impl Args {
+    fn parse(&mut self, meta: ParseNestedMeta, handler: Handler) -> syn::Result<()> {
+        let records = handler != Handler::Extract;
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if records && meta.path.is_ident("record_type") {
+            self.record_type = Some(meta.value()?.parse()?);
+        } else if records && meta.path.is_ident("validate") {
+            self.validate = meta.value()?.parse::<LitBool>()?.value;
+        } else {
+            return Err(meta.error(format!("unsupported {} argument", handler.macro_name())));
         }
-        _ => panic!("{}", err_message),
-    };
+        Ok(())
+    }
+}
 
-    token_stream(name, generics, output, block, input_name, input_ty)
+#[proc_macro_attribute]
+pub fn extract_fn(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    handler_fn(Handler::Extract, attr, item)
 }
 
 #[proc_macro_attribute]
 pub fn transform_fn(
-    _attr: proc_macro::TokenStream,
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    handler_fn(Handler::Transform, attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn listener_fn(
+    attr: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let mut function = parse_macro_input!(item as ItemFn);
+    handler_fn(Handler::Listener, attr, item)
+}
+
+fn handler_fn(
+    handler: Handler,
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| args.parse(meta, handler));
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+
+    expand(handler, &args, &function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(handler: Handler, args: &Args, function: &ItemFn) -> syn::Result<TokenStream> {
+    let macro_name = handler.macro_name();
+    let sig = &function.sig;
 
-    if !matches!(function.vis, syn::Visibility::Public(..)) {
-        panic!("transform_fn expects a public function");
+    if !matches!(function.vis, Visibility::Public(..)) {
+        return Err(Error::new_spanned(
+            sig.fn_token,
+            format!("{} expects a public function", macro_name),
+        ));
     }
 
-    let name = &function.sig.ident;
-    let generics = &function.sig.generics;
-    let inputs = &mut function.sig.inputs;
-    let output = &mut function.sig.output;
-    let block = &function.block;
+    if sig.ident == "main" {
+        return Err(Error::new_spanned(
+            &sig.ident,
+            format!(
+                "{} must not be applied to a `main` function. To fix, rename this to something other than `main`.",
+                macro_name
+            ),
+        ));
+    }
 
-    if name == "main" {
-        panic!(
-            "transform_fn must not be applied to a `main` function. To fix, rename this to something other than `main`."
-        );
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            format!("{} expects a function with no generics", macro_name),
+        ));
+    }
+
+    let message = format!(
+        "{} expects a function that accepts one parameter",
+        macro_name
+    );
+    let (input_name, input_ty) = match sig.inputs.first() {
+        _ if sig.inputs.is_empty() => return Err(Error::new(sig.paren_token.span.join(), message)),
+        Some(FnArg::Typed(PatType { pat, ty, .. })) if sig.inputs.len() == 1 => {
+            match pat.as_ref() {
+                Pat::Ident(PatIdent { ident, .. }) => (ident, ty.as_ref()),
+                _ => return Err(Error::new_spanned(pat, message)),
+            }
+        }
+        _ => return Err(Error::new_spanned(&sig.inputs, message)),
+    };
+
+    let message = format!(
+        "{} expects a function that returns {}",
+        macro_name,
+        handler.output()
+    );
+    let output_ty = match &sig.output {
+        ReturnType::Type(_, ty) => ty.as_ref(),
+        ReturnType::Default => return Err(Error::new_spanned(sig, message)),
+    };
+    check_type(output_ty, handler.output_idents(), &message)?;
+
+    if let (Some(record_type), Some((ident, shown))) = (&args.record_type, handler.records_input())
+        && check_type(input_ty, &[&[ident]], "").is_err()
+    {
+        return Err(Error::new_spanned(
+            record_type,
+            format!("record_type expects a function that accepts {}", shown),
+        ));
     }
 
-    if !generics.params.is_empty() {
-        panic!("transform_fn expects a function with no generics");
+    // `Command` inputs are decoded using the `command_type` of the handler input
+    let tagged =
+        handler == Handler::Extract && last_ident(input_ty).is_some_and(|t| t == "Command");
+
+    Ok(token_stream(
+        handler, args, function, input_name, input_ty, output_ty, tagged,
+    ))
+}
+
+/// Checks `ty` has the shape `idents[0]<idents[1]<..>>`, where each level lists the
+/// idents accepted there. Only the last segment of each path is compared, so qualified
+/// paths match. A path without generic arguments that does not match may be a type
+/// alias, and is left for the compiler to check.
+fn check_type(ty: &Type, idents: &[&[&str]], message: &str) -> syn::Result<()> {
+    let Some((ident, inner)) = idents.split_first() else {
+        return Ok(());
+    };
+    let segment = match ty {
+        Type::Group(group) => return check_type(&group.elem, idents, message),
+        Type::Paren(paren) => return check_type(&paren.elem, idents, message),
+        Type::Path(TypePath { qself: None, path }) => path.segments.last(),
+        _ => None,
+    };
+    let Some(segment) = segment else {
+        return Err(Error::new_spanned(ty, message));
+    };
+
+    if !ident.iter().any(|ident| segment.ident == ident) {
+        // A missing level, such as `FnResult<ExtractResponse>`, is not an alias
+        let alias = matches!(segment.arguments, PathArguments::None)
+            && !inner
+                .iter()
+                .flat_map(|level| level.iter())
+                .any(|inner| segment.ident == inner);
+        return if alias {
+            Ok(())
+        } else {
+            Err(Error::new_spanned(ty, message))
+        };
+    }
+    if inner.is_empty() {
+        return Ok(());
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match arguments.args.first() {
+                Some(GenericArgument::Type(ty)) => check_type(ty, inner, message),
+                _ => Err(Error::new_spanned(arguments, message)),
+            }
+        }
+        _ => Err(Error::new_spanned(segment, message)),
     }
+}
 
-    let err_message = "transform_fn expects a function that accepts one parameter";
-    let (input_name, input_ty) = if inputs.len() != 1 {
-        panic!("{}", err_message);
+fn last_ident(ty: &Type) -> Option<&syn::Ident> {
+    match ty {
+        Type::Path(TypePath { path, .. }) => path.segments.last().map(|t| &t.ident),
+        _ => None,
+    }
+}
+
+fn token_stream(
+    handler: Handler,
+    args: &Args,
+    function: &ItemFn,
+    input_name: &syn::Ident,
+    input_ty: &Type,
+    output_ty: &Type,
+    tagged: bool,
+) -> TokenStream {
+    let name = &function.sig.ident;
+    let output = &function.sig.output;
+    let block = &function.block;
+
+    let check_output = handler.check_output(input_ty, output_ty);
+
+    let command = if tagged {
+        quote! {
+            let input = match contour_rust_pdk::extism_pdk::input::<
+                contour_rust_pdk::extism_pdk::Json<
+                    contour_rust_pdk::inputs::HandlerInput::<contour_rust_pdk::extism_pdk::json::Value>
+                >
+            >() {
+                Ok(contour_rust_pdk::extism_pdk::Json(input)) => input,
+                Err(e) => return report(contour_rust_pdk::error::PluginError::invalid_input(format!("{:#}", e))),
+            };
+            let command = match <#input_ty>::from_command_type(&input.command_type, input.command) {
+                Ok(command) => command,
+                Err(e) => return report(contour_rust_pdk::error::PluginError::invalid_input(format!("{:#}", e))),
+            };
+        }
     } else {
-        match inputs.first().unwrap() {
-            syn::FnArg::Typed(syn::PatType { pat, ty, .. }) => match pat.as_ref() {
-                syn::Pat::Ident(syn::PatIdent { ident, .. }) => (ident, ty),
-                _ => panic!("{}", err_message),
-            },
-            _ => panic!("{}", err_message),
+        quote! {
+            let command = match contour_rust_pdk::extism_pdk::input::<
+                contour_rust_pdk::extism_pdk::Json<contour_rust_pdk::inputs::HandlerInput::<#input_ty>>
+            >() {
+                Ok(contour_rust_pdk::extism_pdk::Json(input)) => input.command,
+                Err(e) => return report(contour_rust_pdk::error::PluginError::invalid_input(format!("{:#}", e))),
+            };
+        }
+    };
+
+    let export = match &args.name {
+        Some(export_name) => quote!(#[unsafe(export_name = #export_name)]),
+        None => quote!(#[unsafe(no_mangle)]),
+    };
+
+    // The panic hook has already reported a panic
+    let mut run = quote! {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| inner(command))) {
+            Ok(Ok(x)) => x,
+            // A status set with `WithReturnCode::new` is kept over the error's exit code
+            Ok(Err(rc)) => {
+                let exit_code = report(contour_rust_pdk::error::PluginError::from(rc.0));
+                return if rc.1 == -1 { exit_code } else { rc.1 };
+            }
+            Err(_) => return contour_rust_pdk::error::PluginError::PANIC_EXIT_CODE,
         }
     };
-    let err_message = "transform_fn expects a function that returns FnResult<TransformResponse<T>>";
-
-    match output {
-        syn::ReturnType::Type(_, t) => {
-            if let syn::Type::Path(p) = t.as_ref() {
-                if let Some(t) = p.path.segments.last() {
-                    if t.ident != "FnResult" {
-                        panic!("{}", err_message);
-                    } else if let syn::PathArguments::AngleBracketed(args) = &t.arguments {
-                        if args.args.len() == 1 {
-                            if let Some(syn::GenericArgument::Type(typ)) = args.args.first() {
-                                let ty_string = quote!(#typ).to_string().replace(' ', "");
-                                if !ty_string.contains("TransformResponse<") {
-                                    panic!("{}", err_message);
-                                }
-                            } else {
-                                panic!("{}", err_message);
-                            }
-                        } else {
-                            panic!("{}", err_message);
-                        }
-                    }
+
+    // Records of other types are dropped, and the handler is skipped if none are left
+    match (handler, &args.record_type) {
+        // Spanned so a type alias for another input points at the argument
+        (Handler::Transform, Some(record_type)) => {
+            let retain = quote_spanned! {record_type.span()=>
+                command.records.retain(|record| record.record_type == #record_type);
+            };
+            run = quote! {{
+                let mut command = command;
+                #retain
+                if command.records.is_empty() {
+                    contour_rust_pdk::response::TransformResponse::None
                 } else {
-                    panic!("{}", err_message);
+                    #run
                 }
+            }};
+        }
+        (Handler::Listener, Some(record_type)) => {
+            let skip = quote_spanned! {record_type.span()=>
+                command.record_type != #record_type
+            };
+            run = quote! {
+                if #skip {
+                    contour_rust_pdk::response::TransformResponse::None
+                } else {
+                    #run
+                }
+            };
+        }
+        _ => {}
+    }
+
+    let validate = if args.validate {
+        quote! {
+            if let contour_rust_pdk::response::TransformResponse::EntryInput(entry) = &output {
+                if let Err(e) = entry.validate() {
+                    contour_rust_pdk::extism_pdk::warn!("{:#}", e);
+                    return report(contour_rust_pdk::error::PluginError::invalid_input(format!("{:#}", e)));
+                }
+                contour_rust_pdk::extism_pdk::info!(
+                    "Entry {} with {} lines",
+                    entry.source_key,
+                    entry.lines.len()
+                );
             }
         }
-        _ => panic!("{}", err_message),
+    } else {
+        quote!()
     };
 
-    token_stream(name, generics, output, block, input_name, input_ty)
-}
-
-fn token_stream(
-    name: &syn::Ident,
-    generics: &syn::Generics,
-    output: &mut syn::ReturnType,
-    block: &syn::Block,
-    input_name: &syn::Ident,
-    input_ty: &syn::Type,
-) -> proc_macro::TokenStream {
     quote! {
-        #[unsafe(no_mangle)]
+        #export
         pub unsafe extern "C" fn #name() -> i32 {
-            fn inner #generics(#input_name: #input_ty) #output {
+            fn inner(#input_name: #input_ty) #output {
                 #block
             }
+            #check_output
 
-            let contour_rust_pdk::extism_pdk::Json(input): contour_rust_pdk::extism_pdk::Json<
-                contour_rust_pdk::inputs::HandlerInput::<#input_ty>
-            > = contour_rust_pdk::extism_pdk::unwrap!(contour_rust_pdk::extism_pdk::input());
-
-            let output = match inner(input.command) {
-                Ok(x) => x,
-                Err(rc) => {
-                    let err = format!("{:?}", rc.0);
-                    let mut mem = contour_rust_pdk::extism_pdk::Memory::from_bytes(&err).unwrap();
-                    unsafe {
-                        contour_rust_pdk::extism_pdk::extism::error_set(mem.offset());
-                    }
-                    return rc.1;
+            // Reports the error to the host as JSON and returns its exit code
+            fn report(error: contour_rust_pdk::error::PluginError) -> i32 {
+                let mut mem = contour_rust_pdk::extism_pdk::Memory::from_bytes(error.to_json()).unwrap();
+                unsafe {
+                    contour_rust_pdk::extism_pdk::extism::error_set(mem.offset());
                 }
-            };
+                error.exit_code()
+            }
+
+            contour_rust_pdk::error::install_panic_hook(report);
+
+            #command
+
+            let output = #run;
+
+            #validate
 
             contour_rust_pdk::extism_pdk::unwrap!(contour_rust_pdk::extism_pdk::output(&output));
             0
         }
     }
-    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn error(handler: Handler, function: ItemFn) -> String {
+        expand(handler, &Args::default(), &function)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_accepts_qualified_paths_and_aliases() {
+        let types: [Type; 5] = [
+            parse_quote!(FnResult<Option<ExtractResponse>>),
+            parse_quote!(extism_pdk::FnResult<std::option::Option<response::ExtractResponse>>),
+            parse_quote!(ExtractResult),
+            parse_quote!(FnResult<Option<Response>>),
+            parse_quote!(FnResult<Option<ExtractReport>>),
+        ];
+        let idents = Handler::Extract.output_idents();
+        for ty in &types {
+            assert!(check_type(ty, idents, "").is_ok(), "{}", quote!(#ty));
+        }
+
+        let ty: Type = parse_quote!(FnResult<contour_rust_pdk::response::TransformResponse<()>>);
+        assert!(check_type(&ty, Handler::Transform.output_idents(), "").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_return_types() {
+        let types: [Type; 5] = [
+            parse_quote!(FnResult<ExtractResponse>),
+            parse_quote!(FnResult<ExtractReport>),
+            parse_quote!(Result<Option<ExtractResponse>, Error>),
+            parse_quote!(FnResult<Vec<ExtractResponse>>),
+            parse_quote!(FnResult<(Option<ExtractResponse>,)>),
+        ];
+        let idents = Handler::Extract.output_idents();
+        for ty in &types {
+            assert!(check_type(ty, idents, "").is_err(), "{}", quote!(#ty));
+        }
+
+        assert_eq!(
+            error(
+                Handler::Extract,
+                parse_quote!(
+                    pub fn extract(cron: Cron) {}
+                )
+            ),
+            "extract_fn expects a function that returns FnResult<Option<ExtractResponse>> or FnResult<Option<ExtractReport>>"
+        );
+        assert_eq!(
+            error(
+                Handler::Listener,
+                parse_quote!(
+                    pub fn listen(change: RecordChange<R>) -> FnResult<()> {}
+                )
+            ),
+            "listener_fn expects a function that returns FnResult<TransformResponse<T>>"
+        );
+    }
+
+    #[test]
+    fn test_rejects_record_type_without_records() {
+        let args = Args {
+            record_type: Some(parse_quote!("Payment")),
+            ..Args::default()
+        };
+        let err = expand(
+            Handler::Transform,
+            &args,
+            &parse_quote!(
+                pub fn transform(cmd: Command<Resync>) -> FnResult<TransformResponse<()>> {}
+            ),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "record_type expects a function that accepts Transform<T, J, M>"
+        );
+
+        let function: ItemFn = parse_quote!(
+            pub fn listen(change: RecordChange<Payment>) -> FnResult<TransformResponse<()>> {}
+        );
+        assert!(expand(Handler::Listener, &args, &function).is_ok());
+        assert!(expand(Handler::Transform, &args, &function).is_err());
+    }
+
+    #[test]
+    fn test_rejects_signatures() {
+        assert_eq!(
+            error(
+                Handler::Transform,
+                parse_quote!(
+                    fn transform(t: T) -> FnResult<TransformResponse<()>> {}
+                )
+            ),
+            "transform_fn expects a public function"
+        );
+        assert_eq!(
+            error(
+                Handler::Transform,
+                parse_quote!(
+                    pub fn transform() -> FnResult<TransformResponse<()>> {}
+                )
+            ),
+            "transform_fn expects a function that accepts one parameter"
+        );
+        assert_eq!(
+            error(
+                Handler::Extract,
+                parse_quote!(
+                    pub fn extract<T>(t: T) -> FnResult<Option<ExtractResponse>> {}
+                )
+            ),
+            "extract_fn expects a function with no generics"
+        );
+    }
 }
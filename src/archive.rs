@@ -0,0 +1,462 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use zip::{ZipArchive, result::ZipError};
+
+use crate::command::{Attachment, AttachmentKind};
+
+/// Extracts ZIP archives and decompresses gzip and deflate data, for zipped email
+/// attachments and compressed API exports.
+///
+/// Stored and deflated ZIP entries are supported, as is legacy ZipCrypto encryption.
+/// AES encrypted entries are not. Extraction stops with an error once more than
+/// [`Archive::MAX_SIZE`] bytes have been produced, so a small archive cannot exhaust
+/// the plugin's memory.
+///
+/// ```no_run
+/// use contour_rust_pdk::{archive::Archive, config, csv::CsvReader};
+///
+/// # fn run(bytes: &[u8]) -> anyhow::Result<()> {
+/// let password = config("zip_password")?;
+/// for file in Archive::extract_zip(bytes, Some(&password))? {
+///     if file.name.ends_with(".csv") {
+///         let table = CsvReader::new().read(&file.data)?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Archive;
+
+/// An entry in a ZIP archive's central directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    /// Path of the entry within the archive, with `/` separators
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub encrypted: bool,
+    pub is_dir: bool,
+}
+
+/// A file extracted from an archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl ArchiveFile {
+    /// The name without any directories
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    /// Detects the type of the file from its contents
+    pub fn kind(&self) -> AttachmentKind {
+        AttachmentKind::detect(&self.data)
+    }
+}
+
+impl Archive {
+    /// The most bytes a single call extracts or decompresses
+    pub const MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+    /// Lists the entries of a ZIP archive without extracting them
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a ZIP archive
+    pub fn zip_entries(bytes: &[u8]) -> Result<Vec<ArchiveEntry>> {
+        let mut archive = Self::open_zip(bytes)?;
+        (0..archive.len())
+            .map(|idx| {
+                let file = archive
+                    .by_index_raw(idx)
+                    .context("Failed to read ZIP entry")?;
+                Ok(ArchiveEntry {
+                    name: file.name().to_string(),
+                    size: file.size(),
+                    compressed_size: file.compressed_size(),
+                    encrypted: file.encrypted(),
+                    is_dir: file.is_dir(),
+                })
+            })
+            .collect()
+    }
+
+    /// Extracts every file in a ZIP archive, skipping directories. The password is only
+    /// used for encrypted entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a ZIP archive, an entry uses an unsupported
+    /// compression method, an encrypted entry has no or the wrong password, or the
+    /// files add up to more than [`Archive::MAX_SIZE`]
+    pub fn extract_zip(bytes: &[u8], password: Option<&str>) -> Result<Vec<ArchiveFile>> {
+        Self::extract_zip_limited(bytes, password, Self::MAX_SIZE)
+    }
+
+    fn extract_zip_limited(
+        bytes: &[u8],
+        password: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<ArchiveFile>> {
+        let mut archive = Self::open_zip(bytes)?;
+        let mut files = Vec::new();
+        let mut remaining = limit;
+        for idx in 0..archive.len() {
+            if let Some(file) = Self::extract_index(&mut archive, idx, password, remaining)? {
+                remaining -= file.data.len() as u64;
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Extracts a single file from a ZIP archive by its path
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive has no such file or it cannot be extracted, see
+    /// [`Archive::extract_zip`]
+    pub fn extract_zip_file(bytes: &[u8], name: &str, password: Option<&str>) -> Result<Vec<u8>> {
+        let mut archive = Self::open_zip(bytes)?;
+        let idx = archive
+            .index_for_name(name)
+            .ok_or_else(|| anyhow!("ZIP archive has no file {}", name))?;
+        Self::extract_index(&mut archive, idx, password, Self::MAX_SIZE)?
+            .map(|file| file.data)
+            .ok_or_else(|| anyhow!("ZIP entry {} is a directory", name))
+    }
+
+    fn open_zip(bytes: &[u8]) -> Result<ZipArchive<Cursor<&[u8]>>> {
+        ZipArchive::new(Cursor::new(bytes)).context("Failed to open ZIP archive")
+    }
+
+    fn extract_index(
+        archive: &mut ZipArchive<Cursor<&[u8]>>,
+        idx: usize,
+        password: Option<&str>,
+        limit: u64,
+    ) -> Result<Option<ArchiveFile>> {
+        let encrypted = archive
+            .by_index_raw(idx)
+            .context("Failed to read ZIP entry")?
+            .encrypted();
+        let file = match (encrypted, password) {
+            (true, Some(password)) => archive.by_index_decrypt(idx, password.as_bytes()),
+            _ => archive.by_index(idx),
+        };
+        let mut file = match file {
+            Ok(file) => file,
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                bail!("ZIP entry {} is encrypted and no password was given", idx)
+            }
+            Err(ZipError::InvalidPassword) => bail!("Wrong password for ZIP entry {}", idx),
+            Err(e) => return Err(e).context("Failed to read ZIP entry"),
+        };
+        if file.is_dir() {
+            return Ok(None);
+        }
+
+        let name = file.name().to_string();
+        let data = read_limited(&mut file, limit)
+            .with_context(|| format!("Failed to extract ZIP entry {}", name))?;
+        Ok(Some(ArchiveFile { name, data }))
+    }
+
+    /// Decompresses gzip data, including files of several concatenated gzip members
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid gzip, or decompresses to more than
+    /// [`Archive::MAX_SIZE`]
+    pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+        read_limited(MultiGzDecoder::new(bytes), Self::MAX_SIZE)
+            .context("Failed to decompress gzip data")
+    }
+
+    /// Decompresses deflate data. HTTP `Content-Encoding: deflate` is meant to be
+    /// zlib wrapped but some servers send raw deflate, so both are accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is neither zlib nor raw deflate, or decompresses to
+    /// more than [`Archive::MAX_SIZE`]
+    pub fn inflate(bytes: &[u8]) -> Result<Vec<u8>> {
+        if let Some(data) = unzlib(bytes)? {
+            return Ok(data);
+        }
+        read_limited(DeflateDecoder::new(bytes), Self::MAX_SIZE)
+            .context("Failed to decompress deflate data")
+    }
+
+    /// Decompresses gzip or zlib data, detected from its header. Anything else is
+    /// returned unchanged, so this is safe to call on payloads that may or may not be
+    /// compressed. As text can start with bytes that look like a zlib header, data
+    /// that does not decompress as zlib is returned unchanged too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data has a gzip header but does not decompress, or
+    /// decompresses to more than [`Archive::MAX_SIZE`]
+    pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            return Self::gunzip(bytes);
+        }
+        Ok(unzlib(bytes)?.unwrap_or_else(|| bytes.to_vec()))
+    }
+}
+
+/// The error for output over the size limit, told apart from malformed data
+#[derive(Debug)]
+pub(crate) struct SizeLimit(u64);
+
+impl std::fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Data is larger than the {} byte limit", self.0)
+    }
+}
+
+impl std::error::Error for SizeLimit {}
+
+/// Reads `reader` to the end, failing once more than `limit` bytes have been read
+fn read_limited(reader: impl Read, limit: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    read_limited_into(reader, limit, &mut data)?;
+    Ok(data)
+}
+
+/// Like [`read_limited`], but appends to `data` so callers can keep what was read
+/// before an error
+pub(crate) fn read_limited_into(reader: impl Read, limit: u64, data: &mut Vec<u8>) -> Result<()> {
+    let start = data.len();
+    reader.take(limit.saturating_add(1)).read_to_end(data)?;
+    if (data.len() - start) as u64 > limit {
+        return Err(SizeLimit(limit).into());
+    }
+    Ok(())
+}
+
+/// Decompresses zlib data, or returns `None` if the data is not valid zlib
+fn unzlib(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !is_zlib(bytes) {
+        return Ok(None);
+    }
+    match read_limited(ZlibDecoder::new(bytes), Archive::MAX_SIZE) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.is::<SizeLimit>() => Err(e).context("Failed to decompress zlib data"),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Checks for a zlib header: a deflate method byte with a window of at most 32 KiB, no
+/// preset dictionary, and check bits that make the first two bytes a multiple of 31
+fn is_zlib(bytes: &[u8]) -> bool {
+    match bytes {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && flg & 0x20 == 0
+                && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
+impl Attachment {
+    /// Unpacks a ZIP or gzip attachment. ZIP files yield every file they contain, and a
+    /// gzip file yields one file named after the attachment without its `.gz`
+    /// extension. Other attachments are returned as a single file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attachment cannot be decoded or extracted, see
+    /// [`Archive::extract_zip`]
+    pub fn unpack(&self, password: Option<&str>) -> Result<Vec<ArchiveFile>> {
+        let bytes = self.bytes()?;
+        let name = self.filename().unwrap_or_default();
+        if AttachmentKind::detect(&bytes) == AttachmentKind::Zip {
+            return Archive::extract_zip(&bytes, password);
+        }
+
+        let (name, data) = if bytes.starts_with(&[0x1f, 0x8b]) {
+            let name = match name.rsplit_once('.') {
+                Some((stem, ext)) if ext.eq_ignore_ascii_case("gz") => stem.to_string(),
+                _ => name,
+            };
+            (name, Archive::gunzip(&bytes)?)
+        } else {
+            (name, bytes)
+        };
+        Ok(vec![ArchiveFile { name, data }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use base64::{Engine, engine::general_purpose};
+    use flate2::{
+        Compression,
+        write::{GzEncoder, ZlibEncoder},
+    };
+    use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::csv::CsvReader;
+
+    const CSV: &[u8] = b"date,amount\n2024-03-01,-3.50\n";
+
+    /// `trades.csv` holding [`CSV`], encrypted with ZipCrypto and the password `s3cret`
+    const ENCRYPTED_ZIP: &str = "UEsDBAoACQAAAPOIUl2UAmhjKQAAAB0AAAAKABwAdHJhZGVzLmNzdlVUCQAD2vzUatr81Gp1eAsAAQQAAAAABAAAAADofwIDOIkXsQcvLICVRa3A8LSjsF1l8/G4NQ7/mRXXdY2eF4sPgei2LFBLBwiUAmhjKQAAAB0AAABQSwECHgMKAAkAAADziFJdlAJoYykAAAAdAAAACgAYAAAAAAABAAAApIEAAAAAdHJhZGVzLmNzdlVUBQAD2vzUanV4CwABBAAAAAAEAAAAAFBLBQYAAAAAAQABAFAAAAB9AAAAAAA=";
+
+    fn zip() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("reports/", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file(
+                "reports/trades.csv",
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+            )
+            .unwrap();
+        writer.write_all(CSV).unwrap();
+        writer
+            .start_file(
+                "readme.txt",
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+            )
+            .unwrap();
+        writer.write_all(b"hello").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_list_and_extract_zip() {
+        let zip = zip();
+        let entries = Archive::zip_entries(&zip).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+            vec!["reports/", "reports/trades.csv", "readme.txt"]
+        );
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].size, CSV.len() as u64);
+
+        let files = Archive::extract_zip(&zip, None).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file_name(), "trades.csv");
+        assert_eq!(files[0].kind(), AttachmentKind::Csv);
+
+        let table = CsvReader::new().read(&files[0].data).unwrap();
+        assert_eq!(
+            table.rows,
+            vec![vec!["date", "amount"], vec!["2024-03-01", "-3.50"]]
+        );
+
+        assert_eq!(
+            Archive::extract_zip_file(&zip, "readme.txt", None).unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            Archive::extract_zip_file(&zip, "missing.csv", None)
+                .unwrap_err()
+                .to_string(),
+            "ZIP archive has no file missing.csv"
+        );
+    }
+
+    #[test]
+    fn test_encrypted_zip() {
+        let zip = general_purpose::STANDARD.decode(ENCRYPTED_ZIP).unwrap();
+        assert!(Archive::zip_entries(&zip).unwrap()[0].encrypted);
+
+        let files = Archive::extract_zip(&zip, Some("s3cret")).unwrap();
+        assert_eq!(files[0].data, CSV);
+
+        assert_eq!(
+            Archive::extract_zip(&zip, None).unwrap_err().to_string(),
+            "ZIP entry 0 is encrypted and no password was given"
+        );
+        assert!(Archive::extract_zip(&zip, Some("wrong")).is_err());
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(Archive::gunzip(&gzip(CSV)).unwrap(), CSV);
+        assert_eq!(Archive::decompress(&gzip(CSV)).unwrap(), CSV);
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(CSV).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert_eq!(Archive::decompress(&zlib).unwrap(), CSV);
+        // Raw deflate is the zlib stream without its 2 byte header and 4 byte checksum
+        assert_eq!(Archive::inflate(&zlib[2..zlib.len() - 4]).unwrap(), CSV);
+
+        assert_eq!(Archive::decompress(CSV).unwrap(), CSV);
+        assert!(Archive::gunzip(CSV).is_err());
+
+        // Starts with a valid zlib header but is plain text
+        let text = b"x^ is not compressed";
+        assert!(is_zlib(text));
+        assert_eq!(Archive::decompress(text).unwrap(), text);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert_eq!(read_limited(&b"hello"[..], 5).unwrap(), b"hello");
+        assert_eq!(
+            read_limited(&b"hello"[..], 4).unwrap_err().to_string(),
+            "Data is larger than the 4 byte limit"
+        );
+
+        let zip = zip();
+        let limit = CSV.len() as u64 + 5;
+        assert_eq!(
+            Archive::extract_zip_limited(&zip, None, limit)
+                .unwrap()
+                .len(),
+            2
+        );
+        let err = Archive::extract_zip_limited(&zip, None, limit - 1).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to extract ZIP entry readme.txt");
+        assert!(err.root_cause().is::<SizeLimit>());
+    }
+
+    #[test]
+    fn test_unpack_attachment() {
+        let attachment = |filename: &str, bytes: &[u8]| Attachment {
+            content_type: "application/octet-stream".to_string(),
+            data: general_purpose::STANDARD.encode(bytes),
+            filename: Some(filename.to_string()),
+            ..Default::default()
+        };
+
+        let files = attachment("export.zip", &zip()).unpack(None).unwrap();
+        assert_eq!(files.len(), 2);
+
+        let files = attachment("trades.csv.GZ", &gzip(CSV))
+            .unpack(None)
+            .unwrap();
+        assert_eq!(
+            files,
+            vec![ArchiveFile {
+                name: "trades.csv".to_string(),
+                data: CSV.to_vec()
+            }]
+        );
+
+        let files = attachment("trades.csv", CSV).unpack(None).unwrap();
+        assert_eq!(files[0].data, CSV);
+    }
+}
@@ -0,0 +1,641 @@
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+
+use crate::inputs::{Effective, EntryInput, LineInput, ResourceSelector, TagSelector};
+use crate::statement::{COUNTERPARTY_TAG_TYPE, REMITTANCE_TAG_TYPE, parse_amount};
+
+/// A parser for ISO 20022 camt.053 bank statements and camt.054 debit/credit
+/// notifications. Elements are matched by local name, so any message version is
+/// accepted.
+pub struct Camt;
+
+impl Camt {
+    /// Parses every statement (`Stmt`) or notification (`Ntfctn`) in a camt.053 or
+    /// camt.054 document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document is not XML, is neither a camt.053 nor a
+    /// camt.054 message, or holds a malformed amount or date
+    pub fn parse(bytes: &[u8]) -> Result<Vec<CamtStatement>> {
+        let text = std::str::from_utf8(bytes).context("camt document is not UTF-8")?;
+        let doc = Document::parse(text).context("Failed to parse camt XML")?;
+
+        let (message, kind) = doc
+            .root_element()
+            .children()
+            .find_map(|node| match node.tag_name().name() {
+                "BkToCstmrStmt" => Some((node, CamtKind::Statement)),
+                "BkToCstmrDbtCdtNtfctn" => Some((node, CamtKind::Notification)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("Document is not a camt.053 or camt.054 message"))?;
+
+        let name = match kind {
+            CamtKind::Statement => "Stmt",
+            CamtKind::Notification => "Ntfctn",
+        };
+        children(message, name)
+            .map(|stmt| CamtStatement::from_node(stmt, kind))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CamtKind {
+    /// camt.053 end of day statement
+    Statement,
+    /// camt.054 debit/credit notification
+    Notification,
+}
+
+/// Which date of an entry becomes its [`Effective`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CamtDate {
+    /// The date the entry was booked (`BookgDt`)
+    Booking,
+    /// The date the funds are available or stop earning interest (`ValDt`)
+    Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtStatement {
+    pub kind: CamtKind,
+    pub id: String,
+    pub created: Option<Effective>,
+    pub account: CamtAccount,
+    pub balances: Vec<CamtBalance>,
+    pub entries: Vec<CamtEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtAccount {
+    pub iban: Option<String>,
+    /// A non-IBAN account number (`Othr/Id`)
+    pub other_id: Option<String>,
+    pub currency: Option<String>,
+    /// BIC of the servicing bank
+    pub servicer_bic: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtBalance {
+    /// Balance type code, for example `OPBD` (opening booked) or `CLBD` (closing
+    /// booked)
+    pub balance_type: String,
+    /// Negative when the balance is a debit
+    pub amount: Decimal,
+    pub currency: String,
+    pub date: Effective,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtEntry {
+    /// `NtryRef`
+    pub reference: Option<String>,
+    /// The bank's reference for the entry (`AcctSvcrRef`)
+    pub account_servicer_reference: Option<String>,
+    /// Positive for credits to the account, negative for debits
+    pub amount: Decimal,
+    pub currency: String,
+    /// Set when the entry reverses an earlier one
+    pub reversal: bool,
+    /// `BOOK`, `PDNG` or `INFO`
+    pub status: Option<String>,
+    pub booking_date: Option<Effective>,
+    pub value_date: Option<Effective>,
+    /// Proprietary bank transaction code, or the domain/family/subfamily codes joined
+    /// with `/`
+    pub bank_transaction_code: Option<String>,
+    pub additional_info: Option<String>,
+    pub transactions: Vec<CamtTransaction>,
+}
+
+/// The details of one transaction within an entry (`TxDtls`). Batch bookings have
+/// several.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtTransaction {
+    pub end_to_end_id: Option<String>,
+    pub amount: Option<Decimal>,
+    /// The debtor of a credit, or the creditor of a debit
+    pub counterparty: Option<CamtParty>,
+    /// Unstructured remittance information lines (`Ustrd`)
+    pub remittance: Vec<String>,
+    /// Structured creditor reference (`CdtrRefInf/Ref`)
+    pub creditor_reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamtParty {
+    pub name: Option<String>,
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+}
+
+impl CamtStatement {
+    fn from_node(stmt: Node, kind: CamtKind) -> Result<Self> {
+        let acct = child(stmt, "Acct").ok_or_else(|| anyhow!("camt statement has no Acct"))?;
+
+        Ok(Self {
+            kind,
+            id: text(stmt, &["Id"]).unwrap_or_default(),
+            created: text(stmt, &["CreDtTm"])
+                .map(|s| parse_date(&s))
+                .transpose()?,
+            account: CamtAccount {
+                iban: text(acct, &["Id", "IBAN"]),
+                other_id: text(acct, &["Id", "Othr", "Id"]),
+                currency: text(acct, &["Ccy"]),
+                servicer_bic: text(acct, &["Svcr", "FinInstnId", "BIC"])
+                    .or_else(|| text(acct, &["Svcr", "FinInstnId", "BICFI"])),
+            },
+            balances: children(stmt, "Bal")
+                .map(CamtBalance::from_node)
+                .collect::<Result<_>>()?,
+            entries: children(stmt, "Ntry")
+                .map(CamtEntry::from_node)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// The balance with the given type code, for example `CLBD`
+    pub fn balance(&self, balance_type: &str) -> Option<&CamtBalance> {
+        self.balances
+            .iter()
+            .find(|balance| balance.balance_type == balance_type)
+    }
+}
+
+impl CamtBalance {
+    fn from_node(bal: Node) -> Result<Self> {
+        let (amount, currency) = signed_amount(bal)?;
+        let date = child(bal, "Dt").ok_or_else(|| anyhow!("camt balance has no Dt"))?;
+
+        Ok(Self {
+            balance_type: text(bal, &["Tp", "CdOrPrtry", "Cd"])
+                .or_else(|| text(bal, &["Tp", "CdOrPrtry", "Prtry"]))
+                .unwrap_or_default(),
+            amount,
+            currency,
+            date: date_choice(date)?,
+        })
+    }
+}
+
+impl CamtEntry {
+    fn from_node(ntry: Node) -> Result<Self> {
+        let (amount, currency) = signed_amount(ntry)?;
+        let counterparty_role = if amount.is_sign_negative() {
+            "Cdtr"
+        } else {
+            "Dbtr"
+        };
+
+        Ok(Self {
+            reference: text(ntry, &["NtryRef"]),
+            account_servicer_reference: text(ntry, &["AcctSvcrRef"]),
+            amount,
+            currency,
+            reversal: text(ntry, &["RvslInd"]).is_some_and(|s| s == "true"),
+            // The status is a plain code before camt.053.001.08 and a `Cd` child after
+            status: text(ntry, &["Sts", "Cd"]).or_else(|| text(ntry, &["Sts"])),
+            booking_date: child(ntry, "BookgDt").map(date_choice).transpose()?,
+            value_date: child(ntry, "ValDt").map(date_choice).transpose()?,
+            bank_transaction_code: text(ntry, &["BkTxCd", "Prtry", "Cd"]).or_else(|| {
+                let domain = child(ntry, "BkTxCd").and_then(|code| child(code, "Domn"))?;
+                Some(format!(
+                    "{}/{}/{}",
+                    text(domain, &["Cd"])?,
+                    text(domain, &["Fmly", "Cd"])?,
+                    text(domain, &["Fmly", "SubFmlyCd"])?
+                ))
+            }),
+            additional_info: text(ntry, &["AddtlNtryInf"]),
+            transactions: children(ntry, "NtryDtls")
+                .flat_map(|details| children(details, "TxDtls"))
+                .map(|tx| CamtTransaction::from_node(tx, counterparty_role))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// The entry's reference: `NtryRef`, falling back to the bank's `AcctSvcrRef` and
+    /// then to the first end to end id
+    pub fn source_key(&self) -> Option<&str> {
+        self.reference
+            .as_deref()
+            .or(self.account_servicer_reference.as_deref())
+            .or_else(|| {
+                self.transactions
+                    .iter()
+                    .find_map(|tx| tx.end_to_end_id.as_deref())
+            })
+    }
+
+    /// Tags for the counterparties and remittance information of the entry
+    pub fn tags(&self) -> Vec<TagSelector> {
+        let mut tags = Vec::new();
+        for tx in &self.transactions {
+            if let Some(party) = &tx.counterparty
+                && let Some(key) = party.iban.as_ref().or(party.name.as_ref())
+            {
+                tags.push(TagSelector::SelectOrCreate {
+                    tag_type: COUNTERPARTY_TAG_TYPE.to_string(),
+                    source_key: key.clone(),
+                    name: party.name.clone(),
+                    data_type: None,
+                });
+            }
+
+            let remittance = tx
+                .creditor_reference
+                .clone()
+                .or_else(|| (!tx.remittance.is_empty()).then(|| tx.remittance.join(" ")));
+            if let Some(remittance) = remittance {
+                tags.push(TagSelector::SelectOrCreate {
+                    tag_type: REMITTANCE_TAG_TYPE.to_string(),
+                    source_key: remittance.clone(),
+                    name: Some(remittance),
+                    data_type: None,
+                });
+            }
+        }
+        tags.dedup();
+        tags
+    }
+
+    /// Maps the entry to an [`EntryInput`] with [`CamtEntry::source_key`] as the source
+    /// key and the chosen date as the effective date, falling back to the other date
+    /// when it is missing. The amount moves into `account` and is balanced against
+    /// `offset`, tagged with [`CamtEntry::tags`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry has no reference or no date
+    pub fn to_entry_input(
+        &self,
+        entry_type: String,
+        date: CamtDate,
+        account: ResourceSelector,
+        offset: ResourceSelector,
+    ) -> Result<EntryInput> {
+        let source_key = self
+            .source_key()
+            .ok_or_else(|| anyhow!("camt entry has no reference"))?;
+        let effective = match date {
+            CamtDate::Booking => self.booking_date.as_ref().or(self.value_date.as_ref()),
+            CamtDate::Value => self.value_date.as_ref().or(self.booking_date.as_ref()),
+        }
+        .ok_or_else(|| anyhow!("camt entry {} has no date", source_key))?;
+
+        Ok(EntryInput::new(
+            effective.clone(),
+            source_key.to_string(),
+            entry_type,
+            LineInput::transfer(
+                account,
+                offset,
+                self.amount,
+                self.description(),
+                self.tags(),
+            ),
+        ))
+    }
+
+    fn description(&self) -> Option<String> {
+        let remittance: Vec<&str> = self
+            .transactions
+            .iter()
+            .flat_map(|tx| tx.remittance.iter().map(String::as_str))
+            .collect();
+        if remittance.is_empty() {
+            self.additional_info.clone()
+        } else {
+            Some(remittance.join(" "))
+        }
+    }
+}
+
+impl CamtTransaction {
+    fn from_node(tx: Node, counterparty_role: &str) -> Result<Self> {
+        let parties = child(tx, "RltdPties");
+        let agents = child(tx, "RltdAgts");
+        let counterparty = parties.map(|parties| CamtParty {
+            // From camt.053.001.08 the party is wrapped in a `Pty` element
+            name: text(parties, &[counterparty_role, "Nm"])
+                .or_else(|| text(parties, &[counterparty_role, "Pty", "Nm"])),
+            iban: text(
+                parties,
+                &[&format!("{}Acct", counterparty_role), "Id", "IBAN"],
+            ),
+            bic: agents.and_then(|agents| {
+                let agent = format!("{}Agt", counterparty_role);
+                text(agents, &[&agent, "FinInstnId", "BIC"])
+                    .or_else(|| text(agents, &[&agent, "FinInstnId", "BICFI"]))
+            }),
+        });
+
+        Ok(Self {
+            end_to_end_id: text(tx, &["Refs", "EndToEndId"]).filter(|id| id != "NOTPROVIDED"),
+            amount: child(tx, "Amt")
+                .and_then(|amt| amt.text())
+                .map(|s| parse_amount(s, "camt"))
+                .transpose()?,
+            counterparty: counterparty.filter(|party| party.name.is_some() || party.iban.is_some()),
+            remittance: child(tx, "RmtInf")
+                .map(|rmt| {
+                    children(rmt, "Ustrd")
+                        .filter_map(|node| node.text())
+                        .map(|s| s.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            creditor_reference: child(tx, "RmtInf").and_then(|rmt| {
+                children(rmt, "Strd").find_map(|strd| text(strd, &["CdtrRefInf", "Ref"]))
+            }),
+        })
+    }
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+/// The trimmed text at the end of a path of child elements
+fn text(node: Node, path: &[&str]) -> Option<String> {
+    path.iter()
+        .try_fold(node, |node, name| child(node, name))?
+        .text()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// The `Amt` of a node, negated when its `CdtDbtInd` is `DBIT`
+fn signed_amount(node: Node) -> Result<(Decimal, String)> {
+    let amt = child(node, "Amt").ok_or_else(|| anyhow!("camt element has no Amt"))?;
+    let amount = parse_amount(amt.text().unwrap_or_default(), "camt")?;
+    let currency = amt.attribute("Ccy").unwrap_or_default().to_string();
+
+    match text(node, &["CdtDbtInd"]).as_deref() {
+        Some("CRDT") => Ok((amount, currency)),
+        Some("DBIT") => Ok((-amount, currency)),
+        other => bail!("Invalid camt credit/debit indicator {:?}", other),
+    }
+}
+
+/// Reads a `Dt` or `DtTm` choice
+fn date_choice(node: Node) -> Result<Effective> {
+    text(node, &["Dt"])
+        .or_else(|| text(node, &["DtTm"]))
+        .ok_or_else(|| anyhow!("camt date has no Dt or DtTm"))
+        .and_then(|s| parse_date(&s))
+}
+
+/// Parses an ISO date or date time. Date times without an offset are taken as UTC.
+fn parse_date(s: &str) -> Result<Effective> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(Effective::Date(date));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+        return Ok(Effective::DateTime(datetime.with_timezone(&Utc)));
+    }
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .map(|datetime| Effective::DateTime(datetime.and_utc()))
+        .map_err(|_| anyhow!("Invalid camt date {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const CAMT_053: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>MSG-1</MsgId><CreDtTm>2024-03-02T06:00:00+01:00</CreDtTm></GrpHdr>
+    <Stmt>
+      <Id>STMT-2024-03-01</Id>
+      <CreDtTm>2024-03-02T06:00:00+01:00</CreDtTm>
+      <Acct>
+        <Id><IBAN>DE89370400440532013000</IBAN></Id>
+        <Ccy>EUR</Ccy>
+        <Svcr><FinInstnId><BIC>COBADEFFXXX</BIC></FinInstnId></Svcr>
+      </Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1000.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2024-03-01</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">250.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Dt><Dt>2024-03-01</Dt></Dt>
+      </Bal>
+      <Ntry>
+        <NtryRef>E-1</NtryRef>
+        <Amt Ccy="EUR">1250.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-01</Dt></BookgDt>
+        <ValDt><Dt>2024-03-04</Dt></ValDt>
+        <AcctSvcrRef>BANK-REF-1</AcctSvcrRef>
+        <BkTxCd><Domn><Cd>PMNT</Cd><Fmly><Cd>ICDT</Cd><SubFmlyCd>ESCT</SubFmlyCd></Fmly></Domn></BkTxCd>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>INV-2024-001</EndToEndId></Refs>
+            <RltdPties>
+              <Cdtr><Nm>Landlord GmbH</Nm></Cdtr>
+              <CdtrAcct><Id><IBAN>DE02120300000000202051</IBAN></Id></CdtrAcct>
+            </RltdPties>
+            <RltdAgts><CdtrAgt><FinInstnId><BIC>BYLADEM1001</BIC></FinInstnId></CdtrAgt></RltdAgts>
+            <RmtInf><Ustrd>Rent March</Ustrd><Ustrd>Flat 3</Ustrd></RmtInf>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    const CAMT_054: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.054.001.08">
+  <BkToCstmrDbtCdtNtfctn>
+    <GrpHdr><MsgId>MSG-2</MsgId><CreDtTm>2024-03-05T10:00:00</CreDtTm></GrpHdr>
+    <Ntfctn>
+      <Id>NTF-1</Id>
+      <Acct><Id><Othr><Id>12345678</Id></Othr></Id></Acct>
+      <Ntry>
+        <Amt Ccy="CHF">99.95</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2024-03-05T09:15:00</DtTm></BookgDt>
+        <AcctSvcrRef>CH-REF-9</AcctSvcrRef>
+        <NtryDtls>
+          <TxDtls>
+            <RltdPties><Dbtr><Pty><Nm>Customer AG</Nm></Pty></Dbtr></RltdPties>
+            <RmtInf><Strd><CdtrRefInf><Ref>RF18539007547034</Ref></CdtrRefInf></Strd></RmtInf>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+    </Ntfctn>
+  </BkToCstmrDbtCdtNtfctn>
+</Document>"#;
+
+    fn date(s: &str) -> Effective {
+        Effective::Date(NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap())
+    }
+
+    #[test]
+    fn test_parse_camt_053() {
+        let statements = Camt::parse(CAMT_053.as_bytes()).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let statement = &statements[0];
+        assert_eq!(statement.kind, CamtKind::Statement);
+        assert_eq!(statement.id, "STMT-2024-03-01");
+        assert_eq!(
+            statement.account,
+            CamtAccount {
+                iban: Some("DE89370400440532013000".to_string()),
+                other_id: None,
+                currency: Some("EUR".to_string()),
+                servicer_bic: Some("COBADEFFXXX".to_string()),
+            }
+        );
+        assert_eq!(statement.balance("OPBD").unwrap().amount, dec!(1000.00));
+        assert_eq!(statement.balance("CLBD").unwrap().amount, dec!(-250.50));
+
+        let entry = &statement.entries[0];
+        assert_eq!(entry.amount, dec!(-1250.50));
+        assert_eq!(entry.status.as_deref(), Some("BOOK"));
+        assert_eq!(entry.value_date, Some(date("2024-03-04")));
+        assert_eq!(
+            entry.bank_transaction_code.as_deref(),
+            Some("PMNT/ICDT/ESCT")
+        );
+        assert_eq!(
+            entry.transactions[0],
+            CamtTransaction {
+                end_to_end_id: Some("INV-2024-001".to_string()),
+                amount: None,
+                counterparty: Some(CamtParty {
+                    name: Some("Landlord GmbH".to_string()),
+                    iban: Some("DE02120300000000202051".to_string()),
+                    bic: Some("BYLADEM1001".to_string()),
+                }),
+                remittance: vec!["Rent March".to_string(), "Flat 3".to_string()],
+                creditor_reference: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_camt_054() {
+        let statements = Camt::parse(CAMT_054.as_bytes()).unwrap();
+        let statement = &statements[0];
+        assert_eq!(statement.kind, CamtKind::Notification);
+        assert_eq!(statement.account.other_id.as_deref(), Some("12345678"));
+        assert!(statement.balances.is_empty());
+
+        let entry = &statement.entries[0];
+        assert_eq!(entry.amount, dec!(99.95));
+        assert_eq!(entry.currency, "CHF");
+        assert_eq!(entry.source_key(), Some("CH-REF-9"));
+        assert_eq!(
+            entry.booking_date,
+            Some(Effective::DateTime(
+                "2024-03-05T09:15:00Z".parse::<DateTime<Utc>>().unwrap()
+            ))
+        );
+        assert_eq!(
+            entry.tags(),
+            vec![
+                TagSelector::SelectOrCreate {
+                    tag_type: COUNTERPARTY_TAG_TYPE.to_string(),
+                    source_key: "Customer AG".to_string(),
+                    name: Some("Customer AG".to_string()),
+                    data_type: None,
+                },
+                TagSelector::SelectOrCreate {
+                    tag_type: REMITTANCE_TAG_TYPE.to_string(),
+                    source_key: "RF18539007547034".to_string(),
+                    name: Some("RF18539007547034".to_string()),
+                    data_type: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_entry_input() {
+        let statements = Camt::parse(CAMT_053.as_bytes()).unwrap();
+        let entry = &statements[0].entries[0];
+        let account = ResourceSelector::SourceKey {
+            resource_type: "BankAccount".to_string(),
+            source_key: "DE89370400440532013000".to_string(),
+        };
+        let offset = ResourceSelector::SourceKey {
+            resource_type: "Expense".to_string(),
+            source_key: "rent".to_string(),
+        };
+
+        let input = entry
+            .to_entry_input(
+                "BankTransaction".to_string(),
+                CamtDate::Value,
+                account,
+                offset,
+            )
+            .unwrap();
+        assert_eq!(input.source_key, "E-1");
+        assert_eq!(input.effective, date("2024-03-04"));
+        assert_eq!(input.lines[0].credit, dec!(1250.50));
+        assert_eq!(input.lines[1].debit, dec!(1250.50));
+        assert_eq!(
+            input.lines[0].description.as_deref(),
+            Some("Rent March Flat 3")
+        );
+        assert_eq!(input.lines[0].tags.len(), 2);
+
+        let booked = entry
+            .to_entry_input(
+                "BankTransaction".to_string(),
+                CamtDate::Booking,
+                input.lines[0].resource.clone(),
+                input.lines[1].resource.clone(),
+            )
+            .unwrap();
+        assert_eq!(booked.effective, date("2024-03-01"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Camt::parse(b"not xml").is_err());
+        assert_eq!(
+            Camt::parse(b"<Document><pain/></Document>")
+                .unwrap_err()
+                .to_string(),
+            "Document is not a camt.053 or camt.054 message"
+        );
+
+        let bad_indicator = CAMT_054.replace("<CdtDbtInd>CRDT", "<CdtDbtInd>X");
+        assert_eq!(
+            Camt::parse(bad_indicator.as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "Invalid camt credit/debit indicator Some(\"X\")"
+        );
+    }
+}
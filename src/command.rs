@@ -1,16 +1,141 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::models::RecordAction;
+
+mod attachment;
+mod backfill;
+mod cron;
+mod routing;
+mod webhook;
+
+pub use attachment::AttachmentKind;
+pub use cron::CronPeriod;
+pub use routing::{EmailAddress, EmailRouter, EmailRule};
+pub use webhook::WebhookSignature;
+
+/// The command an extract handler is invoked with.
+///
+/// The generated exports decode it with [`Command::from_command_type`], using the
+/// `command_type` of the [`HandlerInput`](crate::inputs::HandlerInput) to pick the
+/// variant. Deserializing a `Command` directly keeps the older untagged format, see
+/// [`Command::from_untagged`].
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum Command<V> {
     Cron(Cron),
     Email(Email),
     Scraper(Scraper<V>),
+    Webhook(Webhook),
+    FileUpload(FileUpload),
+    Backfill(Backfill),
     Manual(Manual<V>),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl<V> Command<V> {
+    /// The `command_type` values of the variants
+    pub const COMMAND_TYPES: [&str; 7] = [
+        "cron",
+        "email",
+        "scraper",
+        "webhook",
+        "file_upload",
+        "backfill",
+        "manual",
+    ];
+
+    /// The `command_type` the host sends for this variant
+    pub fn command_type(&self) -> &'static str {
+        match self {
+            Command::Cron(_) => "cron",
+            Command::Email(_) => "email",
+            Command::Scraper(_) => "scraper",
+            Command::Webhook(_) => "webhook",
+            Command::FileUpload(_) => "file_upload",
+            Command::Backfill(_) => "backfill",
+            Command::Manual(_) => "manual",
+        }
+    }
+}
+
+impl<V: DeserializeOwned> Command<V> {
+    /// Decodes the variant named by `command_type`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the variant if `command` is not a valid payload for it,
+    /// or if `command_type` is not one of [`Command::COMMAND_TYPES`]
+    pub fn from_tagged(command_type: &str, command: Value) -> Result<Self> {
+        fn payload<T: DeserializeOwned>(command_type: &str, command: Value) -> Result<T> {
+            serde_json::from_value(command)
+                .map_err(|e| anyhow!("Invalid {} command: {}", command_type, e))
+        }
+
+        Ok(match command_type {
+            "cron" => Command::Cron(payload(command_type, command)?),
+            "email" => Command::Email(payload(command_type, command)?),
+            "scraper" => Command::Scraper(payload(command_type, command)?),
+            "webhook" => Command::Webhook(payload(command_type, command)?),
+            "file_upload" => Command::FileUpload(payload(command_type, command)?),
+            "backfill" => Command::Backfill(payload(command_type, command)?),
+            "manual" => Command::Manual(payload(command_type, command)?),
+            _ => bail!(
+                "Unknown command type {:?}, expected one of {}",
+                command_type,
+                Self::COMMAND_TYPES.join(", ")
+            ),
+        })
+    }
+
+    /// Decodes `command` with [`Command::from_tagged`] when `command_type` is one of
+    /// [`Command::COMMAND_TYPES`], and with [`Command::from_untagged`] otherwise, as
+    /// older hosts send an empty or different `command_type`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `command` is not a valid payload for the variant, or for
+    /// any variant when falling back
+    pub fn from_command_type(command_type: &str, command: Value) -> Result<Self> {
+        if Self::COMMAND_TYPES.contains(&command_type) {
+            Self::from_tagged(command_type, command)
+        } else {
+            Self::from_untagged(command)
+        }
+    }
+
+    /// Decodes the first variant `command` is a valid payload for, trying `Cron`,
+    /// `Email`, `Scraper`, `Webhook`, `FileUpload`, `Backfill` and `Manual` in that
+    /// order. This is the format used before
+    /// `command_type` was taken into account, and is ambiguous when a `Manual` command
+    /// also looks like one of the other variants.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing why each variant did not match
+    pub fn from_untagged(command: Value) -> Result<Self> {
+        let mut errors = Vec::new();
+        for command_type in Self::COMMAND_TYPES {
+            match Self::from_tagged(command_type, command.clone()) {
+                Ok(command) => return Ok(command),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        bail!("Command did not match any variant: {}", errors.join("; "))
+    }
+}
+
+impl<'de, V: DeserializeOwned> Deserialize<'de> for Command<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let command = Value::deserialize(deserializer)?;
+        Self::from_untagged(command).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Cron {
     pub from: DateTime<Utc>,
     pub until: DateTime<Utc>,
@@ -19,16 +144,61 @@ pub struct Cron {
     pub last: bool,
 }
 
+/// A one off import of history, usually years of it when an instance is first set up.
+/// Backfills are done a window at a time: a plugin extracts what it can, reports it with
+/// [`ExtractReport::partial`](crate::response::ExtractReport::partial), and is run
+/// again with the `resume_token` it returned until the whole range is done.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Backfill {
+    pub from: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// The token from the previous partial run, if this is a resumed backfill
+    #[serde(default)]
+    pub resume_token: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Manual<C> {
     pub command: C,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Attachment {
     pub content_type: String,
     // base64 encoded
     pub data: String,
+    #[serde(default)]
+    pub filename: Option<String>,
+    // Size of the decoded data in bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub disposition: Option<Disposition>,
+    // Referenced from the HTML body as `cid:...` by inline attachments
+    #[serde(default)]
+    pub content_id: Option<String>,
+}
+
+/// The `Content-Disposition` of an attachment. Values are read ignoring case, and
+/// values other than `inline` and `attachment` are read as `Unknown` rather than
+/// failing the whole email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    Inline,
+    Attachment,
+    Unknown,
+}
+
+impl<'de> Deserialize<'de> for Disposition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let disposition = String::deserialize(deserializer)?;
+        Ok(match disposition.trim().to_ascii_lowercase().as_str() {
+            "inline" => Disposition::Inline,
+            "attachment" => Disposition::Attachment,
+            _ => Disposition::Unknown,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -45,6 +215,78 @@ pub struct Scraper<C> {
     pub items: Vec<C>,
 }
 
+/// An HTTP request pushed to the plugin's webhook endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Webhook {
+    /// The HTTP method in upper case, such as `POST` or `OPTIONS`
+    #[serde(deserialize_with = "webhook::deserialize_method")]
+    pub method: String,
+    pub path: String,
+    /// Header values by lower case name. A header sent several times keeps every value,
+    /// in the order received.
+    #[serde(default, deserialize_with = "webhook::deserialize_headers")]
+    pub headers: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub query: HashMap<String, String>,
+    // base64 encoded, exactly as received so signatures can be checked
+    #[serde(default)]
+    pub body: String,
+    // When the host received the request, used to check signature timestamps
+    pub received_at: DateTime<Utc>,
+}
+
+/// A file the user uploaded to be imported, such as a statement downloaded from their
+/// bank. The file is an [`Attachment`], so it is decoded and parsed the same way as an
+/// emailed statement.
+///
+/// ```no_run
+/// use contour_rust_pdk::command::{AttachmentKind, FileUpload};
+///
+/// # fn run(upload: FileUpload) -> anyhow::Result<()> {
+/// match upload.file.kind()? {
+///     AttachmentKind::Ofx => {
+///         for statement in upload.file.ofx()? {
+///             // Map the statement's transactions into entries
+///         }
+///     }
+///     AttachmentKind::Pdf => {
+///         let pdf = upload.file.pdf()?;
+///     }
+///     kind => anyhow::bail!("Unsupported upload {:?}", kind),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileUpload {
+    #[serde(flatten)]
+    pub file: Attachment,
+    #[serde(default)]
+    pub hints: UploadHints,
+}
+
+/// What the user told us about an uploaded file. Every hint is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UploadHints {
+    /// The account the file belongs to
+    #[serde(default)]
+    pub account: Option<String>,
+    /// First day covered by the file
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    /// Last day covered by the file
+    #[serde(default)]
+    pub until: Option<NaiveDate>,
+}
+
+impl UploadHints {
+    /// Whether `date` falls in the date range the user gave. Open ends match every
+    /// date.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.until.is_none_or(|until| date <= until)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transform<T, J, M> {
     pub records: Vec<TransformRecord<T, J, M>>,
@@ -65,3 +307,161 @@ pub struct TransformRecord<T, J, M> {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmptyJoins {}
+
+/// A change to a record in the ledger, which a listener handler is invoked with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordChange<R> {
+    pub action: RecordAction,
+    pub source_key: String,
+    pub record_type: String,
+    /// The record before the change, `None` for inserts
+    pub old: Option<R>,
+    /// The record after the change, `None` for deletes
+    pub new: Option<R>,
+    /// When the version written by the change became current. For deletes, this is
+    /// the start of the deleted version.
+    pub sys_period_start: Option<DateTime<Utc>>,
+    /// When the version stopped being current, `None` while it still is. For deletes,
+    /// this is when the record was deleted.
+    pub sys_period_end: Option<DateTime<Utc>>,
+}
+
+impl<R> RecordChange<R> {
+    /// The record as it is after the change, or as it was before a delete
+    pub fn record(&self) -> Option<&R> {
+        self.new.as_ref().or(self.old.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    struct Resync {
+        command: String,
+    }
+
+    #[test]
+    fn test_from_tagged() {
+        // Untagged, `{"command": {...}}` would be read as a manual command wrapping
+        // a manual command
+        let command = json!({ "command": "resync" });
+        let parsed = Command::<Resync>::from_tagged("manual", json!({ "command": command }));
+        match parsed.unwrap() {
+            Command::Manual(manual) => assert_eq!(manual.command.command, "resync"),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        let upload = json!({
+            "filename": "march.csv",
+            "content_type": "text/csv",
+            "data": "RGF0ZSxBbW91bnQK",
+            "hints": { "account": "Checking", "from": "2024-03-01" },
+        });
+        let Command::FileUpload(upload) =
+            Command::<Resync>::from_tagged("file_upload", upload).unwrap()
+        else {
+            panic!("Expected a file upload");
+        };
+        assert_eq!(upload.file.filename.as_deref(), Some("march.csv"));
+        assert_eq!(upload.file.bytes().unwrap(), b"Date,Amount\n");
+        assert_eq!(upload.hints.account.as_deref(), Some("Checking"));
+        assert!(
+            upload
+                .hints
+                .contains(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap())
+        );
+        assert!(
+            !upload
+                .hints
+                .contains(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap())
+        );
+
+        let cron = json!({
+            "from": "2024-01-01T00:00:00Z",
+            "until": "2024-01-02T00:00:00Z",
+            "first": true,
+        });
+        let parsed = Command::<Resync>::from_tagged("cron", cron).unwrap();
+        assert_eq!(parsed.command_type(), "cron");
+    }
+
+    #[test]
+    fn test_from_tagged_errors() {
+        let err = Command::<Resync>::from_tagged("cron", json!({ "from": "yesterday" }));
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .starts_with("Invalid cron command: ")
+        );
+
+        let err = Command::<Resync>::from_tagged("listener", json!({}));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Unknown command type \"listener\", expected one of cron, email, scraper, webhook, file_upload, backfill, manual"
+        );
+    }
+
+    #[test]
+    fn test_from_command_type() {
+        let cron = json!({
+            "from": "2024-01-01T00:00:00Z",
+            "until": "2024-01-02T00:00:00Z",
+            "first": true,
+        });
+        for command_type in ["cron", "", "listener"] {
+            let parsed = Command::<Resync>::from_command_type(command_type, cron.clone());
+            assert_eq!(parsed.unwrap().command_type(), "cron", "{:?}", command_type);
+        }
+
+        let err = Command::<Resync>::from_command_type("cron", json!({ "command": "a" }));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_untagged() {
+        let scraper: Command<Resync> =
+            serde_json::from_value(json!({ "items": [{ "command": "a" }] })).unwrap();
+        assert_eq!(scraper.command_type(), "scraper");
+
+        let err = serde_json::from_value::<Command<Resync>>(json!({ "items": 1 })).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Command did not match any variant: Invalid cron command: ")
+        );
+    }
+
+    #[test]
+    fn test_record_change() {
+        let change: RecordChange<Resync> = serde_json::from_value(json!({
+            "action": "DELETE",
+            "source_key": "txn-1",
+            "record_type": "Transaction",
+            "old": { "command": "resync" },
+            "sys_period_start": "2024-03-01T00:00:00Z",
+            "sys_period_end": "2024-03-02T00:00:00Z",
+        }))
+        .unwrap();
+        assert_eq!(change.action, RecordAction::Delete);
+        assert!(change.new.is_none());
+        assert_eq!(change.record().unwrap().command, "resync");
+        assert_eq!(
+            change.sys_period_end,
+            Some("2024-03-02T00:00:00Z".parse().unwrap())
+        );
+
+        let insert: RecordChange<Resync> = serde_json::from_value(json!({
+            "action": "INSERT",
+            "source_key": "txn-2",
+            "record_type": "Transaction",
+            "new": { "command": "resync" },
+            "sys_period_start": "2024-03-01T00:00:00Z",
+        }))
+        .unwrap();
+        assert!(insert.old.is_none());
+        assert_eq!(insert.sys_period_end, None);
+    }
+}
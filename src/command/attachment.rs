@@ -0,0 +1,558 @@
+use anyhow::{Context, Result};
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+use chrono::{Datelike, NaiveDate};
+use serde::de::DeserializeOwned;
+
+use super::{Attachment, Disposition, Email};
+use crate::{
+    camt::{Camt, CamtStatement},
+    csv::{CsvReader, CsvTable},
+    ofx::{Ofx, OfxStatement},
+    pdf::Pdf,
+    spreadsheet::Spreadsheet,
+};
+
+const PADDING_INDIFFERENT: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PADDING_INDIFFERENT);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, PADDING_INDIFFERENT);
+
+/// The real type of an attachment, detected from its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Csv,
+    Xlsx,
+    /// Legacy binary Excel workbook
+    Xls,
+    /// OpenDocument spreadsheet
+    Ods,
+    Pdf,
+    /// A ZIP archive that is not a spreadsheet
+    Zip,
+    /// An OFX or QFX statement, SGML or XML
+    Ofx,
+    /// Any other XML document, such as a camt statement
+    Xml,
+    Other,
+}
+
+impl AttachmentKind {
+    /// Detects the type of a file from its magic bytes. Text files are told apart by
+    /// their first characters, and CSV is assumed for text with a delimiter on its
+    /// first line.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(b"%PDF-") {
+            return Self::Pdf;
+        }
+        if bytes.starts_with(b"PK\x03\x04") {
+            // Entry names are stored uncompressed in the local file headers
+            return if contains(bytes, b"xl/workbook") {
+                Self::Xlsx
+            } else if contains(bytes, b"application/vnd.oasis.opendocument.spreadsheet") {
+                Self::Ods
+            } else {
+                Self::Zip
+            };
+        }
+        if bytes.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+            return Self::Xls;
+        }
+
+        let head = &bytes[..bytes.len().min(4096)];
+        if head.contains(&0) {
+            return Self::Other;
+        }
+        let text = String::from_utf8_lossy(head);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with("OFXHEADER") || contains(head, b"<?OFX") || contains(head, b"<OFX>") {
+            Self::Ofx
+        } else if text.starts_with('<') {
+            Self::Xml
+        } else if text
+            .lines()
+            .next()
+            .is_some_and(|line| line.contains([',', ';', '\t', '|']))
+        {
+            Self::Csv
+        } else {
+            Self::Other
+        }
+    }
+
+    /// The type a MIME content type claims, used when the contents are not conclusive
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "text/csv" | "text/comma-separated-values" | "application/csv" => Self::Csv,
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => Self::Xlsx,
+            "application/vnd.ms-excel" => Self::Xls,
+            "application/vnd.oasis.opendocument.spreadsheet" => Self::Ods,
+            "application/pdf" => Self::Pdf,
+            "application/zip" | "application/x-zip-compressed" => Self::Zip,
+            "application/x-ofx" | "application/vnd.intu.qfx" => Self::Ofx,
+            "application/xml" | "text/xml" => Self::Xml,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn is_spreadsheet(&self) -> bool {
+        matches!(self, Self::Xlsx | Self::Xls | Self::Ods)
+    }
+}
+
+/// Decodes base64 that may contain line breaks, lack padding or use the URL safe alphabet
+pub(super) fn decode_base64(data: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let engine = if data.contains(['-', '_']) {
+        &URL_SAFE
+    } else {
+        &STANDARD
+    };
+    engine.decode(data)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+impl Attachment {
+    /// Decodes the base64 `data`. Line breaks, missing padding and the URL safe alphabet
+    /// are accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` is not base64
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        decode_base64(&self.data).context("Attachment data is not valid base64")
+    }
+
+    /// The `filename`, falling back to the `name` parameter of the content type, as in
+    /// `text/csv; name="march.csv"`
+    pub fn filename(&self) -> Option<String> {
+        self.filename.clone().or_else(|| {
+            self.content_type.split(';').skip(1).find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("name")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+    }
+
+    /// Whether the attachment is shown inline in the body, such as a logo, rather than
+    /// attached as a file. Attachments with a content id and no or an unknown
+    /// disposition are taken as inline.
+    pub fn is_inline(&self) -> bool {
+        match self.disposition {
+            Some(Disposition::Inline) => true,
+            Some(Disposition::Attachment) => false,
+            Some(Disposition::Unknown) | None => self.content_id.is_some(),
+        }
+    }
+
+    /// Whether the filename matches a glob pattern, ignoring case. `*` matches any run
+    /// of characters and `?` matches one character.
+    pub fn matches_filename(&self, pattern: &str) -> bool {
+        self.filename().is_some_and(|name| {
+            let name: Vec<char> = name.to_lowercase().chars().collect();
+            let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+            glob_match(&pattern, &name)
+        })
+    }
+
+    /// The dates in the filename, in order. Years come first, as in
+    /// `statement_2024-03-31.csv`, `20240331.csv` or `2024_03.csv`. A year and month
+    /// without a day gives the first of the month.
+    pub fn filename_dates(&self) -> Vec<NaiveDate> {
+        self.filename()
+            .map(|name| dates_in(&name))
+            .unwrap_or_default()
+    }
+
+    /// The first date in the filename, see [`Attachment::filename_dates`]
+    pub fn filename_date(&self) -> Option<NaiveDate> {
+        self.filename_dates().into_iter().next()
+    }
+
+    /// Detects the type of the attachment from its contents, falling back to its
+    /// content type and then its file extension
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` is not base64
+    pub fn kind(&self) -> Result<AttachmentKind> {
+        Ok(self.kind_of(&self.bytes()?))
+    }
+
+    fn kind_of(&self, bytes: &[u8]) -> AttachmentKind {
+        match AttachmentKind::detect(bytes) {
+            AttachmentKind::Other => match AttachmentKind::from_content_type(&self.content_type) {
+                AttachmentKind::Other if self.has_extension("csv") => AttachmentKind::Csv,
+                kind => kind,
+            },
+            kind => kind,
+        }
+    }
+
+    /// Whether the filename ends with `.{extension}`, ignoring case
+    pub fn has_extension(&self, extension: &str) -> bool {
+        self.filename().is_some_and(|name| {
+            name.rsplit_once('.')
+                .is_some_and(|(_, ext)| ext.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Reads a CSV or spreadsheet attachment into a [`CsvTable`]. For spreadsheets the
+    /// first sheet is read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attachment cannot be decoded or parsed
+    pub fn table(&self, reader: &CsvReader) -> Result<CsvTable> {
+        let bytes = self.bytes()?;
+        if self.kind_of(&bytes).is_spreadsheet() {
+            Spreadsheet::from_bytes(&bytes)?.first_sheet()?.read(reader)
+        } else {
+            reader.read(&bytes)
+        }
+    }
+
+    /// Deserializes the rows of a CSV or spreadsheet attachment with
+    /// [`CsvTable::deserialize`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attachment cannot be decoded or parsed, or a row does not
+    /// match `T`
+    pub fn deserialize<T: DeserializeOwned>(&self, reader: &CsvReader) -> Result<Vec<T>> {
+        self.table(reader)?.deserialize()
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the attachment is not a readable spreadsheet
+    pub fn spreadsheet(&self) -> Result<Spreadsheet> {
+        Spreadsheet::from_bytes(&self.bytes()?)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the attachment is not an OFX statement
+    pub fn ofx(&self) -> Result<Vec<OfxStatement>> {
+        Ofx::parse(&self.bytes()?)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the attachment is not a camt.053 or camt.054 document
+    pub fn camt(&self) -> Result<Vec<CamtStatement>> {
+        Camt::parse(&self.bytes()?)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the attachment is not a PDF or the PDF is encrypted
+    pub fn pdf(&self) -> Result<Pdf> {
+        Pdf::parse(&self.bytes()?)
+    }
+}
+
+impl Email {
+    /// The attachments whose contents are of the given type. Attachments that are not
+    /// valid base64 cannot be read, so they are left out.
+    pub fn attachments_of_kind(&self, kind: AttachmentKind) -> Vec<&Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| attachment.kind().is_ok_and(|found| found == kind))
+            .collect()
+    }
+
+    /// The attachments whose filename ends with `.{extension}`, ignoring case
+    pub fn attachments_with_extension(&self, extension: &str) -> Vec<&Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| attachment.has_extension(extension))
+            .collect()
+    }
+
+    /// The attachments whose filename matches a glob pattern such as `statement_*.csv`,
+    /// see [`Attachment::matches_filename`]
+    pub fn attachments_matching(&self, pattern: &str) -> Vec<&Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| attachment.matches_filename(pattern))
+            .collect()
+    }
+
+    /// The attachments that are not shown inline in the body
+    pub fn file_attachments(&self) -> Vec<&Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| !attachment.is_inline())
+            .collect()
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((p, rest)) => text
+            .split_first()
+            .is_some_and(|(t, text)| (*p == '?' || p == t) && glob_match(rest, text)),
+    }
+}
+
+/// Finds `YYYY?MM?DD`, `YYYYMMDD` and `YYYY?MM` dates, where `?` is `-`, `_`, `.` or a
+/// space
+fn dates_in(s: &str) -> Vec<NaiveDate> {
+    let bytes = s.as_bytes();
+    let digits = |start: usize, len: usize| -> Option<u32> {
+        let part = bytes.get(start..start + len)?;
+        part.iter()
+            .all(u8::is_ascii_digit)
+            .then(|| std::str::from_utf8(part).ok()?.parse().ok())?
+    };
+    let separator = |idx: usize| bytes.get(idx).is_some_and(|b| b"-_. ".contains(b));
+    let digit_at = |idx: usize| bytes.get(idx).is_some_and(u8::is_ascii_digit);
+
+    let mut dates = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        // Dates must not be part of a longer number
+        if (idx > 0 && digit_at(idx - 1)) || !digit_at(idx) {
+            idx += 1;
+            continue;
+        }
+
+        let found = digits(idx, 4).and_then(|year| {
+            let date = |month, day| NaiveDate::from_ymd_opt(year as i32, month, day);
+            if separator(idx + 4) {
+                let month = digits(idx + 5, 2)?;
+                if separator(idx + 7)
+                    && let Some(day) = digits(idx + 8, 2)
+                    && !digit_at(idx + 10)
+                    && let Some(found) = date(month, day)
+                {
+                    return Some((found, 10));
+                }
+                if digit_at(idx + 7) {
+                    return None;
+                }
+                date(month, 1).map(|found| (found, 7))
+            } else if !digit_at(idx + 8) {
+                let found = date(digits(idx + 4, 2)?, digits(idx + 6, 2)?)?;
+                Some((found, 8))
+            } else {
+                None
+            }
+        });
+
+        match found {
+            Some((date, len)) if (1900..2100).contains(&date.year()) => {
+                dates.push(date);
+                idx += len;
+            }
+            _ => idx += 1,
+        }
+    }
+    dates
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::csv::RowMatch;
+
+    fn attachment(content_type: &str, bytes: &[u8]) -> Attachment {
+        Attachment {
+            content_type: content_type.to_string(),
+            data: general_purpose::STANDARD.encode(bytes),
+            ..Default::default()
+        }
+    }
+
+    fn email(attachments: Vec<Attachment>) -> Email {
+        Email {
+            from: "bank@example.com".to_string(),
+            to: vec!["me@example.com".to_string()],
+            subject: "Your statement".to_string(),
+            body: String::new(),
+            attachments,
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(AttachmentKind::detect(b"%PDF-1.7\n"), AttachmentKind::Pdf);
+        assert_eq!(
+            AttachmentKind::detect(b"PK\x03\x04\x14\x00[Content_Types].xmlxl/workbook.xml"),
+            AttachmentKind::Xlsx
+        );
+        assert_eq!(
+            AttachmentKind::detect(b"PK\x03\x04\x14\x00report.csv"),
+            AttachmentKind::Zip
+        );
+        assert_eq!(
+            AttachmentKind::detect(b"OFXHEADER:100\nDATA:OFXSGML\n<OFX>"),
+            AttachmentKind::Ofx
+        );
+        assert_eq!(
+            AttachmentKind::detect(b"\xEF\xBB\xBF<?xml version=\"1.0\"?><Document/>"),
+            AttachmentKind::Xml
+        );
+        assert_eq!(
+            AttachmentKind::detect(b"Date;Amount\n2024-01-01;1,00"),
+            AttachmentKind::Csv
+        );
+        assert_eq!(
+            AttachmentKind::detect(b"\x89PNG\r\n\x1a\n\0\0"),
+            AttachmentKind::Other
+        );
+    }
+
+    #[test]
+    fn test_bytes_and_kind() {
+        let csv = attachment("application/octet-stream; name=\"march.CSV\"", b"a\n1\n");
+        let mut wrapped = csv.clone();
+        wrapped.data = "YQox\r\nCg".to_string();
+        assert_eq!(wrapped.bytes().unwrap(), b"a\n1\n");
+
+        // A single column has no delimiter, so the extension decides
+        assert_eq!(csv.filename().as_deref(), Some("march.CSV"));
+        assert_eq!(csv.kind().unwrap(), AttachmentKind::Csv);
+        assert_eq!(
+            attachment("application/pdf", b"unknown").kind().unwrap(),
+            AttachmentKind::Pdf
+        );
+
+        let invalid = Attachment {
+            content_type: "text/csv".to_string(),
+            data: "not base64!".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid.bytes().unwrap_err().to_string(),
+            "Attachment data is not valid base64"
+        );
+    }
+
+    #[test]
+    fn test_filter_and_deserialize() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Row {
+            date: String,
+            amount: f64,
+        }
+
+        let email = email(vec![
+            attachment("image/png; name=logo.png", b"\x89PNG\r\n\x1a\n\0\0"),
+            attachment(
+                "text/csv; name=\"statement.csv\"",
+                b"Statement,March\ndate,amount\n2024-03-01,-3.5\n",
+            ),
+        ]);
+
+        let csvs = email.attachments_of_kind(AttachmentKind::Csv);
+        assert_eq!(csvs.len(), 1);
+        assert_eq!(email.attachments_with_extension("png").len(), 1);
+
+        let reader = CsvReader::new().header(RowMatch::columns(["date", "amount"]));
+        let rows: Vec<Row> = csvs[0].deserialize(&reader).unwrap();
+        assert_eq!(
+            rows,
+            vec![Row {
+                date: "2024-03-01".to_string(),
+                amount: -3.5
+            }]
+        );
+    }
+
+    #[test]
+    fn test_metadata_deserializes_with_defaults() {
+        let old: Attachment =
+            serde_json::from_str(r#"{"content_type": "text/csv", "data": "YQ=="}"#).unwrap();
+        assert_eq!(old.filename, None);
+        assert_eq!(old.disposition, None);
+        assert!(!old.is_inline());
+
+        let logo: Attachment = serde_json::from_str(
+            r#"{
+                "content_type": "image/png",
+                "data": "",
+                "filename": "logo.png",
+                "size": 0,
+                "disposition": "inline",
+                "content_id": "logo@example.com"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(logo.disposition, Some(Disposition::Inline));
+        assert!(logo.is_inline());
+
+        let email = email(vec![old, logo]);
+        assert_eq!(email.file_attachments().len(), 1);
+
+        let disposition = |value: &str| {
+            serde_json::from_value::<Attachment>(serde_json::json!({
+                "content_type": "text/csv",
+                "data": "",
+                "disposition": value,
+            }))
+            .unwrap()
+            .disposition
+        };
+        assert_eq!(disposition("Attachment"), Some(Disposition::Attachment));
+        assert_eq!(disposition(" INLINE"), Some(Disposition::Inline));
+        assert_eq!(disposition("form-data"), Some(Disposition::Unknown));
+    }
+
+    #[test]
+    fn test_filename_glob_and_dates() {
+        let named = |filename: &str| Attachment {
+            filename: Some(filename.to_string()),
+            ..Default::default()
+        };
+        let email = email(vec![
+            named("Statement_2024_03.CSV"),
+            named("statement_20240415.csv"),
+            named("logo.png"),
+        ]);
+
+        let statements = email.attachments_matching("statement_*.csv");
+        assert_eq!(statements.len(), 2);
+        assert!(named("a1.csv").matches_filename("a?.csv"));
+        assert!(!named("a12.csv").matches_filename("a?.csv"));
+
+        assert_eq!(
+            statements[0].filename_date(),
+            NaiveDate::from_ymd_opt(2024, 3, 1)
+        );
+        assert_eq!(
+            statements[1].filename_date(),
+            NaiveDate::from_ymd_opt(2024, 4, 15)
+        );
+        assert_eq!(
+            named("export 2024-01-01 to 2024.01.31 v2.csv").filename_dates(),
+            vec![
+                NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            ]
+        );
+        assert!(named("invoice_123456789.pdf").filename_dates().is_empty());
+        assert!(email.attachments[2].filename_dates().is_empty());
+    }
+}
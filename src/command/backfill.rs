@@ -0,0 +1,160 @@
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::{Backfill, Cron};
+use crate::response::ExtractReport;
+
+impl Backfill {
+    /// Where this run picks up: `from`, or where the previous run stopped when resuming
+    /// a backfill reported with [`Backfill::respond`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `from` is after `until`, or if the resume token was not
+    /// written by [`Backfill::respond`]
+    pub fn resume_from(&self) -> Result<DateTime<Utc>> {
+        if self.from > self.until {
+            bail!(
+                "Backfill starts at {} after it ends at {}",
+                self.from,
+                self.until
+            );
+        }
+        match &self.resume_token {
+            Some(token) => DateTime::parse_from_rfc3339(token)
+                .map(|resume| resume.to_utc().clamp(self.from, self.until))
+                .map_err(|_| anyhow!("Invalid backfill resume token {:?}", token)),
+            None => Ok(self.from),
+        }
+    }
+
+    /// The window to extract in this run, at most `max_span` long. The window is
+    /// `first` when the backfill is not being resumed, and `last` when it reaches the end
+    /// of the backfill, so it can be handled the same way as a scheduled run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range or the resume token is invalid
+    pub fn next_window(&self, max_span: TimeDelta) -> Result<Cron> {
+        let from = self.resume_from()?;
+        let until = if max_span > TimeDelta::zero() {
+            from.checked_add_signed(max_span)
+                .map_or(self.until, |until| until.min(self.until))
+        } else {
+            self.until
+        };
+        Ok(Cron {
+            from,
+            until,
+            first: self.resume_token.is_none(),
+            last: until >= self.until,
+        })
+    }
+
+    /// Reports `window` as extracted. Unless it reaches the end of the backfill, the
+    /// response is partial so the host runs the backfill again from the end of the
+    /// window.
+    pub fn respond(&self, window: &Cron) -> ExtractReport {
+        if window.until < self.until {
+            ExtractReport::partial(window.from, window.until, window.until.to_rfc3339())
+        } else {
+            ExtractReport::new(window.from, window.until)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backfill() -> Backfill {
+        Backfill {
+            from: "2022-01-01T00:00:00Z".parse().unwrap(),
+            until: "2024-01-01T00:00:00Z".parse().unwrap(),
+            resume_token: None,
+        }
+    }
+
+    #[test]
+    fn test_resumes_until_done() {
+        let mut command = backfill();
+        let mut windows = Vec::new();
+        loop {
+            let window = command.next_window(TimeDelta::days(365)).unwrap();
+            let response = command.respond(&window);
+            windows.push(window);
+            match response.resume_token {
+                Some(token) => command.resume_token = Some(token),
+                None => break,
+            }
+        }
+
+        let ranges: Vec<_> = windows
+            .iter()
+            .map(|w| {
+                (
+                    w.from.date_naive().to_string(),
+                    w.until.date_naive().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                ("2022-01-01".to_string(), "2023-01-01".to_string()),
+                ("2023-01-01".to_string(), "2024-01-01".to_string()),
+            ]
+        );
+        assert!(windows[0].first && !windows[0].last);
+        assert!(!windows[1].first && windows[1].last);
+    }
+
+    #[test]
+    fn test_partial_response() {
+        let command = backfill();
+        let window = command.next_window(TimeDelta::days(90)).unwrap();
+        let response = command.respond(&window);
+        assert!(response.is_partial());
+        assert_eq!(response.start_date, command.from);
+        assert_eq!(response.end_date, window.until);
+
+        let json = serde_json::to_value(ExtractReport::new(command.from, command.until)).unwrap();
+        assert!(json.get("resume_token").is_none());
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        let command = Backfill {
+            resume_token: Some("page-2".to_string()),
+            ..backfill()
+        };
+        assert_eq!(
+            command.resume_from().unwrap_err().to_string(),
+            "Invalid backfill resume token \"page-2\""
+        );
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        let command = Backfill {
+            from: "2024-01-01T00:00:00Z".parse().unwrap(),
+            until: "2022-01-01T00:00:00Z".parse().unwrap(),
+            resume_token: Some("2023-01-01T00:00:00Z".to_string()),
+        };
+        assert_eq!(
+            command
+                .next_window(TimeDelta::days(1))
+                .unwrap_err()
+                .to_string(),
+            "Backfill starts at 2024-01-01 00:00:00 UTC after it ends at 2022-01-01 00:00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn test_unbounded_span() {
+        let command = backfill();
+        let window = command.next_window(TimeDelta::MAX).unwrap();
+        assert_eq!((window.from, window.until), (command.from, command.until));
+        assert!(window.last);
+    }
+}
@@ -0,0 +1,286 @@
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::Cron;
+
+/// Calendar periods a [`Cron`] can be split along
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CronPeriod {
+    Day,
+    Month,
+    /// Quarters of a fiscal year starting on the first of `first_month` (1 to 12)
+    FiscalQuarter {
+        first_month: u32,
+    },
+    /// Fiscal years starting on the first of `first_month` (1 to 12)
+    FiscalYear {
+        first_month: u32,
+    },
+}
+
+impl Cron {
+    /// Moves `from` back by `overlap`, so data that arrived late for the previous run
+    /// is fetched again. The first run has no previous run and is left as is.
+    ///
+    /// `from` stops at the earliest representable time, and never moves past `until`
+    /// when `overlap` is negative.
+    pub fn with_overlap(&self, overlap: TimeDelta) -> Cron {
+        let mut cron = self.clone();
+        if !self.first {
+            let from =
+                self.from
+                    .checked_sub_signed(overlap)
+                    .unwrap_or(if overlap > TimeDelta::zero() {
+                        DateTime::<Utc>::MIN_UTC
+                    } else {
+                        self.until
+                    });
+            cron.from = from.min(self.until);
+        }
+        cron
+    }
+
+    /// Splits the run into consecutive windows no longer than `max_span`, for APIs
+    /// that limit how much can be queried at once.
+    ///
+    /// Each window is a `Cron` of its own: only the first window keeps `first` and only
+    /// the last keeps `last`. An empty run, or a `max_span` that is not positive, gives
+    /// the run back as a single window.
+    pub fn windows(&self, max_span: TimeDelta) -> Vec<Cron> {
+        if max_span <= TimeDelta::zero() {
+            return vec![self.clone()];
+        }
+        self.split(|start| start.checked_add_signed(max_span).unwrap_or(self.until))
+    }
+
+    /// Splits the run at the start of every `period` in `tz`, so each window covers at
+    /// most one day, month or fiscal period. The first and last windows are partial when
+    /// the run does not start or end on a boundary. Flags are kept as for
+    /// [`Cron::windows`].
+    pub fn calendar_windows(&self, period: CronPeriod, tz: Tz) -> Vec<Cron> {
+        self.split(|start| {
+            let date = start.with_timezone(&tz).date_naive();
+            let next = match period {
+                CronPeriod::Day => date + Days::new(1),
+                CronPeriod::Month => next_month_start(date, 1, 1),
+                CronPeriod::FiscalQuarter { first_month } => next_month_start(date, 3, first_month),
+                CronPeriod::FiscalYear { first_month } => next_month_start(date, 12, first_month),
+            };
+            start_of_day(next, tz)
+        })
+    }
+
+    /// The run's range as query parameters, with `from` and `until` formatted using a
+    /// chrono format string such as `"%Y-%m-%d"`
+    pub fn parameters(
+        &self,
+        from_key: &str,
+        until_key: &str,
+        format: &str,
+    ) -> Vec<(String, String)> {
+        vec![
+            (from_key.to_string(), self.from.format(format).to_string()),
+            (until_key.to_string(), self.until.format(format).to_string()),
+        ]
+    }
+
+    fn split(&self, next: impl Fn(DateTime<Utc>) -> DateTime<Utc>) -> Vec<Cron> {
+        let mut windows = Vec::new();
+        let mut start = self.from;
+        while start < self.until {
+            // Never stalls, even if a boundary cannot be computed
+            let end = next(start)
+                .max(start + TimeDelta::seconds(1))
+                .min(self.until);
+            windows.push(Cron {
+                from: start,
+                until: end,
+                first: self.first && windows.is_empty(),
+                last: false,
+            });
+            start = end;
+        }
+
+        match windows.last_mut() {
+            Some(window) => window.last = self.last,
+            None => windows.push(self.clone()),
+        }
+        windows
+    }
+}
+
+/// The first day of the first period after the one containing `date`, for periods of
+/// `months` months starting in `first_month`
+fn next_month_start(date: NaiveDate, months: u32, first_month: u32) -> NaiveDate {
+    let first_month = first_month.clamp(1, 12);
+    let mut next = date.with_day(1).unwrap_or(date) + Months::new(1);
+    while !(next.month() + 12 - first_month).is_multiple_of(months) {
+        next = next + Months::new(1);
+    }
+    next
+}
+
+fn start_of_day(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    // Where a DST change skips midnight, the day starts an hour later
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(midnight + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map_or_else(
+            || Utc.from_utc_datetime(&midnight),
+            |start| start.with_timezone(&Utc),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cron(from: &str, until: &str, first: bool, last: bool) -> Cron {
+        Cron {
+            from: from.parse().unwrap(),
+            until: until.parse().unwrap(),
+            first,
+            last,
+        }
+    }
+
+    fn ranges(windows: &[Cron]) -> Vec<(String, String)> {
+        windows
+            .iter()
+            .map(|window| (window.from.to_rfc3339(), window.until.to_rfc3339()))
+            .collect()
+    }
+
+    #[test]
+    fn test_windows() {
+        let run = cron("2024-01-01T00:00:00Z", "2024-03-11T00:00:00Z", true, true);
+        let windows = run.windows(TimeDelta::days(31));
+        assert_eq!(
+            ranges(&windows),
+            vec![
+                (
+                    "2024-01-01T00:00:00+00:00".into(),
+                    "2024-02-01T00:00:00+00:00".into()
+                ),
+                (
+                    "2024-02-01T00:00:00+00:00".into(),
+                    "2024-03-03T00:00:00+00:00".into()
+                ),
+                (
+                    "2024-03-03T00:00:00+00:00".into(),
+                    "2024-03-11T00:00:00+00:00".into()
+                ),
+            ]
+        );
+        let flags: Vec<_> = windows.iter().map(|w| (w.first, w.last)).collect();
+        assert_eq!(flags, vec![(true, false), (false, false), (false, true)]);
+
+        let empty = cron("2024-01-01T00:00:00Z", "2024-01-01T00:00:00Z", false, true);
+        assert_eq!(empty.windows(TimeDelta::days(1)).len(), 1);
+
+        let windows = run.windows(TimeDelta::MAX);
+        assert_eq!(ranges(&windows), ranges(&[run]));
+    }
+
+    #[test]
+    fn test_calendar_windows() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let run = cron("2024-02-20T12:00:00Z", "2024-04-10T00:00:00Z", false, false);
+        assert_eq!(
+            ranges(&run.calendar_windows(CronPeriod::Month, berlin)),
+            vec![
+                (
+                    "2024-02-20T12:00:00+00:00".into(),
+                    "2024-02-29T23:00:00+00:00".into()
+                ),
+                (
+                    "2024-02-29T23:00:00+00:00".into(),
+                    "2024-03-31T22:00:00+00:00".into()
+                ),
+                (
+                    "2024-03-31T22:00:00+00:00".into(),
+                    "2024-04-10T00:00:00+00:00".into()
+                ),
+            ]
+        );
+
+        let days = cron("2024-03-30T10:00:00Z", "2024-04-01T00:00:00Z", false, false)
+            .calendar_windows(CronPeriod::Day, berlin);
+        assert_eq!(
+            days[0].until,
+            berlin.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            days[1].until,
+            berlin.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(days.len(), 3);
+    }
+
+    #[test]
+    fn test_fiscal_windows() {
+        let run = cron("2023-05-15T00:00:00Z", "2024-08-01T00:00:00Z", false, false);
+        let quarters = run.calendar_windows(CronPeriod::FiscalQuarter { first_month: 4 }, Tz::UTC);
+        let starts: Vec<_> = quarters
+            .iter()
+            .map(|w| w.from.date_naive().to_string())
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                "2023-05-15",
+                "2023-07-01",
+                "2023-10-01",
+                "2024-01-01",
+                "2024-04-01",
+                "2024-07-01"
+            ]
+        );
+
+        let years = run.calendar_windows(CronPeriod::FiscalYear { first_month: 4 }, Tz::UTC);
+        let starts: Vec<_> = years
+            .iter()
+            .map(|w| w.from.date_naive().to_string())
+            .collect();
+        assert_eq!(starts, vec!["2023-05-15", "2024-04-01"]);
+    }
+
+    #[test]
+    fn test_overlap_and_parameters() {
+        let run = cron("2024-03-01T00:00:00Z", "2024-03-02T00:00:00Z", false, false);
+        let overlapped = run.with_overlap(TimeDelta::days(2));
+        assert_eq!(
+            overlapped.from,
+            Utc.with_ymd_and_hms(2024, 2, 28, 0, 0, 0).unwrap()
+        );
+
+        let first = Cron {
+            first: true,
+            ..run.clone()
+        };
+        assert_eq!(first.with_overlap(TimeDelta::days(2)).from, run.from);
+
+        assert_eq!(
+            run.with_overlap(TimeDelta::MAX).from,
+            DateTime::<Utc>::MIN_UTC
+        );
+        assert_eq!(
+            run.with_overlap(TimeDelta::hours(-6)).from,
+            run.from + TimeDelta::hours(6)
+        );
+        assert_eq!(run.with_overlap(TimeDelta::days(-2)).from, run.until);
+        assert_eq!(run.with_overlap(TimeDelta::MIN).from, run.until);
+
+        assert_eq!(
+            overlapped.parameters("start", "end", "%Y-%m-%d"),
+            vec![
+                ("start".to_string(), "2024-02-28".to_string()),
+                ("end".to_string(), "2024-03-02".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{Context, Result, anyhow, bail};
+use regex::Regex;
+
+use super::{AttachmentKind, Email};
+
+/// A parsed email address such as `"Jane Doe" <jane+receipts@Example.com>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAddress {
+    pub display_name: Option<String>,
+    /// The part before the `@`, without any plus tag
+    pub local: String,
+    /// The plus addressing tag, `receipts` in `jane+receipts@example.com`
+    pub tag: Option<String>,
+    /// Lowercased domain
+    pub domain: String,
+}
+
+impl EmailAddress {
+    /// Parses a single address, with or without a display name. Returns `None` if there
+    /// is no `local@domain` part.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (display_name, address) = match (s.rfind('<'), s.rfind('>')) {
+            (Some(open), Some(close)) if open < close => {
+                let name = s[..open].trim().trim_matches('"').trim();
+                (
+                    Some(name.to_string()).filter(|name| !name.is_empty()),
+                    &s[open + 1..close],
+                )
+            }
+            // `jane@example.com (Jane Doe)`
+            _ => match s.split_once(' ') {
+                Some((address, comment)) => (
+                    Some(comment.trim().trim_matches(['(', ')']).to_string())
+                        .filter(|name| !name.is_empty()),
+                    address,
+                ),
+                None => (None, s),
+            },
+        };
+
+        let (local, domain) = address.trim().rsplit_once('@')?;
+        if local.is_empty() || domain.is_empty() || domain.contains(char::is_whitespace) {
+            return None;
+        }
+        let (local, tag) = match local.split_once('+') {
+            Some((local, tag)) => (local, Some(tag.to_string())),
+            None => (local, None),
+        };
+
+        Some(Self {
+            display_name,
+            local: local.to_string(),
+            tag,
+            domain: domain.to_ascii_lowercase(),
+        })
+    }
+
+    /// Parses a comma separated list of addresses, ignoring commas inside quoted display
+    /// names and skipping entries that are not addresses
+    pub fn parse_list(s: &str) -> Vec<Self> {
+        let mut addresses = Vec::new();
+        let mut quoted = false;
+        let mut start = 0;
+        for (idx, c) in s.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    addresses.extend(Self::parse(&s[start..idx]));
+                    start = idx + 1;
+                }
+                _ => {}
+            }
+        }
+        addresses.extend(Self::parse(&s[start..]));
+        addresses
+    }
+
+    /// The address without display name or plus tag, as in `jane@example.com`
+    pub fn mailbox(&self) -> String {
+        format!("{}@{}", self.local.to_ascii_lowercase(), self.domain)
+    }
+
+    /// Whether the address is at `domain` or one of its subdomains, ignoring case
+    pub fn is_in_domain(&self, domain: &str) -> bool {
+        let domain = domain.trim_start_matches('@').to_ascii_lowercase();
+        self.domain == domain
+            || self
+                .domain
+                .strip_suffix(&domain)
+                .is_some_and(|sub| sub.ends_with('.'))
+    }
+}
+
+impl Email {
+    /// The parsed `from` address.
+    ///
+    /// This is the `From:` header as written by the sender, which anyone can set to any
+    /// address. It is not checked against SPF or DKIM, so it identifies who the email
+    /// claims to be from, not who sent it.
+    pub fn sender(&self) -> Option<EmailAddress> {
+        EmailAddress::parse(&self.from)
+    }
+
+    /// The parsed `to` addresses
+    pub fn recipients(&self) -> Vec<EmailAddress> {
+        self.to
+            .iter()
+            .flat_map(|to| EmailAddress::parse_list(to))
+            .collect()
+    }
+}
+
+/// Conditions an email must meet to be handled by a route. Every condition that is set
+/// must match. Within a condition, matching any one of its values is enough.
+#[derive(Debug, Clone, Default)]
+pub struct EmailRule {
+    sender_domains: Vec<String>,
+    senders: Vec<String>,
+    mailboxes: Vec<String>,
+    tags: Vec<String>,
+    subject: Option<Regex>,
+    attachment: Option<AttachmentKind>,
+}
+
+impl EmailRule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches senders at `domain` or one of its subdomains. The sender is the
+    /// unauthenticated `From:` address, see [`Email::sender`], so this sorts emails
+    /// rather than deciding which ones to trust.
+    pub fn from_domain(mut self, domain: &str) -> Self {
+        self.sender_domains.push(domain.to_string());
+        self
+    }
+
+    /// Matches a sender mailbox such as `statements@bank.com`, ignoring plus tags and
+    /// case
+    pub fn from_address(mut self, address: &str) -> Self {
+        self.senders.push(mailbox(address));
+        self
+    }
+
+    /// Matches emails sent to a mailbox such as `imports@example.com`, ignoring plus tags
+    /// and case
+    pub fn to_address(mut self, address: &str) -> Self {
+        self.mailboxes.push(mailbox(address));
+        self
+    }
+
+    /// Matches emails sent to a plus tagged address, such as `receipts` for
+    /// `imports+receipts@example.com`
+    pub fn to_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_ascii_lowercase());
+        self
+    }
+
+    /// Matches subjects against a regex. Use `(?i)` to ignore case.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex
+    pub fn subject(mut self, pattern: &str) -> Result<Self> {
+        self.subject =
+            Some(Regex::new(pattern).with_context(|| format!("Invalid regex {}", pattern))?);
+        Ok(self)
+    }
+
+    /// Matches emails with at least one attachment of the given type
+    pub fn attachment(mut self, kind: AttachmentKind) -> Self {
+        self.attachment = Some(kind);
+        self
+    }
+
+    /// Whether the email meets every condition of the rule. Attachments that are not
+    /// valid base64 never match an attachment type.
+    pub fn matches(&self, email: &Email) -> bool {
+        let sender = email.sender();
+        let recipients = email.recipients();
+
+        let sender_matches = (self.sender_domains.is_empty() && self.senders.is_empty())
+            || sender.as_ref().is_some_and(|sender| {
+                self.sender_domains
+                    .iter()
+                    .any(|domain| sender.is_in_domain(domain))
+                    || self.senders.contains(&sender.mailbox())
+            });
+        let mailbox_matches = self.mailboxes.is_empty()
+            || recipients
+                .iter()
+                .any(|recipient| self.mailboxes.contains(&recipient.mailbox()));
+        let tag_matches = self.tags.is_empty()
+            || recipients.iter().any(|recipient| {
+                recipient
+                    .tag
+                    .as_ref()
+                    .is_some_and(|tag| self.tags.contains(&tag.to_ascii_lowercase()))
+            });
+        let subject_matches = self
+            .subject
+            .as_ref()
+            .is_none_or(|subject| subject.is_match(&email.subject));
+
+        sender_matches
+            && mailbox_matches
+            && tag_matches
+            && subject_matches
+            && self
+                .attachment
+                .is_none_or(|kind| !email.attachments_of_kind(kind).is_empty())
+    }
+}
+
+fn mailbox(address: &str) -> String {
+    EmailAddress::parse(address)
+        .map(|address| address.mailbox())
+        .unwrap_or_else(|| address.to_ascii_lowercase())
+}
+
+type Handler<T> = Box<dyn Fn(&Email) -> Result<T>>;
+
+/// Sends an email to the handler of the first rule it matches, so one plugin can serve
+/// several senders and mailboxes.
+///
+/// ```
+/// use anyhow::Result;
+/// use contour_rust_pdk::command::{AttachmentKind, Email, EmailRouter, EmailRule};
+///
+/// fn statement(email: &Email) -> Result<usize> {
+///     Ok(email.attachments.len())
+/// }
+///
+/// fn receipt(_email: &Email) -> Result<usize> {
+///     Ok(0)
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let router = EmailRouter::new()
+///     .allow_domain("bank.com")
+///     .allow_domain("shop.com")
+///     .route(
+///         EmailRule::new()
+///             .from_domain("bank.com")
+///             .subject("(?i)statement")?
+///             .attachment(AttachmentKind::Csv),
+///         statement,
+///     )
+///     .route(EmailRule::new().to_tag("receipts"), receipt);
+/// # Ok(())
+/// # }
+/// ```
+pub struct EmailRouter<T> {
+    allowed_domains: Vec<String>,
+    routes: Vec<(EmailRule, Handler<T>)>,
+    fallback: Option<Handler<T>>,
+}
+
+impl<T> Default for EmailRouter<T> {
+    fn default() -> Self {
+        Self {
+            allowed_domains: Vec::new(),
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+}
+
+impl<T> EmailRouter<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accepts emails from senders at `domain` or its subdomains. Emails from other
+    /// senders are rejected before any rule is checked. All senders are accepted when no
+    /// domain is allowed.
+    ///
+    /// This only filters on the `From:` address, which is not authenticated, see
+    /// [`Email::sender`]. It keeps out stray emails, not forged ones, so data from an
+    /// email still needs to be checked like any other untrusted input.
+    pub fn allow_domain(mut self, domain: &str) -> Self {
+        self.allowed_domains.push(domain.to_string());
+        self
+    }
+
+    /// Adds a route. Routes are tried in the order they were added.
+    pub fn route(
+        mut self,
+        rule: EmailRule,
+        handler: impl Fn(&Email) -> Result<T> + 'static,
+    ) -> Self {
+        self.routes.push((rule, Box::new(handler)));
+        self
+    }
+
+    /// Handles emails that match no route, instead of returning an error
+    pub fn fallback(mut self, handler: impl Fn(&Email) -> Result<T> + 'static) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Checks the sender and runs the handler of the first matching route
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sender is not allowed, no route matches and there is no
+    /// fallback, or the handler fails
+    pub fn dispatch(&self, email: &Email) -> Result<T> {
+        if !self.allowed_domains.is_empty() {
+            let sender = email
+                .sender()
+                .ok_or_else(|| anyhow!("Email sender {:?} is not an address", email.from))?;
+            if !self
+                .allowed_domains
+                .iter()
+                .any(|domain| sender.is_in_domain(domain))
+            {
+                bail!("Email sender {} is not allowed", sender.mailbox());
+            }
+        }
+
+        for (rule, handler) in &self.routes {
+            if rule.matches(email) {
+                return handler(email);
+            }
+        }
+        match &self.fallback {
+            Some(handler) => handler(email),
+            None => bail!(
+                "No route matches email from {} with subject {:?}",
+                email.from,
+                email.subject
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose};
+
+    use super::*;
+    use crate::command::Attachment;
+
+    fn email(from: &str, to: &[&str], subject: &str) -> Email {
+        Email {
+            from: from.to_string(),
+            to: to.iter().map(|to| to.to_string()).collect(),
+            subject: subject.to_string(),
+            body: String::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    fn router() -> EmailRouter<&'static str> {
+        EmailRouter::new()
+            .allow_domain("bank.com")
+            .allow_domain("shop.com")
+            .route(
+                EmailRule::new()
+                    .from_domain("bank.com")
+                    .subject("(?i)statement")
+                    .unwrap()
+                    .attachment(AttachmentKind::Csv),
+                |_| Ok("statement"),
+            )
+            .route(EmailRule::new().to_tag("receipts"), |_| Ok("receipt"))
+            .route(EmailRule::new().to_address("Imports@Example.com"), |_| {
+                Ok("import")
+            })
+    }
+
+    #[test]
+    fn test_parse_addresses() {
+        assert_eq!(
+            EmailAddress::parse("\"Doe, Jane\" <Jane+Receipts@Example.COM>"),
+            Some(EmailAddress {
+                display_name: Some("Doe, Jane".to_string()),
+                local: "Jane".to_string(),
+                tag: Some("Receipts".to_string()),
+                domain: "example.com".to_string(),
+            })
+        );
+        let bare = EmailAddress::parse("alerts@mail.bank.com (Bank Alerts)").unwrap();
+        assert_eq!(bare.display_name.as_deref(), Some("Bank Alerts"));
+        assert_eq!(bare.mailbox(), "alerts@mail.bank.com");
+        assert!(bare.is_in_domain("bank.com"));
+        assert!(!bare.is_in_domain("ank.com"));
+        assert_eq!(EmailAddress::parse("Undisclosed recipients"), None);
+
+        let email = email(
+            "Bank <noreply@bank.com>",
+            &["\"Doe, Jane\" <jane@example.com>, imports+receipts@example.com"],
+            "",
+        );
+        assert_eq!(
+            email
+                .recipients()
+                .iter()
+                .map(EmailAddress::mailbox)
+                .collect::<Vec<_>>(),
+            vec!["jane@example.com", "imports@example.com"]
+        );
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let router = router();
+
+        let mut statement = email(
+            "Bank <noreply@bank.com>",
+            &["me@example.com"],
+            "Your March Statement",
+        );
+        assert_eq!(
+            router.dispatch(&statement).unwrap_err().to_string(),
+            "No route matches email from Bank <noreply@bank.com> with subject \"Your March Statement\""
+        );
+        statement.attachments.push(Attachment {
+            content_type: "text/csv".to_string(),
+            data: "not base64!".to_string(),
+            ..Default::default()
+        });
+        let router = router.fallback(|_| Ok("fallback"));
+        assert_eq!(router.dispatch(&statement).unwrap(), "fallback");
+        statement.attachments.push(Attachment {
+            content_type: "text/csv".to_string(),
+            data: general_purpose::STANDARD.encode("date,amount\n"),
+            ..Default::default()
+        });
+        assert_eq!(router.dispatch(&statement).unwrap(), "statement");
+
+        let receipt = email("orders@shop.com", &["me+Receipts@example.com"], "Order 1");
+        assert_eq!(router.dispatch(&receipt).unwrap(), "receipt");
+
+        let import = email("orders@shop.com", &["imports+x@example.com"], "Order 2");
+        assert_eq!(router.dispatch(&import).unwrap(), "import");
+
+        let spoofed = email("noreply@bank.com.evil.io", &["me@example.com"], "Statement");
+        assert_eq!(
+            router.dispatch(&spoofed).unwrap_err().to_string(),
+            "Email sender noreply@bank.com.evil.io is not allowed"
+        );
+
+        let other = email("orders@shop.com", &["me@example.com"], "Hello");
+        assert_eq!(router.dispatch(&other).unwrap(), "fallback");
+    }
+}
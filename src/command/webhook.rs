@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, TimeDelta};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use sha2::Sha256;
+
+use super::{Webhook, attachment::decode_base64};
+use crate::config;
+
+/// How a webhook sender signs its requests. All schemes are an HMAC-SHA256 of the raw
+/// body, in hex, keyed with a shared secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookSignature {
+    /// `Stripe-Signature: t=<unix time>,v1=<signature>`, signing `<t>.<body>`. The
+    /// timestamp must be within `tolerance` of when the request was received, which
+    /// stops old requests from being replayed.
+    Stripe { tolerance: TimeDelta },
+    /// `X-Hub-Signature-256: sha256=<signature>`
+    GitHub,
+    /// The signature in `header`, after `prefix`
+    HmacSha256 { header: String, prefix: String },
+}
+
+impl WebhookSignature {
+    /// Stripe's scheme with its default tolerance of five minutes
+    pub fn stripe() -> Self {
+        Self::Stripe {
+            tolerance: TimeDelta::minutes(5),
+        }
+    }
+}
+
+impl Webhook {
+    /// Looks up the first value of a header, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_values(name).first().map(String::as_str)
+    }
+
+    /// Every value of a header sent several times, ignoring case
+    pub fn header_values(&self, name: &str) -> &[String] {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Decodes the base64 `body`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `body` is not base64
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        decode_base64(&self.body).context("Webhook body is not valid base64")
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the body is not UTF-8 text
+    pub fn text(&self) -> Result<String> {
+        String::from_utf8(self.bytes()?).context("Webhook body is not UTF-8")
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the body is not JSON matching `T`
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_slice(&self.bytes()?).context("Webhook body is not valid JSON")
+    }
+
+    /// Checks the request was signed with `secret`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature header is missing or malformed, the signature
+    /// does not match, or a signed timestamp is outside the tolerance
+    pub fn verify(&self, scheme: &WebhookSignature, secret: &str) -> Result<()> {
+        let body = self.bytes()?;
+        match scheme {
+            WebhookSignature::Stripe { tolerance } => {
+                let header = self.signature_header("Stripe-Signature")?;
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+                for part in header.split(',') {
+                    match part.trim().split_once('=') {
+                        Some(("t", value)) => timestamp = Some(value),
+                        Some(("v1", value)) => signatures.push(value),
+                        _ => {}
+                    }
+                }
+
+                let timestamp =
+                    timestamp.ok_or_else(|| anyhow!("Stripe-Signature header has no timestamp"))?;
+                let signed_at = timestamp
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+                    .ok_or_else(|| anyhow!("Invalid webhook timestamp {:?}", timestamp))?;
+                if (self.received_at - signed_at).abs() > *tolerance {
+                    bail!(
+                        "Webhook timestamp {} is more than {}s from when it was received",
+                        signed_at,
+                        tolerance.num_seconds()
+                    );
+                }
+
+                let mut payload = format!("{}.", timestamp).into_bytes();
+                payload.extend(body);
+                if !signatures
+                    .iter()
+                    .any(|signature| signature_matches(secret, &payload, signature))
+                {
+                    bail!("Webhook signature does not match");
+                }
+            }
+            WebhookSignature::GitHub => {
+                self.verify_header(&body, secret, "X-Hub-Signature-256", "sha256=")?
+            }
+            WebhookSignature::HmacSha256 { header, prefix } => {
+                self.verify_header(&body, secret, header, prefix)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the request was signed with the secret stored under `key` in the
+    /// plugin's config, see [`Webhook::verify`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secret cannot be read or the request is not signed with it
+    pub fn verify_with_config(&self, scheme: &WebhookSignature, key: &str) -> Result<()> {
+        let secret = config(key)?;
+        self.verify(scheme, &secret)
+    }
+
+    fn signature_header(&self, name: &str) -> Result<&str> {
+        self.header(name)
+            .ok_or_else(|| anyhow!("Webhook is missing the {} header", name))
+    }
+
+    fn verify_header(&self, body: &[u8], secret: &str, header: &str, prefix: &str) -> Result<()> {
+        let value = self.signature_header(header)?;
+        let signature = value
+            .trim()
+            .strip_prefix(prefix)
+            .ok_or_else(|| anyhow!("{} header does not start with {:?}", header, prefix))?;
+        if !signature_matches(secret, body, signature) {
+            bail!("Webhook signature does not match");
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn deserialize_method<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    Ok(String::deserialize(deserializer)?.to_ascii_uppercase())
+}
+
+/// Reads headers sent either as a single value or a list of values per name, merging
+/// names that only differ in case
+pub(super) fn deserialize_headers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Values {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let mut headers: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, values) in HashMap::<String, Values>::deserialize(deserializer)? {
+        let entry = headers.entry(name.to_ascii_lowercase()).or_default();
+        match values {
+            Values::One(value) => entry.push(value),
+            Values::Many(values) => entry.extend(values),
+        }
+    }
+    Ok(headers)
+}
+
+/// Compares a hex signature against the HMAC of `payload` in constant time
+fn signature_matches(secret: &str, payload: &[u8], signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::command::Command;
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &str = r#"{"id":"evt_1","type":"charge.succeeded"}"#;
+
+    fn sign(payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(payload);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn webhook(headers: &[(&str, String)]) -> Webhook {
+        Webhook {
+            method: "POST".to_string(),
+            path: "/hooks/payments".to_string(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_ascii_lowercase(), vec![value.clone()]))
+                .collect(),
+            query: HashMap::new(),
+            body: STANDARD.encode(BODY),
+            received_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_body() {
+        let command = json!({
+            "method": "post",
+            "path": "/hooks/payments",
+            "headers": {
+                "Content-Type": "application/json",
+                "X-Forwarded-For": ["203.0.113.7", "10.0.0.1"],
+            },
+            "body": STANDARD.encode(BODY),
+            "received_at": "2024-03-01T12:00:00Z",
+        });
+        let Command::Webhook(webhook) = Command::<()>::from_tagged("webhook", command).unwrap()
+        else {
+            panic!("Expected a webhook");
+        };
+        assert_eq!(webhook.method, "POST");
+        assert_eq!(webhook.header("content-type"), Some("application/json"));
+        assert_eq!(
+            webhook.header_values("x-forwarded-for"),
+            ["203.0.113.7", "10.0.0.1"]
+        );
+        assert!(webhook.header_values("Authorization").is_empty());
+        assert_eq!(webhook.text().unwrap(), BODY);
+        let event: serde_json::Value = webhook.json().unwrap();
+        assert_eq!(event["type"], "charge.succeeded");
+    }
+
+    #[test]
+    fn test_verify_stripe() {
+        let timestamp = webhook(&[]).received_at.timestamp() - 60;
+        let signature = sign(format!("{}.{}", timestamp, BODY).as_bytes());
+        let header = format!("t={},v1={},v1=00", timestamp, signature);
+        let request = webhook(&[("stripe-signature", header)]);
+        request.verify(&WebhookSignature::stripe(), SECRET).unwrap();
+
+        let err = request
+            .verify(&WebhookSignature::stripe(), "other")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Webhook signature does not match");
+
+        let strict = WebhookSignature::Stripe {
+            tolerance: TimeDelta::seconds(30),
+        };
+        let err = request.verify(&strict, SECRET).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Webhook timestamp 2024-03-01 11:59:00 UTC is more than 30s from when it was received"
+        );
+    }
+
+    #[test]
+    fn test_verify_github() {
+        let header = format!("sha256={}", sign(BODY.as_bytes()));
+        webhook(&[("X-Hub-Signature-256", header.clone())])
+            .verify(&WebhookSignature::GitHub, SECRET)
+            .unwrap();
+
+        let custom = WebhookSignature::HmacSha256 {
+            header: "X-Signature".to_string(),
+            prefix: String::new(),
+        };
+        webhook(&[("X-Signature", sign(BODY.as_bytes()))])
+            .verify(&custom, SECRET)
+            .unwrap();
+
+        let err = webhook(&[])
+            .verify(&WebhookSignature::GitHub, SECRET)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Webhook is missing the X-Hub-Signature-256 header"
+        );
+    }
+}
@@ -1,5 +1,16 @@
-use anyhow::{Context, Result};
-use csv_core::{ReadFieldResult, Reader};
+use std::{fmt::Debug, ops::ControlFlow, sync::Arc};
+
+use anyhow::{Context, Result, anyhow, bail};
+pub use csv_core::QuoteStyle;
+use csv_core::{ReadFieldResult, Reader, ReaderBuilder, Terminator};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use de::RecordDeserializer;
+
+mod de;
+mod writer;
+
+pub use writer::CsvWriter;
 
 /// Maximum size for the output buffer when reading CSV fields
 const OUTPUT_BUFFER_SIZE: usize = 1024;
@@ -11,6 +22,9 @@ pub struct Csv;
 impl Csv {
     /// Parses CSV data with optional row and column filtering.
     ///
+    /// Rows that are too short for the requested column window are truncated. Use
+    /// [`CsvReader`] to choose a different [`RaggedRows`] policy.
+    ///
     /// # Arguments
     ///
     /// * `bytes` - The input CSV data as a byte slice
@@ -29,85 +43,569 @@ impl Csv {
     /// * UTF-8 conversion fails
     /// * Input data is malformed
     pub fn parse(
-        mut bytes: &[u8],
+        bytes: &[u8],
         starting_col: Option<usize>,
         starting_row: Option<usize>,
         cols: Option<usize>,
         rows: Option<usize>,
     ) -> Result<Vec<Vec<String>>> {
-        let mut rdr = Reader::new();
+        CsvReader {
+            starting_col: starting_col.unwrap_or(0),
+            starting_row: starting_row.unwrap_or(0),
+            cols,
+            rows,
+            ..CsvReader::default()
+        }
+        .parse(bytes)
+    }
+}
+
+/// Policy for rows that have fewer cells than the column window asks for.
+///
+/// The window width is `cols` when set, otherwise the width of the header row. Without
+/// either, every row keeps all of its cells from `starting_col` on. Rows longer than the
+/// window are always cut to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RaggedRows {
+    /// Fill the missing cells with empty strings
+    Pad,
+    /// Return only the cells the row has
+    #[default]
+    Truncate,
+    /// Fail with the index of the offending row
+    Error,
+}
+
+/// The delimiter, quote and record terminator of a CSV file, shared by [`CsvReader`]
+/// and [`CsvWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Record terminator. `None` reads any of `\r`, `\n` or `\r\n` and writes `\r\n`.
+    pub terminator: Option<u8>,
+}
+
+impl Dialect {
+    /// Comma separated, double quoted
+    pub const CSV: Dialect = Dialect {
+        delimiter: b',',
+        quote: b'"',
+        terminator: None,
+    };
+
+    /// Semicolon separated, as exported by spreadsheet tools in locales that use a
+    /// decimal comma
+    pub const SEMICOLON: Dialect = Dialect {
+        delimiter: b';',
+        ..Dialect::CSV
+    };
+
+    /// Tab separated
+    pub const TSV: Dialect = Dialect {
+        delimiter: b'\t',
+        ..Dialect::CSV
+    };
+
+    fn csv_core_terminator(&self) -> Terminator {
+        self.terminator.map_or(Terminator::CRLF, Terminator::Any)
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect::CSV
+    }
+}
+
+/// Matches a row of a CSV file, used to find where a header or footer begins.
+#[derive(Clone)]
+pub enum RowMatch {
+    /// The row contains every one of these column names, compared trimmed and
+    /// case-insensitively
+    Columns(Vec<String>),
+    /// The first cell starts with this prefix, for example `"Total"`
+    StartsWith(String),
+    /// Every cell in the row is empty
+    Blank,
+    /// A custom check over the cells of the row
+    Predicate(RowPredicate),
+}
+
+pub type RowPredicate = Arc<dyn Fn(&[String]) -> bool + Send + Sync>;
+
+impl RowMatch {
+    pub fn columns<S: Into<String>>(columns: impl IntoIterator<Item = S>) -> Self {
+        Self::Columns(columns.into_iter().map(Into::into).collect())
+    }
+
+    pub fn predicate(f: impl Fn(&[String]) -> bool + Send + Sync + 'static) -> Self {
+        Self::Predicate(Arc::new(f))
+    }
+
+    pub fn matches(&self, row: &[String]) -> bool {
+        match self {
+            RowMatch::Columns(columns) => columns.iter().all(|column| {
+                row.iter()
+                    .any(|cell| cell.trim().eq_ignore_ascii_case(column.trim()))
+            }),
+            RowMatch::StartsWith(prefix) => row
+                .first()
+                .is_some_and(|cell| cell.trim_start().starts_with(prefix.as_str())),
+            RowMatch::Blank => row.iter().all(|cell| cell.trim().is_empty()),
+            RowMatch::Predicate(f) => f(row),
+        }
+    }
+}
+
+impl Debug for RowMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowMatch::Columns(columns) => f.debug_tuple("Columns").field(columns).finish(),
+            RowMatch::StartsWith(prefix) => f.debug_tuple("StartsWith").field(prefix).finish(),
+            RowMatch::Blank => f.write_str("Blank"),
+            RowMatch::Predicate(_) => f.write_str("Predicate"),
+        }
+    }
+}
+
+/// A CSV file split into its preamble, header, data rows and footer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CsvTable {
+    /// Rows before the header, unfiltered
+    pub preamble: Vec<Vec<String>>,
+    /// The header row within the column window. Empty when no header was requested.
+    pub headers: Vec<String>,
+    /// Data rows within the row and column window
+    pub rows: Vec<Vec<String>>,
+    /// The row that matched the footer and everything after it, unfiltered
+    pub footer: Vec<Vec<String>>,
+}
+
+impl CsvTable {
+    /// Keys and their values found in the preamble.
+    ///
+    /// A line yields a key when it has a key cell followed by value cells
+    /// (`Period:,2024-03-01,2024-03-31`) or a single cell split on its first colon
+    /// (`Statement Period: 01/03/2024 - 31/03/2024`). Every non-empty cell after the key
+    /// is kept as a value. Trailing colons are removed from keys and all cells are
+    /// trimmed.
+    pub fn preamble_values(&self) -> Vec<(&str, Vec<&str>)> {
+        self.preamble
+            .iter()
+            .filter_map(|row| key_values(row))
+            .collect()
+    }
+
+    /// Looks up the first preamble value of a key, ignoring case. See
+    /// [`CsvTable::preamble_values`] for every value.
+    pub fn preamble_value(&self, key: &str) -> Option<&str> {
+        self.preamble
+            .iter()
+            .filter_map(|row| key_values(row))
+            .find(|(k, _)| k.eq_ignore_ascii_case(key.trim()))
+            .map(|(_, values)| values[0])
+    }
+
+    /// Deserializes each data row into `T`, matching field names to headers. When no
+    /// header was read the first data row is used as the header.
+    ///
+    /// Cells are parsed into the type of their field, and empty cells deserialize as
+    /// `None` for optional fields.
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a self) -> Result<Vec<T>> {
+        let (headers, rows) = if self.headers.is_empty() {
+            match self.rows.split_first() {
+                Some((headers, rows)) => (headers, rows),
+                None => return Ok(Vec::new()),
+            }
+        } else {
+            (&self.headers, self.rows.as_slice())
+        };
+
+        rows.iter()
+            .enumerate()
+            .map(|(idx, row)| {
+                T::deserialize(RecordDeserializer::new(headers, row))
+                    .map_err(|e| anyhow!("Failed to deserialize data row {}: {}", idx, e))
+            })
+            .collect()
+    }
+
+    /// Index of a header within the column window, ignoring case
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+fn key_values(row: &[String]) -> Option<(&str, Vec<&str>)> {
+    let mut cells = row
+        .iter()
+        .map(|cell| cell.trim())
+        .filter(|cell| !cell.is_empty());
+    let first = cells.next()?;
+
+    let values: Vec<&str> = cells.collect();
+    let (key, values) = if values.is_empty() {
+        let (key, value) = first.split_once(':')?;
+        (key, vec![value.trim()])
+    } else {
+        (first, values)
+    };
+
+    let key = key.trim().trim_end_matches(':').trim_end();
+    (!key.is_empty() && !values[0].is_empty()).then_some((key, values))
+}
+
+/// Receives each record's index and cells, and decides whether reading continues
+type RecordFn<'a> = dyn FnMut(usize, Vec<String>) -> Result<ControlFlow<()>> + 'a;
+
+/// A configurable CSV reader.
+///
+/// ```
+/// use contour_rust_pdk::csv::{CsvReader, RaggedRows};
+///
+/// let table = CsvReader::new()
+///     .starting_row(1)
+///     .cols(2)
+///     .ragged_rows(RaggedRows::Pad)
+///     .parse(b"a,b\n1,2\n3")
+///     .unwrap();
+///
+/// assert_eq!(table, vec![vec!["1", "2"], vec!["3", ""]]);
+/// ```
+///
+/// Exports with a variable length preamble and a totals footer can be located by their
+/// header instead of a fixed offset:
+///
+/// ```
+/// use contour_rust_pdk::csv::{CsvReader, RowMatch};
+///
+/// let input = b"Account Number:,12345\nPeriod:,March 2024\n\nDate,Amount\n2024-03-01,10.00\nTotal,10.00\n";
+///
+/// let table = CsvReader::new()
+///     .header(RowMatch::columns(["Date", "Amount"]))
+///     .footer(RowMatch::StartsWith("Total".to_string()))
+///     .read(input)
+///     .unwrap();
+///
+/// assert_eq!(table.preamble_value("account number"), Some("12345"));
+/// assert_eq!(table.headers, vec!["Date", "Amount"]);
+/// assert_eq!(table.rows, vec![vec!["2024-03-01", "10.00"]]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CsvReader {
+    starting_col: usize,
+    starting_row: usize,
+    cols: Option<usize>,
+    rows: Option<usize>,
+    ragged_rows: RaggedRows,
+    dialect: Dialect,
+    header: Option<RowMatch>,
+    footer: Option<RowMatch>,
+}
+
+impl CsvReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starting column index (0-based)
+    pub fn starting_col(mut self, starting_col: usize) -> Self {
+        self.starting_col = starting_col;
+        self
+    }
+
+    /// Starting row index (0-based). When a header is set this counts data rows after
+    /// the header.
+    pub fn starting_row(mut self, starting_row: usize) -> Self {
+        self.starting_row = starting_row;
+        self
+    }
+
+    /// Number of columns to include
+    pub fn cols(mut self, cols: usize) -> Self {
+        self.cols = Some(cols);
+        self
+    }
+
+    /// Number of rows to include
+    pub fn rows(mut self, rows: usize) -> Self {
+        self.rows = Some(rows);
+        self
+    }
+
+    /// Policy for rows too short to fill the column window
+    pub fn ragged_rows(mut self, ragged_rows: RaggedRows) -> Self {
+        self.ragged_rows = ragged_rows;
+        self
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Treats the first row matching `header` as the header. Rows before it become the
+    /// preamble.
+    pub fn header(mut self, header: RowMatch) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// Ends the data rows at the first row after the header matching `footer`
+    pub fn footer(mut self, footer: RowMatch) -> Self {
+        self.footer = Some(footer);
+        self
+    }
+
+    /// Parses CSV data using the configured window and ragged row policy.
+    ///
+    /// Blank lines are returned as rows with a single empty cell, and a final record
+    /// without a trailing line terminator is included. When a header is set it is
+    /// returned as the first row, and the preamble and footer are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// * UTF-8 conversion fails
+    /// * Input data is malformed
+    /// * A row is too short for the window and the policy is [`RaggedRows::Error`]
+    /// * A header is set and no row matches it
+    pub fn parse(&self, bytes: &[u8]) -> Result<Vec<Vec<String>>> {
+        Ok(self.flatten(self.read(bytes)?))
+    }
+
+    /// Reads CSV data and deserializes its data rows with [`CsvTable::deserialize`]
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<Vec<T>> {
+        self.read(bytes)?.deserialize()
+    }
+
+    /// Reads CSV data into its preamble, header, data rows and footer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the same cases as [`CsvReader::parse`].
+    pub fn read(&self, bytes: &[u8]) -> Result<CsvTable> {
+        self.build_table(|f| self.for_each_record(bytes, f))
+    }
+
+    /// Applies the window, header and footer to rows that were already split into
+    /// cells, such as the rows of a spreadsheet. Returns rows in the same shape as
+    /// [`CsvReader::parse`].
+    pub fn parse_rows(
+        &self,
+        rows: impl IntoIterator<Item = Vec<String>>,
+    ) -> Result<Vec<Vec<String>>> {
+        Ok(self.flatten(self.read_rows(rows)?))
+    }
+
+    /// Splits rows that were already split into cells into their preamble, header,
+    /// data rows and footer
+    pub fn read_rows(&self, rows: impl IntoIterator<Item = Vec<String>>) -> Result<CsvTable> {
+        self.build_table(|f| {
+            for (row_idx, row) in rows.into_iter().enumerate() {
+                if f(row_idx, row)?.is_break() {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn flatten(&self, table: CsvTable) -> Vec<Vec<String>> {
+        let mut rows = Vec::with_capacity(table.rows.len() + 1);
+        if self.header.is_some() {
+            rows.push(table.headers);
+        }
+        rows.extend(table.rows);
+        rows
+    }
+
+    /// Sorts the records produced by `records` into a table
+    fn build_table(&self, records: impl FnOnce(&mut RecordFn) -> Result<()>) -> Result<CsvTable> {
+        let mut table = CsvTable::default();
+        let mut in_preamble = self.header.is_some();
+        let mut in_footer = false;
+        let mut width = None;
+        let mut data_idx = 0;
+
+        records(&mut |row_idx, row| {
+            if in_footer {
+                table.footer.push(row);
+                return Ok(ControlFlow::Continue(()));
+            }
+
+            if in_preamble {
+                if self.header.as_ref().is_some_and(|h| h.matches(&row)) {
+                    let header_width = row.len().saturating_sub(self.starting_col);
+                    let header_width = *width.insert(self.cols.unwrap_or(header_width));
+                    table.headers = self.filter_row(row, row_idx, header_width)?;
+                    in_preamble = false;
+                } else {
+                    table.preamble.push(row);
+                }
+                return Ok(ControlFlow::Continue(()));
+            }
+
+            if self.footer.as_ref().is_some_and(|f| f.matches(&row)) {
+                table.footer.push(row);
+                in_footer = true;
+                return Ok(ControlFlow::Continue(()));
+            }
+
+            if self.should_process_row(data_idx) {
+                // Without `cols` or a header every row keeps its own width
+                let width = width.unwrap_or_else(|| {
+                    self.cols
+                        .unwrap_or(row.len().saturating_sub(self.starting_col))
+                });
+                table.rows.push(self.filter_row(row, row_idx, width)?);
+            }
+
+            // Keep reading when a footer is set so it is still collected
+            if self.should_stop_processing(data_idx) && self.footer.is_none() {
+                return Ok(ControlFlow::Break(()));
+            }
+
+            data_idx += 1;
+            Ok(ControlFlow::Continue(()))
+        })?;
+
+        if in_preamble {
+            bail!("CSV header row not found");
+        }
+
+        Ok(table)
+    }
+
+    /// Reads each record of the input, passing its index and cells to `f` until the
+    /// input ends or `f` breaks
+    fn for_each_record(&self, mut bytes: &[u8], f: &mut RecordFn) -> Result<()> {
+        let mut rdr = self.reader();
         let mut row_idx = 0;
         let output = &mut [0; OUTPUT_BUFFER_SIZE];
 
-        let mut table = Vec::new();
         let mut row = Vec::new();
-        let mut cell = String::new();
+        let mut cell = Vec::new();
 
-        let starting_col = starting_col.unwrap_or(0);
-        let starting_row = starting_row.unwrap_or(0);
+        let mut at_record_start = true;
+        let mut after_cr = false;
 
         loop {
-            let (result, bytes_read, bytes_written) = rdr.read_field(bytes, output);
-            bytes = &bytes[bytes_read..];
-            let field_data = &output[..bytes_written];
-
-            match result {
-                ReadFieldResult::InputEmpty | ReadFieldResult::End => break,
-
-                ReadFieldResult::OutputFull => {
-                    cell.push_str(
-                        std::str::from_utf8(field_data)
-                            .context("Failed to convert field data to UTF-8")?,
-                    );
-                }
+            if at_record_start && self.take_blank_line(&mut bytes, &mut after_cr) {
+                row.push(String::new());
+            } else {
+                let (result, bytes_read, bytes_written) = rdr.read_field(bytes, output);
+                let consumed = &bytes[..bytes_read];
+                bytes = &bytes[bytes_read..];
+                cell.extend_from_slice(&output[..bytes_written]);
+                at_record_start = false;
 
-                ReadFieldResult::Field { record_end } => {
-                    cell.push_str(
-                        std::str::from_utf8(field_data)
-                            .context("Failed to convert field data to UTF-8")?,
-                    );
-                    row.push(cell);
-                    cell = String::new();
-
-                    if record_end {
-                        if Self::should_process_row(row_idx, starting_row, rows) {
-                            let filtered_row = Self::filter_row(&row, starting_col, cols);
-                            table.push(filtered_row);
+                match result {
+                    ReadFieldResult::End => break,
+                    ReadFieldResult::InputEmpty | ReadFieldResult::OutputFull => continue,
+                    ReadFieldResult::Field { record_end } => {
+                        row.push(
+                            String::from_utf8(std::mem::take(&mut cell))
+                                .context("Failed to convert field data to UTF-8")?,
+                        );
+                        if !record_end {
+                            continue;
                         }
-
-                        if Self::should_stop_processing(row_idx, starting_row, rows) {
-                            break;
-                        }
-
-                        row_idx += 1;
-                        row = Vec::new();
+                        after_cr = consumed.last() == Some(&b'\r');
                     }
                 }
             }
+
+            at_record_start = true;
+
+            if f(row_idx, std::mem::take(&mut row))?.is_break() {
+                break;
+            }
+
+            row_idx += 1;
         }
 
-        Ok(table)
+        Ok(())
+    }
+
+    fn reader(&self) -> Reader {
+        ReaderBuilder::new()
+            .delimiter(self.dialect.delimiter)
+            .quote(self.dialect.quote)
+            .terminator(self.dialect.csv_core_terminator())
+            .build()
+    }
+
+    /// Consumes a blank line at the start of a record. csv_core skips these, which
+    /// would shift every following row index.
+    fn take_blank_line(&self, bytes: &mut &[u8], after_cr: &mut bool) -> bool {
+        if let Some(terminator) = self.dialect.terminator {
+            if bytes.first() == Some(&terminator) {
+                *bytes = &bytes[1..];
+                return true;
+            }
+            return false;
+        }
+
+        // The `\n` of a `\r\n` terminator is left unread after the previous record
+        if std::mem::take(after_cr) && bytes.first() == Some(&b'\n') {
+            *bytes = &bytes[1..];
+        }
+
+        match bytes.first() {
+            Some(b'\n') => {
+                *bytes = &bytes[1..];
+                true
+            }
+            Some(b'\r') => {
+                *bytes = &bytes[1..];
+                *after_cr = true;
+                true
+            }
+            _ => false,
+        }
     }
 
     /// Determines if the current row should be processed based on filtering criteria
     #[inline]
-    fn should_process_row(row_idx: usize, starting_row: usize, rows: Option<usize>) -> bool {
-        row_idx >= starting_row && rows.is_none_or(|r| row_idx < starting_row + r)
+    fn should_process_row(&self, row_idx: usize) -> bool {
+        row_idx >= self.starting_row && self.rows.is_none_or(|r| row_idx < self.starting_row + r)
     }
 
     /// Determines if processing should stop based on row constraints
     #[inline]
-    fn should_stop_processing(row_idx: usize, starting_row: usize, rows: Option<usize>) -> bool {
-        rows.is_some_and(|r| row_idx >= starting_row + r)
+    fn should_stop_processing(&self, row_idx: usize) -> bool {
+        self.rows
+            .is_some_and(|r| row_idx + 1 >= self.starting_row + r)
     }
 
-    /// Filters a row based on column constraints
-    #[inline]
-    fn filter_row(row: &[String], starting_col: usize, cols: Option<usize>) -> Vec<String> {
-        match cols {
-            Some(n) => row[starting_col..starting_col + n].to_vec(),
-            None => row[starting_col..].to_vec(),
+    /// Cuts a row down to the column window, applying the ragged row policy when the
+    /// row is too short to fill it
+    fn filter_row(&self, row: Vec<String>, row_idx: usize, width: usize) -> Result<Vec<String>> {
+        let len = row.len();
+        let mut filtered: Vec<String> = row
+            .into_iter()
+            .skip(self.starting_col)
+            .take(width)
+            .collect();
+
+        if filtered.len() < width {
+            match self.ragged_rows {
+                RaggedRows::Pad => filtered.resize(width, String::new()),
+                RaggedRows::Truncate => {}
+                RaggedRows::Error => bail!(
+                    "CSV row {} has {} columns, expected at least {}",
+                    row_idx,
+                    len,
+                    self.starting_col + width
+                ),
+            }
         }
+
+        Ok(filtered)
     }
 }
 
@@ -339,4 +837,228 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_ragged_rows_truncate() {
+        let input = b"a,b,c\n1,2,3\n4\nTotal,10";
+        let result = Csv::parse(input, Some(1), None, Some(2), None).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                vec!["b".to_string(), "c".to_string()],
+                vec!["2".to_string(), "3".to_string()],
+                vec![],
+                vec!["10".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variable_width_without_cols() {
+        // Without `cols` or a header rows keep their own width, as they always have
+        let result = Csv::parse(b"a\n1,2,3", None, None, None, None).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                vec!["a".to_string()],
+                vec!["1".to_string(), "2".to_string(), "3".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ragged_rows_pad() {
+        let input = b"a,b,c\n1,2\n3,4,5,6";
+        let result = CsvReader::new()
+            .cols(3)
+            .ragged_rows(RaggedRows::Pad)
+            .parse(input)
+            .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                vec!["1".to_string(), "2".to_string(), "".to_string()],
+                vec!["3".to_string(), "4".to_string(), "5".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ragged_rows_error() {
+        let input = b"a,b,c\n1,2,3\n4,5";
+        let err = CsvReader::new()
+            .cols(3)
+            .ragged_rows(RaggedRows::Error)
+            .parse(input)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CSV row 2 has 2 columns, expected at least 3"
+        );
+
+        // Rows outside the window are not checked
+        let result = CsvReader::new()
+            .cols(3)
+            .rows(2)
+            .ragged_rows(RaggedRows::Error)
+            .parse(input)
+            .unwrap();
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_crlf_and_blank_lines() {
+        let input = b"a,b\r\n\r\n1,2\r\n";
+        let result = Csv::parse(input, None, None, None, None).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec!["".to_string()],
+                vec!["1".to_string(), "2".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_larger_than_buffer() {
+        let long = "é".repeat(OUTPUT_BUFFER_SIZE);
+        let input = format!("{long},x");
+        let result = Csv::parse(input.as_bytes(), None, None, None, None).unwrap();
+        assert_eq!(result, vec![vec![long, "x".to_string()]]);
+    }
+
+    #[test]
+    fn test_header_and_footer_detection() {
+        let input = b"Brokerage Statement\nAccount: 987-654\nPeriod:,2024-03-01,2024-03-31\n\nTrade Date,Symbol,Amount\n2024-03-04,AAPL,100.00\n2024-03-05,MSFT,-50.00\n\nTotal,,50.00\n";
+        let table = CsvReader::new()
+            .header(RowMatch::columns(["symbol", "trade date"]))
+            .footer(RowMatch::Blank)
+            .read(input)
+            .unwrap();
+
+        assert_eq!(table.preamble.len(), 4);
+        assert_eq!(
+            table.preamble_values(),
+            vec![
+                ("Account", vec!["987-654"]),
+                ("Period", vec!["2024-03-01", "2024-03-31"])
+            ]
+        );
+        assert_eq!(table.preamble_value("ACCOUNT"), Some("987-654"));
+        assert_eq!(
+            table.headers,
+            vec![
+                "Trade Date".to_string(),
+                "Symbol".to_string(),
+                "Amount".to_string()
+            ]
+        );
+        assert_eq!(table.column("amount"), Some(2));
+        assert_eq!(
+            table.rows,
+            vec![
+                vec![
+                    "2024-03-04".to_string(),
+                    "AAPL".to_string(),
+                    "100.00".to_string()
+                ],
+                vec![
+                    "2024-03-05".to_string(),
+                    "MSFT".to_string(),
+                    "-50.00".to_string()
+                ],
+            ]
+        );
+        assert_eq!(
+            table.footer,
+            vec![
+                vec!["".to_string()],
+                vec!["Total".to_string(), "".to_string(), "50.00".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header_predicate_with_windowing() {
+        let input = b"Report generated 2024-04-01\n,Date,Amount\n,2024-03-01,1\n,2024-03-02,2\n,2024-03-03,3\nTotal,,6";
+        let result = CsvReader::new()
+            .header(RowMatch::predicate(|row| {
+                row.get(1).is_some_and(|cell| cell == "Date")
+            }))
+            .footer(RowMatch::StartsWith("Total".to_string()))
+            .starting_col(1)
+            .starting_row(1)
+            .rows(1)
+            .parse(input)
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                vec!["Date".to_string(), "Amount".to_string()],
+                vec!["2024-03-02".to_string(), "2".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header_not_found() {
+        let input = b"a,b\n1,2";
+        let err = CsvReader::new()
+            .header(RowMatch::columns(["Date"]))
+            .read(input)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "CSV header row not found");
+    }
+
+    #[test]
+    fn test_deserialize_rows() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Side {
+            Buy,
+            Sell,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Trade {
+            symbol: String,
+            side: Side,
+            quantity: u32,
+            price: Option<f64>,
+            settled: bool,
+        }
+
+        let input =
+            b"symbol,side,quantity,price,settled\nAAPL,Buy,10,187.5,yes\nMSFT,Sell, 5 ,,false";
+        let trades: Vec<Trade> = CsvReader::new().deserialize(input).unwrap();
+        assert_eq!(
+            trades,
+            vec![
+                Trade {
+                    symbol: "AAPL".to_string(),
+                    side: Side::Buy,
+                    quantity: 10,
+                    price: Some(187.5),
+                    settled: true,
+                },
+                Trade {
+                    symbol: "MSFT".to_string(),
+                    side: Side::Sell,
+                    quantity: 5,
+                    price: None,
+                    settled: false,
+                },
+            ]
+        );
+
+        let err = CsvReader::new()
+            .deserialize::<Trade>(b"symbol,side,quantity,price,settled\nAAPL,Buy,ten,,true")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to deserialize data row 0: quantity: expected u32, found \"ten\""
+        );
+    }
 }
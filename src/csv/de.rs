@@ -0,0 +1,168 @@
+use std::fmt::Display;
+
+use serde::de::{
+    self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+    value::StrDeserializer,
+};
+
+#[derive(Debug)]
+pub(super) struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Presents a row as a map from header to cell
+pub(super) struct RecordDeserializer<'a> {
+    headers: &'a [String],
+    row: &'a [String],
+    idx: usize,
+}
+
+impl<'a> RecordDeserializer<'a> {
+    pub(super) fn new(headers: &'a [String], row: &'a [String]) -> Self {
+        Self {
+            headers,
+            row,
+            idx: 0,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for RecordDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for RecordDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        // Headers without a cell in this row are left out so their fields fall back to
+        // `Option`/`#[serde(default)]` handling
+        if self.idx >= self.headers.len().min(self.row.len()) {
+            return Ok(None);
+        }
+        let header: StrDeserializer<Error> = self.headers[self.idx].trim().into_deserializer();
+        seed.deserialize(header).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let header = &self.headers[self.idx];
+        let cell = &self.row[self.idx];
+        self.idx += 1;
+        seed.deserialize(CellDeserializer(cell))
+            .map_err(|e| Error(format!("{}: {}", header.trim(), e)))
+    }
+}
+
+/// Deserializes a single cell, parsing it into whichever type is asked for
+struct CellDeserializer<'a>(&'a str);
+
+impl CellDeserializer<'_> {
+    fn parse<T: std::str::FromStr>(&self, expected: &str) -> Result<T, Error> {
+        self.0
+            .trim()
+            .parse()
+            .map_err(|_| Error(format!("expected {}, found {:?}", expected, self.0)))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse::<$ty>(stringify!($ty))?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for CellDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+        deserialize_char => visit_char: char,
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => visitor.visit_bool(true),
+            "false" | "no" | "n" | "0" => visitor.visit_bool(false),
+            _ => Err(Error(format!("expected bool, found {:?}", self.0))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.0.trim().is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant: StrDeserializer<Error> = self.0.trim().into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
@@ -0,0 +1,695 @@
+use anyhow::{Context, Result, bail};
+use csv_core::{WriteResult, Writer, WriterBuilder};
+use serde::{
+    Serialize,
+    ser::{self, Impossible},
+};
+
+use super::{Dialect, QuoteStyle};
+
+/// Size of the scratch buffer csv_core writes into before it is copied to the output
+const OUTPUT_BUFFER_SIZE: usize = 1024;
+
+/// Writes CSV data into an in-memory buffer.
+///
+/// Records can be written as rows of strings or as serde structs, in which case the
+/// field names are written as a header before the first record. The finished output
+/// can be sent as a request body:
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use contour_rust_pdk::csv::CsvWriter;
+/// use contour_rust_pdk::inputs::{RequestBuilder, RequestMethod};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Row {
+///     account: String,
+///     balance: f64,
+/// }
+///
+/// let mut writer = CsvWriter::new();
+/// writer.serialize(&Row { account: "Cash, USD".to_string(), balance: 10.5 }).unwrap();
+/// let body = writer.into_string().unwrap();
+/// assert_eq!(body, "account,balance\r\n\"Cash, USD\",10.5\r\n");
+///
+/// let request = RequestBuilder::new(
+///     "https://api.example.com".to_string(),
+///     "/upload".to_string(),
+///     RequestMethod::Post,
+/// )
+/// .add_headers(HashMap::from([(
+///     "Content-Type".to_string(),
+///     "text/csv".to_string(),
+/// )]))
+/// .add_body(body)
+/// .unwrap()
+/// .build();
+/// ```
+#[derive(Debug)]
+pub struct CsvWriter {
+    wtr: Writer,
+    output: Vec<u8>,
+    header: Option<Vec<String>>,
+}
+
+impl CsvWriter {
+    pub fn new() -> Self {
+        Self::with_options(Dialect::default(), QuoteStyle::Necessary)
+    }
+
+    /// Creates a writer using `dialect` and quoting fields according to `quote_style`
+    pub fn with_options(dialect: Dialect, quote_style: QuoteStyle) -> Self {
+        Self {
+            wtr: WriterBuilder::new()
+                .delimiter(dialect.delimiter)
+                .quote(dialect.quote)
+                .terminator(dialect.csv_core_terminator())
+                .quote_style(quote_style)
+                .build(),
+            output: Vec::new(),
+            header: None,
+        }
+    }
+
+    /// Writes one record
+    pub fn write_record<I, F>(&mut self, record: I) -> Result<()>
+    where
+        I: IntoIterator<Item = F>,
+        F: AsRef<[u8]>,
+    {
+        for (idx, field) in record.into_iter().enumerate() {
+            if idx > 0 {
+                self.write_with(|wtr, out| wtr.delimiter(out))?;
+            }
+            self.write_field(field.as_ref())?;
+        }
+        self.write_with(|wtr, out| wtr.terminator(out))
+    }
+
+    /// Writes each row as a record
+    pub fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<()> {
+        for row in rows {
+            self.write_record(row)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a serde struct or map as a record. The keys of the first record written
+    /// are used as the header, and every later record must have the same keys in the
+    /// same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not a struct or map, if a field holds a nested
+    /// sequence, map or struct, or if its keys do not match the header.
+    pub fn serialize<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        let mut fields = RecordSerializer::default();
+        record.serialize(&mut fields)?;
+        let keys: Vec<String> = fields.0.iter().map(|(key, _)| key.clone()).collect();
+
+        match &self.header {
+            Some(header) if *header != keys => bail!(
+                "CSV record has fields {:?}, expected the header {:?}",
+                keys,
+                header
+            ),
+            Some(_) => {}
+            None => {
+                self.write_record(&keys)?;
+                self.header = Some(keys);
+            }
+        }
+        self.write_record(fields.0.iter().map(|(_, value)| value))
+    }
+
+    /// Finishes writing and returns the CSV bytes
+    pub fn into_bytes(mut self) -> Result<Vec<u8>> {
+        self.write_with(|wtr, out| wtr.finish(out))?;
+        Ok(self.output)
+    }
+
+    /// Finishes writing and returns the CSV as a string
+    pub fn into_string(self) -> Result<String> {
+        String::from_utf8(self.into_bytes()?).context("Failed to convert CSV output to UTF-8")
+    }
+
+    fn write_field(&mut self, mut field: &[u8]) -> Result<()> {
+        let out = &mut [0; OUTPUT_BUFFER_SIZE];
+        loop {
+            let (result, nin, nout) = self.wtr.field(field, out);
+            field = &field[nin..];
+            self.output.extend_from_slice(&out[..nout]);
+            if let WriteResult::InputEmpty = result {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a csv_core step that only produces output until it fits the buffer
+    fn write_with(
+        &mut self,
+        mut step: impl FnMut(&mut Writer, &mut [u8]) -> (WriteResult, usize),
+    ) -> Result<()> {
+        let out = &mut [0; OUTPUT_BUFFER_SIZE];
+        loop {
+            let (result, nout) = step(&mut self.wtr, out);
+            self.output.extend_from_slice(&out[..nout]);
+            if let WriteResult::InputEmpty = result {
+                return Ok(());
+            }
+            if nout == 0 {
+                bail!("CSV writer made no progress");
+            }
+        }
+    }
+}
+
+impl Default for CsvWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    Error(format!("Cannot write {what} to CSV"))
+}
+
+/// Collects the fields of a struct or map as `(name, cell)` pairs
+#[derive(Default)]
+struct RecordSerializer(Vec<(String, String)>);
+
+impl<'a> ser::Serializer for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = RecordMap<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<RecordMap<'a>, Error> {
+        Ok(RecordMap {
+            record: self,
+            key: None,
+        })
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<(), Error> {
+        Err(unsupported("a bool as a record"))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), Error> {
+        Err(unsupported("a number as a record"))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), Error> {
+        Err(unsupported("a number as a record"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), Error> {
+        Err(unsupported("a number as a record"))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), Error> {
+        Err(unsupported("a string as a record"))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), Error> {
+        Err(unsupported("a string as a record"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), Error> {
+        Err(unsupported("bytes as a record"))
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Err(unsupported("an empty value as a record"))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Err(unsupported("an empty value as a record"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Err(unsupported("a unit struct as a record"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        Err(unsupported("an enum as a record"))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), Error> {
+        Err(unsupported("an enum as a record"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(unsupported("a sequence as a record"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(unsupported("a tuple as a record"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(unsupported("a tuple struct as a record"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(unsupported("an enum as a record"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(unsupported("an enum as a record"))
+    }
+}
+
+impl ser::SerializeStruct for &mut RecordSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.0
+            .push((key.to_string(), value.serialize(CellSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+struct RecordMap<'a> {
+    record: &'a mut RecordSerializer,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for RecordMap<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(CellSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("Map value written before its key".to_string()))?;
+        self.record.0.push((key, value.serialize(CellSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Formats a single scalar value as the text of a cell
+struct CellSerializer;
+
+impl ser::Serializer for CellSerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<String, Error> {
+        String::from_utf8(v.to_vec()).map_err(|e| Error(e.to_string()))
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(unsupported("an enum with data in a cell"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(unsupported("a sequence in a cell"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(unsupported("a tuple in a cell"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(unsupported("a tuple struct in a cell"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(unsupported("an enum with data in a cell"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(unsupported("a map in a cell"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(unsupported("a struct in a cell"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(unsupported("an enum with data in a cell"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::csv::CsvReader;
+
+    #[derive(Serialize)]
+    enum Side {
+        Buy,
+    }
+
+    #[derive(Serialize)]
+    struct Trade {
+        date: DateTime<Utc>,
+        side: Side,
+        symbol: String,
+        amount: Decimal,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_write_rows_quotes_fields() {
+        let mut writer = CsvWriter::new();
+        writer
+            .write_rows(&[
+                vec!["a,b".to_string(), "c".to_string()],
+                vec!["\"quoted\"".to_string(), "line\nbreak".to_string()],
+            ])
+            .unwrap();
+
+        let output = writer.into_bytes().unwrap();
+        assert_eq!(
+            output,
+            b"\"a,b\",c\r\n\"\"\"quoted\"\"\",\"line\nbreak\"\r\n"
+        );
+        assert_eq!(
+            CsvReader::new().parse(&output).unwrap(),
+            vec![
+                vec!["a,b".to_string(), "c".to_string()],
+                vec!["\"quoted\"".to_string(), "line\nbreak".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serialize_structs() {
+        let mut writer = CsvWriter::with_options(Dialect::SEMICOLON, QuoteStyle::Necessary);
+        for note in [None, Some("partial; fill".to_string())] {
+            writer
+                .serialize(&Trade {
+                    date: "2024-03-01T00:00:00Z".parse().unwrap(),
+                    side: Side::Buy,
+                    symbol: "AAPL".to_string(),
+                    amount: dec!(100.50),
+                    note,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            writer.into_string().unwrap(),
+            "date;side;symbol;amount;note\r\n\
+             2024-03-01T00:00:00Z;Buy;AAPL;100.50;\r\n\
+             2024-03-01T00:00:00Z;Buy;AAPL;100.50;\"partial; fill\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_serialize_rejects_nested_values() {
+        #[derive(Serialize)]
+        struct Nested {
+            tags: Vec<String>,
+        }
+
+        let mut writer = CsvWriter::new();
+        let err = writer
+            .serialize(&Nested {
+                tags: vec!["a".to_string()],
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cannot write a sequence in a cell to CSV");
+
+        let err = writer.serialize(&"not a record").unwrap_err();
+        assert_eq!(err.to_string(), "Cannot write a string as a record to CSV");
+    }
+
+    #[test]
+    fn test_serialize_rejects_other_fields() {
+        #[derive(Serialize)]
+        struct Row {
+            account: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            balance: Option<f64>,
+        }
+
+        let mut writer = CsvWriter::new();
+        writer
+            .serialize(&Row {
+                account: "Cash".to_string(),
+                balance: Some(10.5),
+            })
+            .unwrap();
+        let err = writer
+            .serialize(&Row {
+                account: "Card".to_string(),
+                balance: None,
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CSV record has fields [\"account\"], expected the header [\"account\", \"balance\"]"
+        );
+        assert_eq!(
+            writer.into_string().unwrap(),
+            "account,balance\r\nCash,10.5\r\n"
+        );
+    }
+}
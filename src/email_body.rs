@@ -0,0 +1,643 @@
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+
+use crate::command::Email;
+use crate::statement::parse_decimal;
+use crate::text::decode_entities;
+
+/// Turns email bodies into text and rows, and pulls amounts, dates and other fields out
+/// of the text.
+///
+/// Receipts and trade confirmations often carry their data only in HTML tables in the
+/// body. [`EmailBody::tables`] returns each table in the same shape as
+/// [`Csv::parse`](crate::csv::Csv::parse), so the rows can be mapped the same way as an
+/// attached CSV.
+pub struct EmailBody;
+
+/// Decimal numbers with optional thousands separators, as in `1,234.56`, `1.234,56`,
+/// `1 234,56` or `1'234.56`
+const NUMBER: &str = r"\d{1,3}(?:[,.' \u{a0}]\d{3})+(?:[.,]\d{1,2})?|\d+(?:[.,]\d{1,2})?";
+
+static AMOUNT: LazyLock<Regex> = LazyLock::new(|| amount_regex("").unwrap());
+
+static COLSPAN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\bcolspan\s*=\s*["']?(\d+)"#).unwrap());
+static ROWSPAN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\browspan\s*=\s*["']?(\d+)"#).unwrap());
+
+static ISO_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{4})-(\d{1,2})-(\d{1,2})\b").unwrap());
+static DOTTED_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2})\.(\d{1,2})\.(\d{4})\b").unwrap());
+static DAY_MONTH_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(\d{1,2})(?:st|nd|rd|th)?\.?\s+([a-z]{3,9})\.?,?\s+(\d{4})\b").unwrap()
+});
+static MONTH_DAY_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b([a-z]{3,9})\.?\s+(\d{1,2})(?:st|nd|rd|th)?,?\s+(\d{4})\b").unwrap()
+});
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Elements that are set apart from the text around them by a blank line
+const PARAGRAPH_ELEMENTS: [&str; 11] = [
+    "blockquote",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ol",
+    "p",
+    "table",
+    "ul",
+];
+
+/// Elements that start a new line
+const LINE_ELEMENTS: [&str; 9] = [
+    "address", "article", "div", "footer", "header", "hr", "li", "section", "tr",
+];
+
+impl EmailBody {
+    /// Whether the body looks like HTML rather than plain text
+    pub fn is_html(body: &str) -> bool {
+        let lower = body.trim_start().to_ascii_lowercase();
+        lower.starts_with("<!doctype html")
+            || lower.starts_with("<html")
+            || ["<body", "<table", "<div", "<p>", "<br"]
+                .iter()
+                .any(|tag| lower.contains(tag))
+    }
+
+    /// Converts an HTML body to plain text. Scripts, styles and the `<head>` are dropped,
+    /// paragraphs and tables are set apart by blank lines, other block elements start new
+    /// lines and table cells are separated by tabs. Plain text bodies are returned
+    /// unchanged.
+    pub fn to_text(body: &str) -> String {
+        if !Self::is_html(body) {
+            return body.to_string();
+        }
+
+        let mut text = String::new();
+        let mut in_head = false;
+        for token in tokens(body) {
+            match token {
+                Token::Start { name, .. } if name == "head" => in_head = true,
+                Token::End(name) if name == "head" => in_head = false,
+                _ if in_head => {}
+                Token::Start { name, .. } if name == "br" => text.push('\n'),
+                Token::Start { name, .. } | Token::End(name)
+                    if PARAGRAPH_ELEMENTS.contains(&name.as_str()) =>
+                {
+                    while !text.is_empty() && !text.ends_with("\n\n") {
+                        text.push('\n');
+                    }
+                }
+                Token::Start { name, .. } | Token::End(name)
+                    if LINE_ELEMENTS.contains(&name.as_str())
+                        && !text.is_empty()
+                        && !text.ends_with('\n') =>
+                {
+                    text.push('\n')
+                }
+                Token::Start { name, .. }
+                    if (name == "td" || name == "th") && !text.ends_with('\n') =>
+                {
+                    text.push('\t')
+                }
+                Token::Text(s) => push_collapsed(&mut text, &decode_entities(s)),
+                _ => {}
+            }
+        }
+
+        let mut lines: Vec<&str> = Vec::new();
+        for line in text.lines().map(|line| line.trim_matches([' ', '\t'])) {
+            if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(line);
+            }
+        }
+        lines.join("\n").trim().to_string()
+    }
+
+    /// Extracts every `<table>` in document order as rows of cell text. Cells spanning
+    /// several columns or rows repeat their text across the span. Nested tables are
+    /// returned as tables of their own, and their text also appears in the cell that
+    /// holds them.
+    pub fn tables(html: &str) -> Vec<Vec<Vec<String>>> {
+        let mut tables: Vec<Option<Vec<Vec<String>>>> = Vec::new();
+        let mut open: Vec<(usize, TableBuilder)> = Vec::new();
+
+        for token in tokens(html) {
+            if let Token::Start { name, .. } | Token::End(name) = &token
+                && matches!(name.as_str(), "table" | "tr" | "td" | "th" | "br")
+            {
+                // Keeps the cells of a nested table apart in the cell that holds it
+                push_text(&mut open, " ");
+            }
+
+            match token {
+                Token::Start { name, attrs } => match name.as_str() {
+                    "table" => {
+                        tables.push(None);
+                        open.push((tables.len() - 1, TableBuilder::default()));
+                    }
+                    "tr" => {
+                        if let Some((_, table)) = open.last_mut() {
+                            table.start_row();
+                        }
+                    }
+                    "td" | "th" => {
+                        if let Some((_, table)) = open.last_mut() {
+                            table.start_cell(span(&COLSPAN, attrs), span(&ROWSPAN, attrs));
+                        }
+                    }
+                    _ => {}
+                },
+                Token::End(name) => match name.as_str() {
+                    "table" => {
+                        if let Some((idx, table)) = open.pop() {
+                            tables[idx] = Some(table.finish());
+                        }
+                    }
+                    "tr" => {
+                        if let Some((_, table)) = open.last_mut() {
+                            table.end_row();
+                        }
+                    }
+                    "td" | "th" => {
+                        if let Some((_, table)) = open.last_mut() {
+                            table.end_cell();
+                        }
+                    }
+                    _ => {}
+                },
+                Token::Text(s) => push_text(&mut open, &decode_entities(s)),
+            }
+        }
+
+        // Tables left open at the end of the body
+        while let Some((idx, table)) = open.pop() {
+            tables[idx] = Some(table.finish());
+        }
+        tables.into_iter().flatten().collect()
+    }
+
+    /// The first capture group of `pattern` in `text`, or the whole match when the
+    /// pattern has no groups
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex
+    pub fn field(text: &str, pattern: &str) -> Result<Option<String>> {
+        let regex = Regex::new(pattern).with_context(|| format!("Invalid regex {}", pattern))?;
+        Ok(regex.captures(text).and_then(|captures| {
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|m| m.as_str().trim().to_string())
+        }))
+    }
+
+    /// Every amount in `text`, in order. Currency symbols and codes are ignored, a
+    /// leading `-` or surrounding parentheses make the amount negative, and the decimal
+    /// separator is worked out from the position of `.` and `,`.
+    pub fn amounts(text: &str) -> Vec<Decimal> {
+        AMOUNT
+            .captures_iter(text)
+            .filter_map(|captures| parse_amount(&captures))
+            .collect()
+    }
+
+    /// The first amount after a label matching `label`, ignoring case, as in
+    /// `EmailBody::amount(text, "total( due)?")` for `Total due: $1,234.50`. The amount
+    /// must be on the same line as the label.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `label` is not a valid regex
+    pub fn amount(text: &str, label: &str) -> Result<Option<Decimal>> {
+        let regex = amount_regex(label).with_context(|| format!("Invalid regex {}", label))?;
+        Ok(regex
+            .captures_iter(text)
+            .find_map(|captures| parse_amount(&captures)))
+    }
+
+    /// Every date in `text`, in order. ISO dates (`2024-03-05`), dates with a month name
+    /// (`5 March 2024`, `Mar 5, 2024`) and dotted day first dates (`05.03.2024`) are
+    /// found. Dates with slashes are skipped since the order of the day and month is
+    /// ambiguous.
+    pub fn dates(text: &str) -> Vec<NaiveDate> {
+        let mut dates: Vec<(usize, NaiveDate)> = Vec::new();
+        let mut push = |regex: &Regex, to_date: &dyn Fn(&[&str]) -> Option<NaiveDate>| {
+            for captures in regex.captures_iter(text) {
+                let parts: Vec<&str> = captures
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map_or("", |m| m.as_str()))
+                    .collect();
+                let start = captures.get(0).map_or(0, |m| m.start());
+                if let Some(date) = to_date(&parts)
+                    && !dates.iter().any(|(idx, _)| *idx == start)
+                {
+                    dates.push((start, date));
+                }
+            }
+        };
+
+        push(&ISO_DATE, &|p| ymd(p[0], p[1], p[2]));
+        push(&DOTTED_DATE, &|p| ymd(p[2], p[1], p[0]));
+        push(&DAY_MONTH_DATE, &|p| ymd(p[2], &month(p[1])?, p[0]));
+        push(&MONTH_DAY_DATE, &|p| ymd(p[2], &month(p[0])?, p[1]));
+
+        dates.sort_by_key(|(idx, _)| *idx);
+        dates.into_iter().map(|(_, date)| date).collect()
+    }
+
+    /// The first date after a label matching `label`, ignoring case, on the same line or
+    /// the line after. See [`EmailBody::dates`] for the formats found.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `label` is not a valid regex
+    pub fn date(text: &str, label: &str) -> Result<Option<NaiveDate>> {
+        let regex = Regex::new(&format!("(?i){}", label))
+            .with_context(|| format!("Invalid regex {}", label))?;
+        Ok(regex.find_iter(text).find_map(|m| {
+            let rest = &text[m.end()..];
+            let end = rest
+                .match_indices('\n')
+                .nth(1)
+                .map_or(rest.len(), |(idx, _)| idx);
+            Self::dates(&rest[..end]).into_iter().next()
+        }))
+    }
+}
+
+impl Email {
+    /// Whether the body is HTML
+    pub fn is_html(&self) -> bool {
+        EmailBody::is_html(&self.body)
+    }
+
+    /// The body as plain text, see [`EmailBody::to_text`]
+    pub fn text(&self) -> String {
+        EmailBody::to_text(&self.body)
+    }
+
+    /// The tables in an HTML body, see [`EmailBody::tables`]
+    pub fn tables(&self) -> Vec<Vec<Vec<String>>> {
+        EmailBody::tables(&self.body)
+    }
+}
+
+/// An amount, optionally after a label, with the sign, parenthesis and number captured
+fn amount_regex(label: &str) -> Result<Regex, regex::Error> {
+    let prefix = if label.is_empty() {
+        String::new()
+    } else {
+        format!(r"(?i:{})[^\d\n(-]*?", label)
+    };
+    Regex::new(&format!(
+        r"{}(?P<sign>-\s*)?(?P<open>\()?\s*(?:[$€£¥]|[A-Z]{{3}}\b)?\s*(?P<sign2>-)?(?P<number>{})(?:\s*(?:[$€£¥]|[A-Z]{{3}}\b))?\s*(?P<close>\))?",
+        prefix, NUMBER
+    ))
+}
+
+fn parse_amount(captures: &regex::Captures) -> Option<Decimal> {
+    let number = captures.name("number")?.as_str();
+    // In prose a dot followed by three digits, as in `1.234`, groups thousands
+    let amount = match (number.rfind('.'), number.contains(',')) {
+        (Some(dot), false) if number.matches('.').count() > 1 || number.len() - dot - 1 == 3 => {
+            parse_decimal(&number.replace('.', ""))?
+        }
+        _ => parse_decimal(number)?,
+    };
+    let negative = captures.name("sign").is_some()
+        || captures.name("sign2").is_some()
+        || (captures.name("open").is_some() && captures.name("close").is_some());
+    Some(if negative { -amount } else { amount })
+}
+
+fn ymd(year: &str, month: &str, day: &str) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// The month number of an English month name or abbreviation, as in `Mar`, `Sept` or
+/// `March`
+fn month(name: &str) -> Option<String> {
+    let lower = name.to_ascii_lowercase();
+    let idx = MONTHS
+        .iter()
+        .position(|month| lower.len() >= 3 && month.starts_with(&lower))?;
+    Some((idx + 1).to_string())
+}
+
+enum Token<'a> {
+    Start { name: String, attrs: &'a str },
+    End(String),
+    Text(&'a str),
+}
+
+/// Splits HTML into tags and text. Comments, doctypes and the contents of `<script>`
+/// and `<style>` are skipped. Element names are lowercased.
+fn tokens(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        if lt > 0 {
+            tokens.push(Token::Text(&rest[..lt]));
+        }
+        rest = &rest[lt..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let name_start = if closing { 2 } else { 1 };
+        let name_len = rest[name_start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - name_start);
+        if name_len == 0 {
+            // A `<` that does not start a tag
+            tokens.push(Token::Text(&rest[..1]));
+            rest = &rest[1..];
+            continue;
+        }
+        let name = rest[name_start..name_start + name_len].to_ascii_lowercase();
+
+        let end = tag_end(rest).unwrap_or(rest.len());
+        let attrs = rest[name_start + name_len..end].trim_end_matches(['>', '/']);
+        rest = &rest[end..];
+
+        if closing {
+            tokens.push(Token::End(name));
+        } else if name == "script" || name == "style" {
+            let lower = rest.to_ascii_lowercase();
+            rest = lower
+                .find(&format!("</{}", name))
+                .map_or("", |idx| &rest[idx..]);
+        } else {
+            tokens.push(Token::Start { name, attrs });
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// The index just after the `>` that ends the tag at the start of `s`, skipping `>`
+/// inside quoted attribute values
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (idx, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(idx + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn span(regex: &Regex, attrs: &str) -> usize {
+    regex
+        .captures(attrs)
+        .and_then(|captures| captures[1].parse().ok())
+        .unwrap_or(1)
+        .clamp(1, 1000)
+}
+
+/// Appends text with runs of whitespace collapsed to single spaces
+fn push_collapsed(out: &mut String, s: &str) {
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !out.ends_with([' ', '\n', '\t']) && !out.is_empty() {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+/// Adds text to the open cell of every open table, so nested tables also show up in
+/// the cell that holds them
+fn push_text(open: &mut [(usize, TableBuilder)], text: &str) {
+    for (_, table) in open {
+        if let Some(cell) = &mut table.cell {
+            push_collapsed(&mut cell.text, text);
+        }
+    }
+}
+
+#[derive(Default)]
+struct TableBuilder {
+    rows: Vec<Vec<Cell>>,
+    row: Option<Vec<Cell>>,
+    cell: Option<Cell>,
+}
+
+struct Cell {
+    text: String,
+    colspan: usize,
+    rowspan: usize,
+}
+
+impl TableBuilder {
+    fn start_row(&mut self) {
+        self.end_row();
+        self.row = Some(Vec::new());
+    }
+
+    fn end_row(&mut self) {
+        self.end_cell();
+        if let Some(row) = self.row.take()
+            && !row.is_empty()
+        {
+            self.rows.push(row);
+        }
+    }
+
+    fn start_cell(&mut self, colspan: usize, rowspan: usize) {
+        self.end_cell();
+        self.cell = Some(Cell {
+            text: String::new(),
+            colspan,
+            rowspan,
+        });
+    }
+
+    fn end_cell(&mut self) {
+        if let Some(mut cell) = self.cell.take() {
+            cell.text = cell.text.trim().to_string();
+            // Cells outside a `<tr>` start a row of their own
+            self.row.get_or_insert_with(Vec::new).push(cell);
+        }
+    }
+
+    /// Lays the cells out on a grid, repeating spanning cells
+    fn finish(mut self) -> Vec<Vec<String>> {
+        self.end_row();
+
+        let mut grid: Vec<Vec<Option<String>>> = vec![Vec::new(); self.rows.len()];
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            let mut col = 0;
+            for cell in row {
+                while grid[row_idx].get(col).is_some_and(Option::is_some) {
+                    col += 1;
+                }
+                let last_row = (row_idx + cell.rowspan).min(grid.len());
+                for grid_row in &mut grid[row_idx..last_row] {
+                    if grid_row.len() < col + cell.colspan {
+                        grid_row.resize(col + cell.colspan, None);
+                    }
+                    for slot in &mut grid_row[col..col + cell.colspan] {
+                        *slot = Some(cell.text.clone());
+                    }
+                }
+                col += cell.colspan;
+            }
+        }
+
+        grid.into_iter()
+            .map(|row| row.into_iter().map(Option::unwrap_or_default).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const RECEIPT: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Receipt</title><style>td { color: red; }</style></head>
+<body>
+  <!-- header -->
+  <p>Thanks for your order &amp; welcome!</p>
+  <p>Order date: March 5th, 2024</p>
+  <table class="items">
+    <tr><th>Item</th><th>Qty</th><th>Price</th></tr>
+    <tr><td>Coffee <b>beans</b></td><td>2</td><td>&euro;12,50</td></tr>
+    <tr><td rowspan="2">Filter</td><td>1</td><td>&euro;3,00</td></tr>
+    <tr><td>1</td><td>&euro;3,00</td></tr>
+    <tr><td colspan=2>Total</td><td>&euro;31,00</td></tr>
+  </table>
+  <script>var x = "<table>";</script>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_to_text() {
+        assert!(EmailBody::is_html(RECEIPT));
+        assert!(!EmailBody::is_html("Total: 3 < 4"));
+        assert_eq!(EmailBody::to_text("plain\n\ntext"), "plain\n\ntext");
+
+        let text = EmailBody::to_text(RECEIPT);
+        assert_eq!(
+            text,
+            "Thanks for your order & welcome!\n\nOrder date: March 5th, 2024\n\nItem\tQty\tPrice\nCoffee beans\t2\t€12,50\nFilter\t1\t€3,00\n1\t€3,00\nTotal\t€31,00"
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let tables = EmailBody::tables(RECEIPT);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(
+            tables[0],
+            vec![
+                vec!["Item", "Qty", "Price"],
+                vec!["Coffee beans", "2", "€12,50"],
+                vec!["Filter", "1", "€3,00"],
+                vec!["Filter", "1", "€3,00"],
+                vec!["Total", "Total", "€31,00"],
+            ]
+        );
+
+        let nested = EmailBody::tables(
+            "<table><tr><td>Layout<table><tr><td>a<td>b</table></td></tr></table>",
+        );
+        assert_eq!(nested, vec![vec![vec!["Layout a b"]], vec![vec!["a", "b"]]]);
+    }
+
+    #[test]
+    fn test_amounts() {
+        assert_eq!(
+            EmailBody::amounts("Paid $1,234.56, refunded (12.00) and -€3,50 of EUR 1.234,00"),
+            vec![dec!(1234.56), dec!(-12.00), dec!(-3.50), dec!(1234.00)]
+        );
+
+        let text = EmailBody::to_text(RECEIPT);
+        assert_eq!(
+            EmailBody::amount(&text, "total").unwrap(),
+            Some(dec!(31.00))
+        );
+        assert_eq!(EmailBody::amount(&text, "missing").unwrap(), None);
+        assert_eq!(
+            EmailBody::amount("Balance due: 1 234,5 CHF", "balance( due)?").unwrap(),
+            Some(dec!(1234.5))
+        );
+        assert!(EmailBody::amount("", "(").is_err());
+    }
+
+    #[test]
+    fn test_dates_and_fields() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(
+            EmailBody::dates(
+                "2024-03-05, 6 Mar 2024, Sept 7, 2024 and 08.03.2024 but not 03/09/2024"
+            ),
+            vec![
+                date(2024, 3, 5),
+                date(2024, 3, 6),
+                date(2024, 9, 7),
+                date(2024, 3, 8)
+            ]
+        );
+
+        let text = EmailBody::to_text(RECEIPT);
+        assert_eq!(
+            EmailBody::date(&text, "order date").unwrap(),
+            Some(date(2024, 3, 5))
+        );
+        assert_eq!(
+            EmailBody::date("Settlement date:\n2024-04-01", "settlement date").unwrap(),
+            Some(date(2024, 4, 1))
+        );
+
+        assert_eq!(
+            EmailBody::field("Order #A-1234 confirmed", r"Order #(\S+)").unwrap(),
+            Some("A-1234".to_string())
+        );
+        assert_eq!(EmailBody::field("none", r"\d+").unwrap(), None);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fmt, panic::PanicHookInfo, sync::Once};
+
+use serde::{Deserialize, Serialize};
+
+/// An error a handler can fail with, so the host can tell what went wrong and react,
+/// for example by asking the user to reconnect or by retrying later.
+///
+/// Handlers return it through `FnResult` like any other error:
+///
+/// ```
+/// use contour_rust_pdk::{FnResult, error::PluginError, response::ExtractResponse};
+///
+/// fn check_status(status: u16) -> FnResult<Option<ExtractResponse>> {
+///     if status == 401 {
+///         return Err(PluginError::auth("The API key was rejected").into());
+///     }
+///     Ok(None)
+/// }
+/// ```
+///
+/// The generated exports report it as JSON tagged with `code`, such as
+/// `{"code":"rate_limited","message":"Too many requests","retry_after":30}`, and exit
+/// with [`PluginError::exit_code`]. Any other error is reported as `Internal`. A status
+/// given with `WithReturnCode::new`, other than the default of -1, is kept as the exit
+/// code, while the error is still reported as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum PluginError {
+    /// Credentials are missing, expired or rejected
+    Auth { message: String },
+    /// The upstream API is rate limiting us. `retry_after` is in seconds.
+    RateLimited {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+    /// The upstream API is down or timing out
+    UpstreamUnavailable { message: String },
+    /// The command or data given to the plugin is invalid
+    InvalidInput { message: String },
+    /// The plugin's config is missing or invalid
+    Config { message: String },
+    /// A bug in the plugin or anything else unexpected
+    Internal { message: String },
+    /// The handler panicked. `location` is the file, line and column of the panic.
+    Panic {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        location: Option<String>,
+    },
+}
+
+impl PluginError {
+    /// The exit code of [`PluginError::Panic`]
+    pub const PANIC_EXIT_CODE: i32 = 7;
+
+    pub fn auth(message: impl Into<String>) -> Self {
+        Self::Auth {
+            message: message.into(),
+        }
+    }
+
+    pub fn rate_limited(message: impl Into<String>, retry_after: Option<u64>) -> Self {
+        Self::RateLimited {
+            message: message.into(),
+            retry_after,
+        }
+    }
+
+    pub fn upstream_unavailable(message: impl Into<String>) -> Self {
+        Self::UpstreamUnavailable {
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config {
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::Internal {
+            message: message.into(),
+        }
+    }
+
+    /// Describes a panic from the hook's info
+    pub fn from_panic(info: &PanicHookInfo) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Handler panicked".to_string());
+        Self::Panic {
+            message,
+            location: info.location().map(|location| location.to_string()),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Auth { message }
+            | Self::RateLimited { message, .. }
+            | Self::UpstreamUnavailable { message }
+            | Self::InvalidInput { message }
+            | Self::Config { message }
+            | Self::Internal { message }
+            | Self::Panic { message, .. } => message,
+        }
+    }
+
+    fn message_mut(&mut self) -> &mut String {
+        match self {
+            Self::Auth { message }
+            | Self::RateLimited { message, .. }
+            | Self::UpstreamUnavailable { message }
+            | Self::InvalidInput { message }
+            | Self::Config { message }
+            | Self::Internal { message }
+            | Self::Panic { message, .. } => message,
+        }
+    }
+
+    /// The exit code of the generated export. These never change, so hosts can rely on
+    /// them.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Internal { .. } => 1,
+            Self::InvalidInput { .. } => 2,
+            Self::Config { .. } => 3,
+            Self::Auth { .. } => 4,
+            Self::RateLimited { .. } => 5,
+            Self::UpstreamUnavailable { .. } => 6,
+            Self::Panic { .. } => Self::PANIC_EXIT_CODE,
+        }
+    }
+
+    /// The JSON reported to the host
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .unwrap_or_else(|_| format!(r#"{{"code":"internal","message":{:?}}}"#, self.message()))
+    }
+}
+
+/// Installs a panic hook that passes panics to `report` as [`PluginError::Panic`],
+/// before running the hook that was already installed. The generated exports call this
+/// so that a panicking handler is reported like any other error, even where the panic
+/// then aborts the instance instead of unwinding. Only the first call has an effect.
+pub fn install_panic_hook(report: fn(PluginError) -> i32) {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            report(PluginError::from_panic(info));
+            previous(info);
+        }));
+    });
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+/// Finds a `PluginError` anywhere in the error's chain, so context added with
+/// `.context(...)` keeps the error's code. The message includes that context. Errors
+/// without one become `Internal`.
+impl From<anyhow::Error> for PluginError {
+    fn from(error: anyhow::Error) -> Self {
+        let message = format!("{:#}", error);
+        match error.chain().find_map(|e| e.downcast_ref::<PluginError>()) {
+            Some(plugin_error) => {
+                let mut plugin_error = plugin_error.clone();
+                *plugin_error.message_mut() = message;
+                plugin_error
+            }
+            None => Self::internal(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, anyhow};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let error = PluginError::rate_limited("Too many requests", Some(30));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&error.to_json()).unwrap(),
+            json!({ "code": "rate_limited", "message": "Too many requests", "retry_after": 30 })
+        );
+        assert_eq!(error.exit_code(), 5);
+
+        let error = PluginError::upstream_unavailable("Bank API timed out");
+        assert_eq!(
+            error.to_json(),
+            r#"{"code":"upstream_unavailable","message":"Bank API timed out"}"#
+        );
+    }
+
+    #[test]
+    fn test_from_anyhow() {
+        let error: anyhow::Error = PluginError::auth("Token expired").into();
+        let error = PluginError::from(error.context("Fetching accounts"));
+        assert_eq!(error, PluginError::auth("Fetching accounts: Token expired"));
+
+        let error = Err::<(), _>(anyhow!("Index out of range"))
+            .context("Parsing statement")
+            .unwrap_err();
+        assert_eq!(
+            PluginError::from(error),
+            PluginError::internal("Parsing statement: Index out of range")
+        );
+    }
+}
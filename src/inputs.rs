@@ -1,6 +1,6 @@
 use std::{collections::HashMap, fmt::Debug, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use chrono::{DateTime, NaiveDate, Utc};
 use chrono_tz::Tz;
 use rust_decimal::Decimal;
@@ -135,6 +135,42 @@ impl EntryInput {
             lines,
         }
     }
+
+    /// Checks the entry can be posted: it has a source key and lines, no line has a
+    /// negative debit or credit, and debits balance credits
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first problem found
+    pub fn validate(&self) -> Result<()> {
+        if self.source_key.is_empty() {
+            bail!("Entry has no source_key");
+        }
+        if self.lines.is_empty() {
+            bail!("Entry {} has no lines", self.source_key);
+        }
+        for (idx, line) in self.lines.iter().enumerate() {
+            if line.debit.is_sign_negative() || line.credit.is_sign_negative() {
+                bail!(
+                    "Line {} of entry {} has a negative debit or credit",
+                    idx + 1,
+                    self.source_key
+                );
+            }
+        }
+
+        let debits: Decimal = self.lines.iter().map(|line| line.debit).sum();
+        let credits: Decimal = self.lines.iter().map(|line| line.credit).sum();
+        if debits != credits {
+            bail!(
+                "Entry {} does not balance: debits are {} and credits are {}",
+                self.source_key,
+                debits,
+                credits
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -168,6 +204,35 @@ impl LineInput {
             parent_line_id: None,
         }
     }
+
+    /// The two lines of a movement of `amount` into `account`, balanced against
+    /// `offset`. A negative amount moves out of `account`. Both lines carry the
+    /// description and tags.
+    pub fn transfer(
+        account: ResourceSelector,
+        offset: ResourceSelector,
+        amount: Decimal,
+        description: Option<String>,
+        tags: Vec<TagSelector>,
+    ) -> Vec<LineInput> {
+        let (debit, credit) = if amount.is_sign_negative() {
+            (Decimal::ZERO, amount.abs())
+        } else {
+            (amount, Decimal::ZERO)
+        };
+
+        vec![
+            LineInput::new(
+                account,
+                debit,
+                credit,
+                Decimal::ONE,
+                description.clone(),
+                tags.clone(),
+            ),
+            LineInput::new(offset, credit, debit, Decimal::ONE, description, tags),
+        ]
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -364,6 +429,8 @@ pub struct RecordHistoryDelete {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HandlerInput<C> {
+    /// Empty when sent by hosts that predate it
+    #[serde(default)]
     pub command_type: String,
     pub command: C,
 }
@@ -409,4 +476,34 @@ mod tests {
 
         assert_eq!(get_type(&input_resource), "TestStruct");
     }
+
+    #[test]
+    fn test_validate_entry() {
+        let lines = LineInput::transfer(
+            ResourceSelector::Id(Uuid::nil()),
+            ResourceSelector::Id(Uuid::max()),
+            Decimal::new(-1250, 2),
+            None,
+            vec![],
+        );
+        let mut entry = EntryInput::new(
+            Effective::Date(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()),
+            "txn-1".to_string(),
+            "Transaction".to_string(),
+            lines,
+        );
+        entry.validate().unwrap();
+
+        entry.lines[0].credit = Decimal::new(1200, 2);
+        assert_eq!(
+            entry.validate().unwrap_err().to_string(),
+            "Entry txn-1 does not balance: debits are 12.50 and credits are 12.00"
+        );
+
+        entry.lines.clear();
+        assert_eq!(
+            entry.validate().unwrap_err().to_string(),
+            "Entry txn-1 has no lines"
+        );
+    }
 }
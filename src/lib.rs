@@ -1,11 +1,22 @@
 #![allow(improper_ctypes_definitions)]
 #![allow(improper_ctypes)]
 
+pub mod archive;
+pub mod camt;
 pub mod command;
 pub mod csv;
+pub mod email_body;
+pub mod error;
 pub mod inputs;
 pub mod models;
+pub mod mt940;
+pub mod ofx;
+pub mod pdf;
+pub mod qif;
 pub mod response;
+pub mod spreadsheet;
+pub mod statement;
+mod text;
 
 #[cfg(not(target_arch = "wasm32"))]
 use host_fns::*;
@@ -17,7 +28,7 @@ pub use rust_decimal_macros::dec;
 use serde::{Serialize, de::DeserializeOwned};
 
 use crate::inputs::{RecordHistoryDelete, RecordHistoryInput, RequestInput, TimezoneInput};
-pub use contour_rust_pdk_macros::{extract_fn, transform_fn};
+pub use contour_rust_pdk_macros::{extract_fn, listener_fn, transform_fn};
 
 #[cfg(target_arch = "wasm32")]
 #[extism_pdk::host_fn]
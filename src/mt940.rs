@@ -0,0 +1,843 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
+use chrono::{Datelike, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+
+use crate::{
+    inputs::{
+        Effective, EntryInput, LineInput, MeasurementInput, ObservationInput, ResourceSelector,
+        TagSelector,
+    },
+    statement::{self, COUNTERPARTY_TAG_TYPE, REMITTANCE_TAG_TYPE},
+};
+
+/// A parser for SWIFT MT940 customer statements and MT942 interim transaction reports.
+///
+/// Files may hold several messages, either wrapped in SWIFT `{1:...}{2:...}{4:` blocks
+/// or separated by `-` lines. Bytes that are not UTF-8 are read as Latin-1.
+pub struct Mt940;
+
+impl Mt940 {
+    /// Parses every statement in an MT940 or MT942 file. Each statement starts at its
+    /// `:20:` field.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is text before the first field, a statement has no
+    /// `:25:` account, or a balance or `:61:` line is malformed
+    pub fn parse(bytes: &[u8]) -> Result<Vec<Mt940Statement>> {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => bytes.iter().map(|&b| b as char).collect(),
+        };
+
+        let mut messages: Vec<Vec<(String, String)>> = Vec::new();
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in text.lines() {
+            let mut line = line.trim_end();
+            if line.starts_with('{') {
+                // Only the text block `{4:` holds fields, the rest are SWIFT headers
+                match line.find("{4:") {
+                    Some(idx) => line = &line[idx + 3..],
+                    None => continue,
+                }
+            }
+            if line.trim() == "-" || line.starts_with("-}") {
+                messages.extend((!fields.is_empty()).then(|| std::mem::take(&mut fields)));
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match Self::field_tag(line) {
+                Some((tag, value)) => {
+                    if tag == "20" && fields.iter().any(|(tag, _)| tag == "20") {
+                        messages.push(std::mem::take(&mut fields));
+                    }
+                    fields.push((tag.to_string(), value.to_string()));
+                }
+                None => match fields.last_mut() {
+                    Some((_, value)) => {
+                        value.push('\n');
+                        value.push_str(line);
+                    }
+                    None => bail!("MT940 text before the first field: {}", line),
+                },
+            }
+        }
+        messages.extend((!fields.is_empty()).then_some(fields));
+
+        messages
+            .into_iter()
+            .map(|fields| Mt940Statement::from_fields(&fields))
+            .collect()
+    }
+
+    /// Splits `:61:value` into its tag and value
+    fn field_tag(line: &str) -> Option<(&str, &str)> {
+        let rest = line.strip_prefix(':')?;
+        let end = rest.find(':')?;
+        let tag = &rest[..end];
+        let valid = (2..=3).contains(&tag.len())
+            && tag.split_at_checked(2).is_some_and(|(number, letter)| {
+                number.bytes().all(|b| b.is_ascii_digit())
+                    && letter.bytes().all(|b| b.is_ascii_uppercase())
+            });
+        valid.then(|| (tag, &rest[end + 1..]))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mt940Statement {
+    /// Transaction reference number (`:20:`)
+    pub reference: String,
+    /// Related reference (`:21:`)
+    pub related_reference: Option<String>,
+    /// Account identification (`:25:`), often `BLZ/account` or an IBAN
+    pub account: String,
+    /// Statement and sequence number (`:28C:`)
+    pub statement_number: Option<String>,
+    /// When an MT942 report was produced (`:13D:`)
+    pub created: Option<Effective>,
+    /// `:60F:` or `:60M:`
+    pub opening_balance: Option<Mt940Balance>,
+    /// `:62F:` or `:62M:`
+    pub closing_balance: Option<Mt940Balance>,
+    /// Closing available balance (`:64:`)
+    pub available_balance: Option<Mt940Balance>,
+    /// Forward available balances (`:65:`)
+    pub forward_balances: Vec<Mt940Balance>,
+    pub transactions: Vec<Mt940Transaction>,
+    /// Information to account owner (`:86:`) that follows the last balance rather than a
+    /// `:61:` line
+    pub information: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mt940Balance {
+    /// Negative for a debit balance
+    pub amount: Decimal,
+    pub currency: String,
+    pub date: NaiveDate,
+    /// Set for the `M` (intermediate) variants of `:60:` and `:62:` used when a
+    /// statement is split over several messages
+    pub intermediate: bool,
+}
+
+/// A statement line (`:61:`) with its information to account owner (`:86:`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mt940Transaction {
+    pub value_date: NaiveDate,
+    /// Booking date. Only the month and day are sent, so the year is taken from the
+    /// value date.
+    pub entry_date: Option<NaiveDate>,
+    /// Positive for credits to the account, negative for debits
+    pub amount: Decimal,
+    /// Set for `RC` and `RD` lines, which reverse an earlier credit or debit
+    pub reversal: bool,
+    /// Third letter of the currency code, when sent
+    pub funds_code: Option<char>,
+    /// Transaction type identification code, for example `NTRF` or `NCHK`
+    pub transaction_type: String,
+    /// Reference for the account owner. `NONREF` when there is none.
+    pub customer_reference: String,
+    /// Reference of the account servicing institution (after `//`)
+    pub bank_reference: Option<String>,
+    /// Supplementary details on the line after the `:61:` field
+    pub supplementary_details: Option<String>,
+    pub narrative: Option<Mt940Narrative>,
+    /// The bank reference, then the customer reference, then the statement reference
+    /// and the position of the line in the statement
+    pub source_key: String,
+}
+
+/// Which date of a statement line becomes its [`Effective`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mt940Date {
+    /// The entry date, falling back to the value date when it is not sent
+    Entry,
+    Value,
+}
+
+/// The `:86:` field of a statement line.
+///
+/// Two common bank-specific layouts are split into subfields:
+///
+/// * The German DFÜ layout, `GVC?00posting text?20purpose...?32name`, keyed by the two
+///   digit subfield number with the business transaction code under `GVC`
+/// * The slash layout used by Dutch and Belgian banks, `/NAME/.../REMI/.../`, keyed by
+///   the code between slashes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mt940Narrative {
+    /// The field as sent, with line breaks
+    pub text: String,
+    pub subfields: BTreeMap<String, String>,
+}
+
+impl Mt940Statement {
+    fn from_fields(fields: &[(String, String)]) -> Result<Self> {
+        let mut statement = Self {
+            reference: String::new(),
+            related_reference: None,
+            account: String::new(),
+            statement_number: None,
+            created: None,
+            opening_balance: None,
+            closing_balance: None,
+            available_balance: None,
+            forward_balances: Vec::new(),
+            transactions: Vec::new(),
+            information: None,
+        };
+
+        for (tag, value) in fields {
+            let trimmed = value.trim().to_string();
+            match tag.as_str() {
+                "20" => statement.reference = trimmed,
+                "21" => statement.related_reference = Some(trimmed),
+                "25" => statement.account = trimmed,
+                "28C" | "28" => statement.statement_number = Some(trimmed),
+                "13D" | "13" => statement.created = Some(parse_datetime(&trimmed)?),
+                "60F" | "60M" => {
+                    statement.opening_balance = Some(parse_balance(&trimmed, tag == "60M")?)
+                }
+                "62F" | "62M" => {
+                    statement.closing_balance = Some(parse_balance(&trimmed, tag == "62M")?)
+                }
+                "64" => statement.available_balance = Some(parse_balance(&trimmed, false)?),
+                "65" => statement
+                    .forward_balances
+                    .push(parse_balance(&trimmed, false)?),
+                "61" => {
+                    let position = statement.transactions.len() + 1;
+                    let fallback = format!("{}/{}", statement.reference, position);
+                    statement
+                        .transactions
+                        .push(Mt940Transaction::parse(value, fallback)?);
+                }
+                "86" => match statement.transactions.last_mut() {
+                    Some(transaction)
+                        if transaction.narrative.is_none()
+                            && statement.closing_balance.is_none() =>
+                    {
+                        transaction.narrative = Some(Mt940Narrative::parse(value))
+                    }
+                    _ => statement.information = Some(value.clone()),
+                },
+                // MT942 floor limits and debit/credit totals, and anything bank specific
+                _ => {}
+            }
+        }
+
+        if statement.account.is_empty() {
+            bail!(
+                "MT940 statement {} has no :25: account",
+                statement.reference
+            );
+        }
+        Ok(statement)
+    }
+
+    /// The opening, closing and available balances as observations to reconcile the
+    /// account against. Each observation has one measurement whose
+    /// `measurement_identifier` is the currency and whose `measurement_type` is
+    /// `OpeningBalance`, `ClosingBalance`, `AvailableBalance` or `ForwardBalance`.
+    pub fn balance_observations(&self, observation_type: String) -> Vec<ObservationInput> {
+        let balances = [
+            ("OpeningBalance", self.opening_balance.as_ref()),
+            ("ClosingBalance", self.closing_balance.as_ref()),
+            ("AvailableBalance", self.available_balance.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(kind, balance)| Some((kind, balance?)))
+        .chain(
+            self.forward_balances
+                .iter()
+                .map(|balance| ("ForwardBalance", balance)),
+        );
+
+        balances
+            .map(|(kind, balance)| ObservationInput {
+                effective: Effective::Date(balance.date),
+                source_key: format!("{}/{}/{}", self.account, balance.date, kind),
+                observation_type: observation_type.clone(),
+                measurements: vec![MeasurementInput {
+                    measurement: Some(balance.amount),
+                    description: self.statement_number.clone(),
+                    measurement_type: kind.to_string(),
+                    measurement_identifier: Some(balance.currency.clone()),
+                    tags: Vec::new(),
+                }],
+            })
+            .collect()
+    }
+}
+
+impl Mt940Transaction {
+    /// Parses `YYMMDD[MMDD]{C|D|RC|RD}[funds code]amount{type}{customer ref}[//bank
+    /// ref][\nsupplementary details]`
+    fn parse(value: &str, fallback_key: String) -> Result<Self> {
+        let (line, supplementary_details) = match value.split_once('\n') {
+            Some((line, details)) => (line.trim(), Some(details.trim().replace('\n', " "))),
+            None => (value.trim(), None),
+        };
+        let invalid = || anyhow!("Invalid MT940 statement line {}", line);
+
+        let value_date = parse_date(line.get(..6).ok_or_else(invalid)?)?;
+        let mut rest = &line[6..];
+
+        let entry_date = match rest.get(..4) {
+            Some(mmdd) if mmdd.bytes().all(|b| b.is_ascii_digit()) => {
+                rest = &rest[4..];
+                Some(entry_date(value_date, mmdd).ok_or_else(invalid)?)
+            }
+            _ => None,
+        };
+
+        // A reversal of a credit is a debit to the account, and the other way round
+        let (credit, reversal) = if let Some(r) = rest.strip_prefix("RC") {
+            rest = r;
+            (false, true)
+        } else if let Some(r) = rest.strip_prefix("RD") {
+            rest = r;
+            (true, true)
+        } else if let Some(r) = rest.strip_prefix('C') {
+            rest = r;
+            (true, false)
+        } else if let Some(r) = rest.strip_prefix('D') {
+            rest = r;
+            (false, false)
+        } else {
+            return Err(invalid());
+        };
+
+        let funds_code = rest.chars().next().filter(char::is_ascii_alphabetic);
+        if funds_code.is_some() {
+            rest = &rest[1..];
+        }
+
+        let amount_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != ',')
+            .ok_or_else(invalid)?;
+        let amount = parse_amount(&rest[..amount_len])?;
+        rest = &rest[amount_len..];
+
+        let transaction_type = rest.get(..4).ok_or_else(invalid)?.to_string();
+        rest = &rest[4..];
+        let (customer_reference, bank_reference) = match rest.split_once("//") {
+            Some((customer, bank)) => (customer, Some(bank.trim().to_string())),
+            None => (rest, None),
+        };
+        let customer_reference = customer_reference.trim().to_string();
+
+        let source_key = bank_reference
+            .clone()
+            .filter(|reference| !reference.is_empty() && reference != "NONREF")
+            .or_else(|| Some(customer_reference.clone()).filter(|r| r != "NONREF" && !r.is_empty()))
+            .unwrap_or(fallback_key);
+
+        Ok(Self {
+            value_date,
+            entry_date,
+            amount: if credit { amount } else { -amount },
+            reversal,
+            funds_code,
+            transaction_type,
+            customer_reference,
+            bank_reference,
+            supplementary_details,
+            narrative: None,
+            source_key,
+        })
+    }
+
+    /// A description from the narrative purpose, falling back to the narrative text and
+    /// then the supplementary details
+    pub fn description(&self) -> Option<String> {
+        self.narrative
+            .as_ref()
+            .and_then(|narrative| {
+                narrative
+                    .purpose()
+                    .or_else(|| Some(narrative.text.replace('\n', " ")))
+            })
+            .or_else(|| self.supplementary_details.clone())
+    }
+
+    /// Tags for the counterparty and remittance information in the narrative
+    pub fn tags(&self) -> Vec<TagSelector> {
+        let Some(narrative) = &self.narrative else {
+            return Vec::new();
+        };
+
+        let mut tags = Vec::new();
+        let name = narrative.counterparty_name();
+        if let Some(key) = narrative.counterparty_account().or(name.clone()) {
+            tags.push(TagSelector::SelectOrCreate {
+                tag_type: COUNTERPARTY_TAG_TYPE.to_string(),
+                source_key: key,
+                name,
+                data_type: None,
+            });
+        }
+        if let Some(purpose) = narrative.purpose() {
+            tags.push(TagSelector::SelectOrCreate {
+                tag_type: REMITTANCE_TAG_TYPE.to_string(),
+                source_key: purpose.clone(),
+                name: Some(purpose),
+                data_type: None,
+            });
+        }
+        tags
+    }
+
+    /// Maps the statement line to an [`EntryInput`] keyed by
+    /// [`Mt940Transaction::source_key`]. The amount moves into `account` and is balanced
+    /// against `offset`, tagged with [`Mt940Transaction::tags`].
+    pub fn to_entry_input(
+        &self,
+        entry_type: String,
+        date: Mt940Date,
+        account: ResourceSelector,
+        offset: ResourceSelector,
+    ) -> EntryInput {
+        let effective = match date {
+            Mt940Date::Entry => self.entry_date.unwrap_or(self.value_date),
+            Mt940Date::Value => self.value_date,
+        };
+
+        EntryInput::new(
+            Effective::Date(effective),
+            self.source_key.clone(),
+            entry_type,
+            LineInput::transfer(
+                account,
+                offset,
+                self.amount,
+                self.description(),
+                self.tags(),
+            ),
+        )
+    }
+}
+
+impl Mt940Narrative {
+    fn parse(text: &str) -> Self {
+        // Subfields run across line breaks, which are only there to fit 65 columns
+        let joined: String = text.lines().map(str::trim_end).collect();
+        let structured = joined.split_at_checked(3).is_some_and(|(code, rest)| {
+            code.bytes().all(|b| b.is_ascii_digit()) && rest.starts_with('?')
+        });
+        let subfields = if structured {
+            Self::question_mark_subfields(&joined)
+        } else if joined.starts_with('/') {
+            Self::slash_subfields(&joined)
+        } else {
+            BTreeMap::new()
+        };
+
+        Self {
+            text: text.trim().to_string(),
+            subfields,
+        }
+    }
+
+    /// Splits `GVC?20...?21...`, where the three digit transaction code is ASCII
+    fn question_mark_subfields(joined: &str) -> BTreeMap<String, String> {
+        let (code, rest) = joined.split_at(3);
+        let mut subfields = BTreeMap::from([("GVC".to_string(), code.to_string())]);
+        for part in rest[1..].split('?') {
+            if part.len() >= 2 && part.is_char_boundary(2) {
+                let (key, value) = part.split_at(2);
+                subfields
+                    .entry(key.to_string())
+                    .or_insert_with(String::new)
+                    .push_str(value);
+            }
+        }
+        subfields
+    }
+
+    fn slash_subfields(joined: &str) -> BTreeMap<String, String> {
+        let is_key =
+            |s: &str| (2..=4).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_uppercase());
+
+        let mut subfields = BTreeMap::new();
+        let mut key: Option<String> = None;
+        let mut value: Option<String> = None;
+        for token in joined.trim_matches('/').split('/') {
+            match (&key, &mut value) {
+                (Some(_), None) => value = Some(token.to_string()),
+                // A token that does not look like a key is a value that contained a slash
+                (Some(_), Some(value)) if !is_key(token) => {
+                    value.push('/');
+                    value.push_str(token);
+                }
+                _ => {
+                    if let (Some(key), Some(value)) = (key.take(), value.take()) {
+                        subfields.insert(key, value);
+                    }
+                    key = Some(token.to_string());
+                }
+            }
+        }
+        if let Some(key) = key {
+            subfields.insert(key, value.unwrap_or_default());
+        }
+        subfields
+    }
+
+    /// The business transaction code (`GVC`) or transaction type (`TRTP`)
+    pub fn transaction_code(&self) -> Option<&str> {
+        self.get("GVC").or_else(|| self.get("TRTP"))
+    }
+
+    /// The posting text (`?00`)
+    pub fn posting_text(&self) -> Option<&str> {
+        self.get("00")
+    }
+
+    /// The purpose lines `?20` to `?29` and `?60` to `?63` joined with spaces, or the
+    /// `REMI` subfield
+    pub fn purpose(&self) -> Option<String> {
+        let lines: Vec<&str> = (20..=29)
+            .chain(60..=63)
+            .filter_map(|n| self.get(&n.to_string()))
+            .collect();
+        if lines.is_empty() {
+            self.get("REMI").map(str::to_string)
+        } else {
+            Some(lines.join(" "))
+        }
+    }
+
+    /// `?32` and `?33`, or the `NAME` subfield
+    pub fn counterparty_name(&self) -> Option<String> {
+        match (self.get("32"), self.get("33")) {
+            (Some(first), Some(second)) => Some(format!("{}{}", first, second)),
+            (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+            (None, None) => self.get("NAME").map(str::to_string),
+        }
+    }
+
+    /// The counterparty IBAN or account number (`?31` or `IBAN`)
+    pub fn counterparty_account(&self) -> Option<String> {
+        self.get("31")
+            .or_else(|| self.get("IBAN"))
+            .map(str::to_string)
+    }
+
+    /// The counterparty bank code or BIC (`?30` or `BIC`)
+    pub fn counterparty_bank(&self) -> Option<&str> {
+        self.get("30").or_else(|| self.get("BIC"))
+    }
+
+    /// A subfield by key, ignoring empty ones
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.subfields
+            .get(key)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// Parses `{C|D}YYMMDDCCCamount`
+fn parse_balance(s: &str, intermediate: bool) -> Result<Mt940Balance> {
+    let invalid = || anyhow!("Invalid MT940 balance {}", s);
+    let sign = match s.get(..1) {
+        Some("C") => Decimal::ONE,
+        Some("D") => Decimal::NEGATIVE_ONE,
+        _ => return Err(invalid()),
+    };
+    let date = parse_date(s.get(1..7).ok_or_else(invalid)?)?;
+    let currency = s.get(7..10).ok_or_else(invalid)?.to_string();
+    let amount = parse_amount(s.get(10..).ok_or_else(invalid)?)?;
+
+    Ok(Mt940Balance {
+        amount: sign * amount,
+        currency,
+        date,
+        intermediate,
+    })
+}
+
+/// Amounts use a decimal comma and no thousands separator
+fn parse_amount(s: &str) -> Result<Decimal> {
+    statement::parse_amount(&s.replace(',', "."), "MT940")
+}
+
+/// Parses `YYMMDD`. Years below 80 are in the 2000s.
+fn parse_date(s: &str) -> Result<NaiveDate> {
+    let invalid = || anyhow!("Invalid MT940 date {}", s);
+    let number = |range: std::ops::Range<usize>| -> Result<u32> {
+        s.get(range)
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(invalid)
+    };
+    let year = number(0..2)? as i32;
+    let year = if year < 80 { 2000 + year } else { 1900 + year };
+    NaiveDate::from_ymd_opt(year, number(2..4)?, number(4..6)?).ok_or_else(invalid)
+}
+
+/// Places an `MMDD` entry date in the year closest to the value date, so a December
+/// booking of a January value date falls in the previous year
+fn entry_date(value_date: NaiveDate, mmdd: &str) -> Option<NaiveDate> {
+    let (month, day) = mmdd.split_at_checked(2)?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+    [
+        value_date.year(),
+        value_date.year() - 1,
+        value_date.year() + 1,
+    ]
+    .into_iter()
+    .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+    .min_by_key(|date| (*date - value_date).num_days().abs())
+}
+
+/// Parses an MT942 `:13D:` date time, `YYMMDDHHMM+HHMM`
+fn parse_datetime(s: &str) -> Result<Effective> {
+    let invalid = || anyhow!("Invalid MT942 date time {}", s);
+    let (local, offset) = s.split_at_checked(10).ok_or_else(invalid)?;
+    let local = NaiveDateTime::parse_from_str(local, "%y%m%d%H%M").map_err(|_| invalid())?;
+
+    let offset = match offset.split_at_checked(1) {
+        Some((sign, hhmm)) if hhmm.len() == 4 => {
+            let (hh, mm) = hhmm.split_at_checked(2).ok_or_else(invalid)?;
+            let secs = hh.parse::<i32>().map_err(|_| invalid())? * 3600
+                + mm.parse::<i32>().map_err(|_| invalid())? * 60;
+            match sign {
+                "+" => FixedOffset::east_opt(secs),
+                "-" => FixedOffset::west_opt(secs),
+                _ => None,
+            }
+        }
+        _ if offset.is_empty() => FixedOffset::east_opt(0),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+
+    let datetime = offset
+        .from_local_datetime(&local)
+        .single()
+        .ok_or_else(invalid)?;
+    Ok(Effective::DateTime(datetime.with_timezone(&Utc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const MT940: &str =
+        "{1:F01COBADEFFAXXX0000000000}{2:O9401200240301COBADEFFAXXX00000000002403011200N}{4:
+:20:STARTUMS
+:25:37040044/0532013000
+:28C:00045/001
+:60F:C240229EUR1000,00
+:61:2403010301RD25,50NDDTNONREF//BANK-0001
+:86:105?00LASTSCHRIFT?20EREF+INV-77?21Monthly subscription?3
+2Streaming Service?31DE02120300000000202051?30BYLADEM1001
+:61:2403040302CR1500,NTRFPAYROLL-03
+/OCMT/EUR1500,/
+:86:/TRTP/SEPA CREDIT TRANSFER/NAME/Employer B.V./REMI/Salary March/20
+24/IBAN/NL91ABNA0417164300/
+:61:240305C0,50NINTNONREF
+:62F:C240305EUR2475,00
+:64:C240305EUR2475,00
+:86:Statement end
+-}";
+
+    const MT942: &str = ":20:INTRADAY
+:25:NL91ABNA0417164300
+:28C:1/1
+:34F:EURD0,
+:13D:2403051430+0100
+:61:240305D12,NMSCCARD-1
+:86:Card payment
+:90D:1EUR12,
+:90C:0EUR0,
+-";
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_mt940() {
+        let statements = Mt940::parse(MT940.as_bytes()).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let statement = &statements[0];
+        assert_eq!(statement.account, "37040044/0532013000");
+        assert_eq!(statement.statement_number.as_deref(), Some("00045/001"));
+        assert_eq!(
+            statement.opening_balance,
+            Some(Mt940Balance {
+                amount: dec!(1000.00),
+                currency: "EUR".to_string(),
+                date: date(2024, 2, 29),
+                intermediate: false,
+            })
+        );
+        assert_eq!(statement.information.as_deref(), Some("Statement end"));
+        assert_eq!(statement.transactions.len(), 3);
+
+        let direct_debit = &statement.transactions[0];
+        assert_eq!(direct_debit.entry_date, Some(date(2024, 3, 1)));
+        assert_eq!(direct_debit.amount, dec!(25.50));
+        assert!(direct_debit.reversal);
+        assert_eq!(direct_debit.transaction_type, "NDDT");
+        assert_eq!(direct_debit.source_key, "BANK-0001");
+        let narrative = direct_debit.narrative.as_ref().unwrap();
+        assert_eq!(narrative.transaction_code(), Some("105"));
+        assert_eq!(narrative.posting_text(), Some("LASTSCHRIFT"));
+        assert_eq!(
+            narrative.purpose().as_deref(),
+            Some("EREF+INV-77 Monthly subscription")
+        );
+        assert_eq!(
+            narrative.counterparty_name().as_deref(),
+            Some("Streaming Service")
+        );
+        assert_eq!(narrative.counterparty_bank(), Some("BYLADEM1001"));
+
+        let salary = &statement.transactions[1];
+        assert_eq!(salary.amount, dec!(1500));
+        assert_eq!(salary.customer_reference, "PAYROLL-03");
+        assert_eq!(
+            salary.supplementary_details.as_deref(),
+            Some("/OCMT/EUR1500,/")
+        );
+        let narrative = salary.narrative.as_ref().unwrap();
+        assert_eq!(narrative.get("NAME"), Some("Employer B.V."));
+        assert_eq!(narrative.get("REMI"), Some("Salary March/2024"));
+        assert_eq!(
+            narrative.counterparty_account().as_deref(),
+            Some("NL91ABNA0417164300")
+        );
+
+        let interest = &statement.transactions[2];
+        assert_eq!(interest.source_key, "STARTUMS/3");
+        assert_eq!(interest.entry_date, None);
+        assert!(interest.narrative.is_none());
+    }
+
+    #[test]
+    fn test_parse_mt942() {
+        let statements = Mt940::parse(MT942.as_bytes()).unwrap();
+        let statement = &statements[0];
+        assert_eq!(statement.reference, "INTRADAY");
+        assert_eq!(
+            statement.created,
+            Some(Effective::DateTime("2024-03-05T13:30:00Z".parse().unwrap()))
+        );
+        assert!(statement.opening_balance.is_none());
+        assert_eq!(statement.transactions[0].amount, dec!(-12));
+        assert_eq!(
+            statement.transactions[0].description().as_deref(),
+            Some("Card payment")
+        );
+    }
+
+    #[test]
+    fn test_to_entry_input_and_observations() {
+        let statements = Mt940::parse(MT940.as_bytes()).unwrap();
+        let salary = &statements[0].transactions[1];
+        let account = ResourceSelector::SourceKey {
+            resource_type: "BankAccount".to_string(),
+            source_key: "0532013000".to_string(),
+        };
+        let offset = ResourceSelector::SourceKey {
+            resource_type: "Income".to_string(),
+            source_key: "salary".to_string(),
+        };
+
+        let input = salary.to_entry_input(
+            "BankTransaction".to_string(),
+            Mt940Date::Entry,
+            account,
+            offset,
+        );
+        assert_eq!(input.source_key, "PAYROLL-03");
+        assert_eq!(input.effective, Effective::Date(date(2024, 3, 2)));
+        assert_eq!(input.lines[0].debit, dec!(1500));
+        assert_eq!(input.lines[1].credit, dec!(1500));
+        assert_eq!(
+            input.lines[0].description.as_deref(),
+            Some("Salary March/2024")
+        );
+        assert_eq!(input.lines[0].tags.len(), 2);
+
+        let observations = statements[0].balance_observations("BankBalance".to_string());
+        assert_eq!(observations.len(), 3);
+        assert_eq!(
+            observations[1].source_key,
+            "37040044/0532013000/2024-03-05/ClosingBalance"
+        );
+        assert_eq!(
+            observations[1].measurements[0].measurement,
+            Some(dec!(2475.00))
+        );
+        assert_eq!(
+            observations[1].measurements[0]
+                .measurement_identifier
+                .as_deref(),
+            Some("EUR")
+        );
+    }
+
+    #[test]
+    fn test_entry_date_across_year_end() {
+        let statements = Mt940::parse(
+            b":20:X\n:25:1\n:61:2401021229D1,NTRFNONREF\n:61:2312310101D1,NTRFNONREF\n",
+        )
+        .unwrap();
+        assert_eq!(
+            statements[0].transactions[0].entry_date,
+            Some(date(2023, 12, 29))
+        );
+        assert_eq!(
+            statements[0].transactions[1].entry_date,
+            Some(date(2024, 1, 1))
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_narratives() {
+        let input = ":20:X\n:25:1\n:61:240101D1,NTRFNONREF\n:86:ää Bäckerei\n:aé:x\n:61:240102D1,NTRFNONREF\n:86:1ä?20x\n";
+        let statements = Mt940::parse(input.as_bytes()).unwrap();
+        let transactions = &statements[0].transactions;
+        let narrative = transactions[0].narrative.as_ref().unwrap();
+        assert_eq!(narrative.text, "ää Bäckerei\n:aé:x");
+        assert!(narrative.subfields.is_empty());
+        assert!(
+            transactions[1]
+                .narrative
+                .as_ref()
+                .unwrap()
+                .subfields
+                .is_empty()
+        );
+        assert_eq!(entry_date(date(2024, 1, 1), "ä1"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Mt940::parse(b":20:X\n:61:240101D1,NTRF\n")
+                .unwrap_err()
+                .to_string(),
+            "MT940 statement X has no :25: account"
+        );
+        assert_eq!(
+            Mt940::parse(b":20:X\n:25:1\n:60F:X240101EUR1,\n")
+                .unwrap_err()
+                .to_string(),
+            "Invalid MT940 balance X240101EUR1,"
+        );
+        assert!(Mt940::parse(b"hello").is_err());
+    }
+}
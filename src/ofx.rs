@@ -0,0 +1,670 @@
+use std::borrow::Cow;
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+
+use crate::inputs::{Effective, EntryInput, LineInput, ResourceSelector, TagSelector};
+use crate::statement::parse_amount;
+use crate::text::decode_entities;
+
+/// A parser for OFX and QFX bank and credit card statement downloads. Both the SGML
+/// based OFX 1.x format, where leaf elements have no closing tag, and the XML based
+/// OFX 2.x format are supported.
+pub struct Ofx;
+
+impl Ofx {
+    /// Parses every bank (`STMTRS`) and credit card (`CCSTMTRS`) statement in an OFX
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not UTF-8 and does not declare the Windows-1252
+    /// character set, if it has no `<OFX>` element, or if a statement is missing
+    /// a required element or holds a malformed amount or date
+    pub fn parse(bytes: &[u8]) -> Result<Vec<OfxStatement>> {
+        let text = decode(bytes)?;
+        let start = text
+            .find("<OFX>")
+            .ok_or_else(|| anyhow!("OFX data has no <OFX> element"))?;
+        let root = Element::parse(&text[start..])?;
+
+        let mut statements = Vec::new();
+        root.visit(&mut |element| {
+            if element.name == "STMTRS" || element.name == "CCSTMTRS" {
+                statements.push(OfxStatement::from_element(element));
+            }
+        });
+        statements.into_iter().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfxStatement {
+    /// ISO 4217 currency of the statement (`CURDEF`)
+    pub currency: Option<String>,
+    pub account: OfxAccount,
+    /// Start of the period the transaction list covers (`DTSTART`)
+    pub start: Option<Effective>,
+    /// End of the period the transaction list covers (`DTEND`)
+    pub end: Option<Effective>,
+    pub transactions: Vec<OfxTransaction>,
+    pub ledger_balance: Option<OfxBalance>,
+    pub available_balance: Option<OfxBalance>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfxAccount {
+    /// Routing number of the bank. Not present for credit cards.
+    pub bank_id: Option<String>,
+    pub branch_id: Option<String>,
+    pub account_id: String,
+    /// `CHECKING`, `SAVINGS`, `MONEYMRKT` or `CREDITLINE`. Not present for credit cards.
+    pub account_type: Option<String>,
+    pub credit_card: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfxTransaction {
+    /// `TRNTYPE`, for example `DEBIT`, `CREDIT`, `CHECK` or `FEE`
+    pub transaction_type: String,
+    pub posted: Effective,
+    /// Positive amounts are paid into the account
+    pub amount: Decimal,
+    /// The bank's unique id for the transaction (`FITID`)
+    pub fitid: String,
+    pub name: Option<String>,
+    pub memo: Option<String>,
+    pub check_number: Option<String>,
+    pub reference_number: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfxBalance {
+    pub amount: Decimal,
+    pub as_of: Effective,
+}
+
+impl OfxStatement {
+    fn from_element(stmt: &Element) -> Result<Self> {
+        let credit_card = stmt.name == "CCSTMTRS";
+        let account = stmt
+            .child(if credit_card {
+                "CCACCTFROM"
+            } else {
+                "BANKACCTFROM"
+            })
+            .ok_or_else(|| anyhow!("OFX statement has no account"))?;
+        let list = stmt.child("BANKTRANLIST");
+
+        Ok(Self {
+            currency: stmt.value("CURDEF").map(String::from),
+            account: OfxAccount {
+                bank_id: account.value("BANKID").map(String::from),
+                branch_id: account.value("BRANCHID").map(String::from),
+                account_id: account.required("ACCTID")?.to_string(),
+                account_type: account.value("ACCTTYPE").map(String::from),
+                credit_card,
+            },
+            start: list
+                .and_then(|list| list.value("DTSTART"))
+                .map(parse_date)
+                .transpose()?,
+            end: list
+                .and_then(|list| list.value("DTEND"))
+                .map(parse_date)
+                .transpose()?,
+            transactions: list
+                .map(|list| {
+                    list.children
+                        .iter()
+                        .filter(|child| child.name == "STMTTRN")
+                        .map(OfxTransaction::from_element)
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+            ledger_balance: stmt
+                .child("LEDGERBAL")
+                .map(OfxBalance::from_element)
+                .transpose()?,
+            available_balance: stmt
+                .child("AVAILBAL")
+                .map(OfxBalance::from_element)
+                .transpose()?,
+        })
+    }
+}
+
+impl OfxTransaction {
+    fn from_element(trn: &Element) -> Result<Self> {
+        let fitid = trn.required("FITID")?;
+        let context = || format!("Invalid OFX transaction {}", fitid);
+
+        Ok(Self {
+            transaction_type: trn.value("TRNTYPE").unwrap_or("OTHER").to_string(),
+            posted: parse_date(trn.required("DTPOSTED")?).with_context(context)?,
+            amount: parse_amount(trn.required("TRNAMT")?, "OFX").with_context(context)?,
+            fitid: fitid.to_string(),
+            name: trn.value("NAME").map(String::from),
+            memo: trn.value("MEMO").map(String::from),
+            check_number: trn.value("CHECKNUM").map(String::from),
+            reference_number: trn.value("REFNUM").map(String::from),
+        })
+    }
+
+    /// Maps the transaction to an entry with `FITID` as the source key and `DTPOSTED`
+    /// as the effective date. The amount moves into `account` and is balanced against
+    /// `offset`, with the name and memo as the line description.
+    pub fn to_entry_input(
+        &self,
+        entry_type: String,
+        account: ResourceSelector,
+        offset: ResourceSelector,
+        tags: Vec<TagSelector>,
+    ) -> EntryInput {
+        let description = match (&self.name, &self.memo) {
+            (Some(name), Some(memo)) if name != memo => Some(format!("{} - {}", name, memo)),
+            (Some(name), _) => Some(name.clone()),
+            (None, memo) => memo.clone(),
+        };
+
+        EntryInput::new(
+            self.posted.clone(),
+            self.fitid.clone(),
+            entry_type,
+            LineInput::transfer(account, offset, self.amount, description, tags),
+        )
+    }
+}
+
+impl OfxBalance {
+    fn from_element(balance: &Element) -> Result<Self> {
+        Ok(Self {
+            amount: parse_amount(balance.required("BALAMT")?, "OFX")?,
+            as_of: parse_date(balance.required("DTASOF")?)?,
+        })
+    }
+}
+
+/// Parses an OFX date, `YYYYMMDD[HHMM[SS[.XXX]]][[+|-]H[.MM][:TZ]]`. Dates without a
+/// time become [`Effective::Date`], and times without an offset are in UTC.
+fn parse_date(s: &str) -> Result<Effective> {
+    let err = || anyhow!("Invalid OFX date {}", s);
+
+    let (datetime, tz) = match s.trim().split_once('[') {
+        Some((datetime, tz)) => (datetime, Some(tz.trim_end_matches(']'))),
+        None => (s.trim(), None),
+    };
+    let datetime = datetime.split('.').next().unwrap_or_default();
+
+    if datetime.len() == 8 {
+        return NaiveDate::parse_from_str(datetime, "%Y%m%d")
+            .map(Effective::Date)
+            .map_err(|_| err());
+    }
+
+    let naive = match datetime.len() {
+        12 => NaiveDateTime::parse_from_str(datetime, "%Y%m%d%H%M"),
+        14 => NaiveDateTime::parse_from_str(datetime, "%Y%m%d%H%M%S"),
+        _ => return Err(err()),
+    }
+    .map_err(|_| err())?;
+
+    let offset_seconds = match tz {
+        Some(tz) => parse_offset(tz.split(':').next().unwrap_or_default()).ok_or_else(err)?,
+        None => 0,
+    };
+    let offset = FixedOffset::east_opt(offset_seconds).ok_or_else(err)?;
+    let datetime = offset
+        .from_local_datetime(&naive)
+        .single()
+        .ok_or_else(err)?;
+
+    Ok(Effective::DateTime(datetime.with_timezone(&Utc)))
+}
+
+/// Parses an offset from UTC, `[+|-]H[.MM]`, into seconds. The part after the dot is
+/// minutes, so `+5.30` is five and a half hours.
+fn parse_offset(s: &str) -> Option<i32> {
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(s) => (-1, s),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (hours, minutes) = s.split_once('.').unwrap_or((s, "0"));
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(hours) || !all_digits(minutes) {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Decodes an OFX file as UTF-8, or as Windows-1252 when the header declares
+/// `CHARSET:1252` or an XML `windows-1252` or `ISO-8859-1` encoding.
+fn decode(bytes: &[u8]) -> Result<Cow<'_, str>> {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let header_end = bytes
+        .windows(5)
+        .position(|w| w == b"<OFX>")
+        .unwrap_or(bytes.len());
+    let header = String::from_utf8_lossy(&bytes[..header_end]).to_ascii_uppercase();
+    let header: String = header.split_whitespace().collect();
+    let windows_1252 = ["CHARSET:1252", "WINDOWS-1252", "ISO-8859-1", "LATIN1"]
+        .iter()
+        .any(|charset| header.contains(charset));
+    if !windows_1252 {
+        bail!("OFX data is not valid UTF-8 and declares no supported character set");
+    }
+    Ok(Cow::Owned(
+        bytes.iter().map(|&b| windows_1252_char(b)).collect(),
+    ))
+}
+
+/// Maps a Windows-1252 byte to its character. Bytes outside 0x80 to 0x9F match
+/// ISO-8859-1, and so the Unicode code point of the same value.
+fn windows_1252_char(b: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    match b {
+        0x80..=0x9f => HIGH[(b - 0x80) as usize],
+        _ => b as char,
+    }
+}
+
+/// How deeply elements may nest
+const MAX_DEPTH: usize = 256;
+
+/// An element of an OFX document. Leaf elements hold a value, aggregates hold
+/// children.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    value: Option<String>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    /// Parses an OFX document into a tree. SGML leaf elements are closed by the next tag.
+    /// Fails once elements are nested deeper than [`MAX_DEPTH`].
+    fn parse(text: &str) -> Result<Element> {
+        let mut stack = vec![Element::default()];
+        let mut rest = text;
+
+        while let Some(open) = rest.find('<') {
+            let value = decode_entities(rest[..open].trim());
+            if !value.is_empty() {
+                let top = stack.last_mut().expect("stack always has the root");
+                top.value = Some(value);
+            }
+
+            let close = rest[open..]
+                .find('>')
+                .map(|i| open + i)
+                .ok_or_else(|| anyhow!("Unterminated OFX tag"))?;
+            let tag = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+
+            // A leaf with a value followed by any tag other than its own end tag was
+            // never closed, as SGML allows
+            if stack.len() > 1 && stack.last().is_some_and(|top| top.value.is_some()) {
+                let closes_top = tag
+                    .strip_prefix('/')
+                    .is_some_and(|name| stack.last().is_some_and(|top| top.name == name.trim()));
+                if !closes_top {
+                    Self::close_top(&mut stack);
+                }
+            }
+
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim();
+                if !stack[1..].iter().any(|element| element.name == name) {
+                    bail!("Unexpected OFX end tag </{}>", name);
+                }
+                while stack.len() > 1 {
+                    let done = stack.last().is_some_and(|top| top.name == name);
+                    Self::close_top(&mut stack);
+                    if done {
+                        break;
+                    }
+                }
+            } else if let Some(name) = tag.strip_suffix('/') {
+                let top = stack.last_mut().expect("stack always has the root");
+                top.children.push(Element {
+                    name: name.trim().to_string(),
+                    ..Element::default()
+                });
+            } else {
+                // The root is not an element of the document
+                if stack.len() > MAX_DEPTH {
+                    bail!("OFX elements are nested more than {} deep", MAX_DEPTH);
+                }
+                stack.push(Element {
+                    name: tag
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    ..Element::default()
+                });
+            }
+        }
+
+        while stack.len() > 1 {
+            Self::close_top(&mut stack);
+        }
+
+        let mut root = stack.pop().expect("stack always has the root");
+        root.children
+            .pop()
+            .ok_or_else(|| anyhow!("OFX data has no elements"))
+    }
+
+    fn close_top(stack: &mut Vec<Element>) {
+        if let Some(element) = stack.pop()
+            && let Some(parent) = stack.last_mut()
+        {
+            parent.children.push(element);
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .and_then(|child| child.value.as_deref())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn required(&self, name: &str) -> Result<&str> {
+        self.value(name)
+            .ok_or_else(|| anyhow!("OFX element {} has no {}", self.name, name))
+    }
+
+    /// Calls `f` for this element and each of its descendants, in document order
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Element)) {
+        let mut pending = vec![self];
+        while let Some(element) = pending.pop() {
+            f(element);
+            pending.extend(element.children.iter().rev());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+SECURITY:NONE
+ENCODING:USASCII
+CHARSET:1252
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0<SEVERITY>INFO</STATUS><DTSERVER>20240405120000<LANGUAGE>ENG</SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1>
+<STMTTRNRS>
+<TRNUID>1
+<STATUS><CODE>0<SEVERITY>INFO</STATUS>
+<STMTRS>
+<CURDEF>USD
+<BANKACCTFROM>
+<BANKID>121000248
+<ACCTID>000123456789
+<ACCTTYPE>CHECKING
+</BANKACCTFROM>
+<BANKTRANLIST>
+<DTSTART>20240301
+<DTEND>20240331
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240304120000.000[-5:EST]
+<TRNAMT>-42.10
+<FITID>2024030401
+<NAME>GROCERY &amp; CO
+<MEMO>POS PURCHASE
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>CREDIT
+<DTPOSTED>20240315
+<TRNAMT>1500.00
+<FITID>2024031501
+<NAME>PAYROLL
+</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL>
+<BALAMT>2457.90
+<DTASOF>20240331
+</LEDGERBAL>
+</STMTRS>
+</STMTTRNRS>
+</BANKMSGSRSV1>
+</OFX>
+";
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>
+<OFX>
+  <CREDITCARDMSGSRSV1>
+    <CCSTMTTRNRS>
+      <TRNUID>0</TRNUID>
+      <CCSTMTRS>
+        <CURDEF>EUR</CURDEF>
+        <CCACCTFROM><ACCTID>4111111111111111</ACCTID></CCACCTFROM>
+        <BANKTRANLIST>
+          <DTSTART>20240301000000</DTSTART>
+          <DTEND>20240331000000</DTEND>
+          <STMTTRN>
+            <TRNTYPE>DEBIT</TRNTYPE>
+            <DTPOSTED>20240302093000[+1:CET]</DTPOSTED>
+            <TRNAMT>-9,99</TRNAMT>
+            <FITID>CC-1</FITID>
+            <NAME>Streaming</NAME>
+            <MEMO/>
+          </STMTTRN>
+        </BANKTRANLIST>
+        <LEDGERBAL><BALAMT>-9.99</BALAMT><DTASOF>20240331000000</DTASOF></LEDGERBAL>
+        <AVAILBAL><BALAMT>990.01</BALAMT><DTASOF>20240331000000</DTASOF></AVAILBAL>
+      </CCSTMTRS>
+    </CCSTMTTRNRS>
+  </CREDITCARDMSGSRSV1>
+</OFX>"#;
+
+    fn utc(s: &str) -> Effective {
+        Effective::DateTime(DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc))
+    }
+
+    #[test]
+    fn test_parse_sgml() {
+        let statements = Ofx::parse(SGML.as_bytes()).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let statement = &statements[0];
+        assert_eq!(statement.currency.as_deref(), Some("USD"));
+        assert_eq!(
+            statement.account,
+            OfxAccount {
+                bank_id: Some("121000248".to_string()),
+                branch_id: None,
+                account_id: "000123456789".to_string(),
+                account_type: Some("CHECKING".to_string()),
+                credit_card: false,
+            }
+        );
+        assert_eq!(
+            statement.start,
+            Some(Effective::Date(
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+            ))
+        );
+        assert_eq!(
+            statement.transactions[0],
+            OfxTransaction {
+                transaction_type: "DEBIT".to_string(),
+                posted: utc("2024-03-04T17:00:00Z"),
+                amount: dec!(-42.10),
+                fitid: "2024030401".to_string(),
+                name: Some("GROCERY & CO".to_string()),
+                memo: Some("POS PURCHASE".to_string()),
+                check_number: None,
+                reference_number: None,
+            }
+        );
+        assert_eq!(statement.transactions[1].amount, dec!(1500.00));
+        assert_eq!(
+            statement.ledger_balance,
+            Some(OfxBalance {
+                amount: dec!(2457.90),
+                as_of: Effective::Date(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap()),
+            })
+        );
+        assert_eq!(statement.available_balance, None);
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let statements = Ofx::parse(XML.as_bytes()).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let statement = &statements[0];
+        assert!(statement.account.credit_card);
+        assert_eq!(statement.account.account_id, "4111111111111111");
+        assert_eq!(statement.end, Some(utc("2024-03-31T00:00:00Z")));
+
+        let transaction = &statement.transactions[0];
+        assert_eq!(transaction.posted, utc("2024-03-02T08:30:00Z"));
+        assert_eq!(transaction.amount, dec!(-9.99));
+        assert_eq!(transaction.memo, None);
+        assert_eq!(
+            statement.available_balance.as_ref().map(|b| b.amount),
+            Some(dec!(990.01))
+        );
+    }
+
+    #[test]
+    fn test_to_entry_input() {
+        let statements = Ofx::parse(SGML.as_bytes()).unwrap();
+        let account = ResourceSelector::SourceKey {
+            resource_type: "BankAccount".to_string(),
+            source_key: "000123456789".to_string(),
+        };
+        let offset = ResourceSelector::SourceKey {
+            resource_type: "Uncategorized".to_string(),
+            source_key: "uncategorized".to_string(),
+        };
+
+        let entry = statements[0].transactions[0].to_entry_input(
+            "BankTransaction".to_string(),
+            account.clone(),
+            offset.clone(),
+            vec![],
+        );
+        assert_eq!(entry.source_key, "2024030401");
+        assert_eq!(entry.effective, utc("2024-03-04T17:00:00Z"));
+        assert_eq!(entry.lines.len(), 2);
+        assert_eq!(entry.lines[0].resource, account);
+        assert_eq!(
+            (entry.lines[0].debit, entry.lines[0].credit),
+            (dec!(0), dec!(42.10))
+        );
+        assert_eq!(entry.lines[1].resource, offset);
+        assert_eq!(
+            (entry.lines[1].debit, entry.lines[1].credit),
+            (dec!(42.10), dec!(0))
+        );
+        assert_eq!(
+            entry.lines[0].description.as_deref(),
+            Some("GROCERY & CO - POS PURCHASE")
+        );
+    }
+
+    #[test]
+    fn test_offsets_and_amounts() {
+        let sgml = SGML
+            .replace(
+                "20240304120000.000[-5:EST]",
+                "20240304223000.000[+5.30:IST]",
+            )
+            .replace("<TRNAMT>1500.00", "<TRNAMT>1,234.56");
+        let statements = Ofx::parse(sgml.as_bytes()).unwrap();
+        let transactions = &statements[0].transactions;
+        assert_eq!(transactions[0].posted, utc("2024-03-04T17:00:00Z"));
+        assert_eq!(transactions[1].amount, dec!(1234.56));
+
+        assert_eq!(parse_offset("-3.45"), Some(-(3 * 3600 + 45 * 60)));
+        assert_eq!(parse_offset("0"), Some(0));
+        assert_eq!(parse_offset("5.5x"), None);
+        assert_eq!(parse_offset("5.60"), None);
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        let sgml = SGML.replace("GROCERY &amp; CO", "CAFE");
+        let at = sgml.find("CAFE").unwrap() + 4;
+        let mut bytes = sgml.into_bytes();
+        bytes.splice(at..at, [0xe9, b' ', 0x80]);
+        let statements = Ofx::parse(&bytes).unwrap();
+        assert_eq!(
+            statements[0].transactions[0].name.as_deref(),
+            Some("CAFE\u{e9} \u{20ac}")
+        );
+
+        let at = bytes.windows(4).position(|w| w == b"1252").unwrap();
+        bytes.splice(at..at + 4, *b"NONE");
+        assert_eq!(
+            Ofx::parse(&bytes).unwrap_err().to_string(),
+            "OFX data is not valid UTF-8 and declares no supported character set"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Ofx::parse(b"not ofx").unwrap_err().to_string(),
+            "OFX data has no <OFX> element"
+        );
+
+        let missing_amount = SGML.replace("<TRNAMT>1500.00\n", "");
+        assert_eq!(
+            Ofx::parse(missing_amount.as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "OFX element STMTTRN has no TRNAMT"
+        );
+
+        let bad_date = SGML.replace("20240315\n", "2024-03-15\n");
+        let err = Ofx::parse(bad_date.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid OFX transaction 2024031501");
+        assert_eq!(err.root_cause().to_string(), "Invalid OFX date 2024-03-15");
+
+        let too_deep = format!("<OFX>{}", "<A>".repeat(100_000));
+        assert_eq!(
+            Ofx::parse(too_deep.as_bytes()).unwrap_err().to_string(),
+            "OFX elements are nested more than 256 deep"
+        );
+        let nested = format!(
+            "<OFX>{}{}",
+            "<A>".repeat(MAX_DEPTH - 1),
+            "</A>".repeat(MAX_DEPTH - 1)
+        );
+        assert!(Ofx::parse(nested.as_bytes()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,1861 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{Result, anyhow, bail};
+use flate2::read::ZlibDecoder;
+
+use crate::archive::{Archive, SizeLimit, read_limited_into};
+
+/// Text extraction from PDF statements, with the position of every run of text so rows
+/// can be rebuilt from column positions.
+///
+/// Only what is needed for text is read: the page tree, fonts with their encodings,
+/// `ToUnicode` maps and widths, and page and form content. Streams may be uncompressed
+/// or `FlateDecode`d, and objects may live in object streams. Encrypted PDFs are not
+/// supported, and text drawn as images (scans) cannot be extracted.
+///
+/// Positions are in PDF units (1/72 inch) with the origin at the bottom left of the
+/// page, so `y` grows up the page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pdf {
+    pages: Vec<PdfPage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfPage {
+    /// 1 based page number
+    pub number: usize,
+    pub width: f64,
+    pub height: f64,
+    /// Runs of text in the order they are drawn
+    pub spans: Vec<TextSpan>,
+}
+
+/// A run of text drawn by one text showing operator
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSpan {
+    pub text: String,
+    /// Left edge of the run
+    pub x: f64,
+    /// Baseline of the run
+    pub y: f64,
+    /// Estimated from the font's glyph widths
+    pub width: f64,
+    pub font_size: f64,
+}
+
+/// Spans that share a baseline, ordered left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+    pub y: f64,
+    pub spans: Vec<TextSpan>,
+}
+
+impl Pdf {
+    /// Reads the text of every page
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a PDF, the PDF is encrypted or it has no
+    /// pages
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let start = find(bytes, b"%PDF-", 0)
+            .filter(|start| *start < 1024)
+            .ok_or_else(|| anyhow!("Data is not a PDF"))?;
+        let doc = Document::load(&bytes[start..])?;
+
+        let mut pages = Vec::new();
+        for (idx, page) in doc.pages()?.into_iter().enumerate() {
+            let media_box = page
+                .media_box
+                .as_ref()
+                .map(numbers)
+                .filter(|numbers| numbers.len() == 4)
+                .unwrap_or_else(|| vec![0.0, 0.0, 612.0, 792.0]);
+
+            let mut interpreter = Interpreter::new(&doc);
+            for content in &page.contents {
+                interpreter.run(content, page.resources.as_ref(), Matrix::IDENTITY, 0);
+            }
+            pages.push(PdfPage {
+                number: idx + 1,
+                width: (media_box[2] - media_box[0]).abs(),
+                height: (media_box[3] - media_box[1]).abs(),
+                spans: interpreter.spans,
+            });
+        }
+
+        if pages.is_empty() {
+            bail!("PDF has no pages");
+        }
+        Ok(Self { pages })
+    }
+
+    pub fn pages(&self) -> &[PdfPage] {
+        &self.pages
+    }
+
+    /// The text of every page, see [`PdfPage::text`]. Pages are separated by a blank
+    /// line.
+    pub fn text(&self) -> String {
+        self.pages
+            .iter()
+            .map(PdfPage::text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl PdfPage {
+    /// Groups the spans into lines, top to bottom. Spans belong to the same line when
+    /// their baselines are within a third of the font size of each other.
+    pub fn lines(&self) -> Vec<TextLine> {
+        let mut spans: Vec<&TextSpan> = self
+            .spans
+            .iter()
+            .filter(|span| !span.text.trim().is_empty())
+            .collect();
+        spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+        let mut lines: Vec<TextLine> = Vec::new();
+        for span in spans {
+            let tolerance = (span.font_size / 3.0).max(1.0);
+            match lines.last_mut() {
+                Some(line) if (line.y - span.y).abs() <= tolerance => line.spans.push(span.clone()),
+                _ => lines.push(TextLine {
+                    y: span.y,
+                    spans: vec![span.clone()],
+                }),
+            }
+        }
+        for line in &mut lines {
+            line.spans.sort_by(|a, b| a.x.total_cmp(&b.x));
+        }
+        lines
+    }
+
+    /// The text of the page, one line per [`TextLine`]
+    pub fn text(&self) -> String {
+        self.lines()
+            .iter()
+            .map(TextLine::text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Rebuilds a table by splitting every line at the given x coordinates, which are
+    /// the left edges of the second and later columns. A span goes in the column its
+    /// left edge falls in, so every row has `columns.len() + 1` cells.
+    ///
+    /// The rows have the same shape as [`Csv::parse`](crate::csv::Csv::parse), so a
+    /// [`CsvReader`](crate::csv::CsvReader) can find the header and footer with
+    /// [`read_rows`](crate::csv::CsvReader::read_rows).
+    pub fn table(&self, columns: &[f64]) -> Vec<Vec<String>> {
+        self.lines()
+            .iter()
+            .map(|line| {
+                let mut cells: Vec<Vec<&TextSpan>> = vec![Vec::new(); columns.len() + 1];
+                for span in &line.spans {
+                    let col = columns.iter().filter(|edge| span.x >= **edge).count();
+                    cells[col].push(span);
+                }
+                cells.iter().map(|spans| join_spans(spans)).collect()
+            })
+            .collect()
+    }
+}
+
+impl TextLine {
+    /// The spans joined left to right, with a space wherever there is a visible gap
+    pub fn text(&self) -> String {
+        join_spans(&self.spans.iter().collect::<Vec<_>>())
+    }
+}
+
+fn join_spans(spans: &[&TextSpan]) -> String {
+    let mut text = String::new();
+    let mut end: Option<f64> = None;
+    for span in spans {
+        if let Some(end) = end
+            && span.x - end > span.font_size * 0.15
+            && !text.ends_with(' ')
+            && !span.text.starts_with(' ')
+        {
+            text.push(' ');
+        }
+        text.push_str(&span.text);
+        end = Some(span.x + span.width);
+    }
+    text.trim().to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Object {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<Object>),
+    Dict(Dict),
+    Stream(Dict, Rc<Vec<u8>>),
+    Ref(u32),
+    /// A bare keyword, such as a content stream operator
+    Operator(String),
+}
+
+type Dict = HashMap<String, Object>;
+
+impl Object {
+    fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Object::Dict(dict) | Object::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Object::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&str> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn numbers(object: &Object) -> Vec<f64> {
+    match object {
+        Object::Array(items) => items.iter().filter_map(Object::as_number).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|idx| idx + from)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, 0 | b'\t' | b'\n' | 0x0c | b'\r' | b' ')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(
+        b,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+/// How deeply arrays and dictionaries may nest
+const MAX_DEPTH: usize = 256;
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos: pos.min(data.len()),
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            if is_whitespace(b) {
+                self.pos += 1;
+            } else if b == b'%' {
+                while self.peek().is_some_and(|b| b != b'\n' && b != b'\r') {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn starts_with(&self, s: &[u8]) -> bool {
+        self.data
+            .get(self.pos..)
+            .is_some_and(|rest| rest.starts_with(s))
+    }
+
+    fn regular(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|b| !is_whitespace(b) && !is_delimiter(b))
+        {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    /// Reads the next object. With `refs`, `1 0 R` is read as a reference, which is
+    /// only done in the file body since content streams have no references.
+    fn object(&mut self, refs: bool) -> Option<Object> {
+        // Skips stray delimiters, which do not start an object
+        let b = loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                b')' | b'>' | b']' | b'{' | b'}' => self.pos += 1,
+                b => break b,
+            }
+        };
+        match b {
+            b'/' => {
+                self.pos += 1;
+                Some(Object::Name(decode_name(self.regular())))
+            }
+            b'(' => Some(Object::String(self.literal_string())),
+            b'<' if self.starts_with(b"<<") => {
+                self.pos += 2;
+                self.nested(|lexer| lexer.dict(refs))
+            }
+            b'<' => Some(Object::String(self.hex_string())),
+            b'[' => {
+                self.pos += 1;
+                self.nested(|lexer| lexer.array(refs))
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => {
+                let token = self.regular();
+                let number: f64 = std::str::from_utf8(token).ok()?.parse().ok().or_else(|| {
+                    // Tolerates malformed numbers such as `--1` or `1.2.3`
+                    let cleaned: String = String::from_utf8_lossy(token)
+                        .trim_start_matches(['+', '-'])
+                        .chars()
+                        .filter(|c| c.is_ascii_digit() || *c == '.')
+                        .collect();
+                    cleaned
+                        .split('.')
+                        .take(2)
+                        .collect::<Vec<_>>()
+                        .join(".")
+                        .parse()
+                        .ok()
+                })?;
+                if refs && token.iter().all(u8::is_ascii_digit) {
+                    let save = self.pos;
+                    self.skip_whitespace();
+                    let generation = self.regular();
+                    self.skip_whitespace();
+                    if !generation.is_empty()
+                        && generation.iter().all(u8::is_ascii_digit)
+                        && self.regular() == b"R"
+                    {
+                        return Some(Object::Ref(number as u32));
+                    }
+                    self.pos = save;
+                }
+                Some(Object::Number(number))
+            }
+            _ => {
+                let token = self.regular();
+                Some(match token {
+                    b"true" => Object::Bool(true),
+                    b"false" => Object::Bool(false),
+                    b"null" => Object::Null,
+                    _ => Object::Operator(String::from_utf8_lossy(token).into_owned()),
+                })
+            }
+        }
+    }
+
+    /// Reads the contents of a container, failing once containers are nested deeper
+    /// than [`MAX_DEPTH`] so hostile input cannot overflow the stack
+    fn nested(&mut self, read: impl FnOnce(&mut Self) -> Option<Object>) -> Option<Object> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let object = read(self);
+        self.depth -= 1;
+        object
+    }
+
+    /// Reads the entries of a dictionary after its `<<`. Returns `None` if the data
+    /// ends before the closing `>>`.
+    fn dict(&mut self, refs: bool) -> Option<Object> {
+        let mut dict = Dict::new();
+        loop {
+            self.skip_whitespace();
+            if self.starts_with(b">>") {
+                self.pos += 2;
+                return Some(Object::Dict(dict));
+            }
+            self.peek()?;
+            match self.object(refs)? {
+                Object::Name(key) => {
+                    let value = self.object(refs)?;
+                    dict.insert(key, value);
+                }
+                // Skips junk so one bad entry does not lose the dictionary
+                _ => continue,
+            }
+        }
+    }
+
+    /// Reads the items of an array after its `[`. Returns `None` if the data ends
+    /// before the closing `]`.
+    fn array(&mut self, refs: bool) -> Option<Object> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                b']' => {
+                    self.pos += 1;
+                    return Some(Object::Array(items));
+                }
+                _ => items.push(self.object(refs)?),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        let mut depth = 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    out.push(b);
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else { break };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(d - b'0');
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues the string
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => out.push(other),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        self.pos += 1;
+        let mut digits = Vec::new();
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'>' => break,
+                _ if b.is_ascii_hexdigit() => digits.push(b),
+                _ => {}
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        digits
+            .chunks(2)
+            .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect()
+    }
+}
+
+fn decode_name(raw: &[u8]) -> String {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut idx = 0;
+    while idx < raw.len() {
+        if raw[idx] == b'#'
+            && let Some(byte) = raw
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+        {
+            out.push(byte);
+            idx += 3;
+        } else {
+            out.push(raw[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The objects of a PDF file. Objects are found by scanning for `n g obj` rather than
+/// through the cross reference table, which is often broken in generated statements.
+struct Document {
+    objects: HashMap<u32, Object>,
+    root: Option<u32>,
+}
+
+struct PageRef {
+    resources: Option<Object>,
+    media_box: Option<Object>,
+    contents: Vec<Rc<Vec<u8>>>,
+}
+
+impl Document {
+    fn load(data: &[u8]) -> Result<Self> {
+        let mut objects = HashMap::new();
+        let mut search = 0;
+        while let Some(idx) = find(data, b"obj", search) {
+            search = idx + 3;
+            if let Some(id) = object_number(data, idx)
+                && data
+                    .get(idx + 3)
+                    .is_none_or(|b| is_whitespace(*b) || is_delimiter(*b))
+                && let Some(object) = Self::read_object(data, idx + 3)
+            {
+                objects.insert(id, object);
+            }
+        }
+
+        let mut doc = Self {
+            objects,
+            root: None,
+        };
+        doc.expand_object_streams();
+
+        let trailer = rfind(data, b"trailer")
+            .and_then(|idx| Lexer::new(data, idx + 7).object(true))
+            .and_then(|trailer| trailer.as_dict().cloned());
+        let xref_stream = doc
+            .objects
+            .values()
+            .filter_map(Object::as_dict)
+            .find(|dict| dict.get("Type").and_then(Object::as_name) == Some("XRef"))
+            .cloned();
+        let trailers: Vec<&Dict> = trailer.iter().chain(xref_stream.iter()).collect();
+
+        if trailers.iter().any(|dict| dict.contains_key("Encrypt")) {
+            bail!("Encrypted PDFs are not supported");
+        }
+        doc.root = trailers.iter().find_map(|dict| match dict.get("Root") {
+            Some(Object::Ref(id)) => Some(*id),
+            _ => None,
+        });
+        Ok(doc)
+    }
+
+    fn read_object(data: &[u8], pos: usize) -> Option<Object> {
+        let mut lexer = Lexer::new(data, pos);
+        let object = lexer.object(true)?;
+        let Object::Dict(dict) = object else {
+            return Some(object);
+        };
+
+        lexer.skip_whitespace();
+        if !lexer.starts_with(b"stream") {
+            return Some(Object::Dict(dict));
+        }
+        let mut start = lexer.pos + 6;
+        if data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+
+        let declared = dict
+            .get("Length")
+            .and_then(Object::as_number)
+            .and_then(|length| usize::try_from(length as u64).ok())
+            .and_then(|length| start.checked_add(length))
+            .filter(|end| *end <= data.len())
+            .filter(|end| {
+                data.get(*end..).is_some_and(|rest| {
+                    let skip = rest.iter().take_while(|b| is_whitespace(**b)).count();
+                    rest[skip..].starts_with(b"endstream")
+                })
+            });
+        let end = match declared {
+            Some(end) => end,
+            None => {
+                let mut end = find(data, b"endstream", start)?;
+                if data[..end].ends_with(b"\r\n") {
+                    end -= 2;
+                } else if data[..end].ends_with(b"\n") || data[..end].ends_with(b"\r") {
+                    end -= 1;
+                }
+                end.max(start)
+            }
+        };
+        Some(Object::Stream(dict, Rc::new(data[start..end].to_vec())))
+    }
+
+    /// Adds the objects stored inside object streams. Objects defined directly in the
+    /// file take precedence.
+    fn expand_object_streams(&mut self) {
+        let streams: Vec<(Dict, Rc<Vec<u8>>)> = self
+            .objects
+            .values()
+            .filter_map(|object| match object {
+                Object::Stream(dict, data)
+                    if dict.get("Type").and_then(Object::as_name) == Some("ObjStm") =>
+                {
+                    Some((dict.clone(), data.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        for (dict, data) in streams {
+            let Some(data) = decode_stream(&dict, &data) else {
+                continue;
+            };
+            let count = dict.get("N").and_then(Object::as_number).unwrap_or(0.0) as usize;
+            let first = dict.get("First").and_then(Object::as_number).unwrap_or(0.0) as usize;
+
+            let mut header = Lexer::new(&data, 0);
+            for _ in 0..count {
+                let (Some(Object::Number(id)), Some(Object::Number(offset))) =
+                    (header.object(false), header.object(false))
+                else {
+                    break;
+                };
+                let id = id as u32;
+                if self.objects.contains_key(&id) {
+                    continue;
+                }
+                let Some(pos) = first
+                    .checked_add(offset as usize)
+                    .filter(|pos| *pos < data.len())
+                else {
+                    continue;
+                };
+                if let Some(object) = Lexer::new(&data, pos).object(true) {
+                    self.objects.insert(id, object);
+                }
+            }
+        }
+    }
+
+    fn resolve<'a>(&'a self, object: &'a Object) -> &'a Object {
+        let mut object = object;
+        // Bounded so reference cycles cannot loop forever
+        for _ in 0..32 {
+            match object {
+                Object::Ref(id) => object = self.objects.get(id).unwrap_or(&Object::Null),
+                _ => return object,
+            }
+        }
+        &Object::Null
+    }
+
+    fn get<'a>(&'a self, dict: &'a Dict, key: &str) -> Option<&'a Object> {
+        dict.get(key).map(|object| self.resolve(object))
+    }
+
+    fn get_dict<'a>(&'a self, dict: &'a Dict, key: &str) -> Option<&'a Dict> {
+        self.get(dict, key).and_then(Object::as_dict)
+    }
+
+    fn pages(&self) -> Result<Vec<PageRef>> {
+        let root = match self.root.and_then(|id| self.objects.get(&id)) {
+            Some(root) => root,
+            None => self
+                .objects
+                .values()
+                .find(|object| {
+                    object.as_dict().is_some_and(|dict| {
+                        dict.get("Type").and_then(Object::as_name) == Some("Catalog")
+                    })
+                })
+                .ok_or_else(|| anyhow!("PDF has no catalog"))?,
+        };
+        let pages = root
+            .as_dict()
+            .and_then(|root| self.get(root, "Pages"))
+            .ok_or_else(|| anyhow!("PDF has no page tree"))?;
+
+        let mut out = Vec::new();
+        self.collect_pages(pages, None, None, &mut out, 0);
+        Ok(out)
+    }
+
+    fn collect_pages(
+        &self,
+        node: &Object,
+        resources: Option<&Object>,
+        media_box: Option<&Object>,
+        out: &mut Vec<PageRef>,
+        depth: usize,
+    ) {
+        let Some(dict) = node.as_dict() else { return };
+        if depth > 64 {
+            return;
+        }
+        let resources = self.get(dict, "Resources").or(resources);
+        let media_box = self.get(dict, "MediaBox").or(media_box);
+
+        match self.get(dict, "Kids") {
+            Some(Object::Array(kids)) => {
+                for kid in kids {
+                    self.collect_pages(self.resolve(kid), resources, media_box, out, depth + 1);
+                }
+            }
+            _ => {
+                let contents = match self.get(dict, "Contents") {
+                    Some(Object::Array(parts)) => {
+                        parts.iter().map(|part| self.resolve(part)).collect()
+                    }
+                    Some(content) => vec![content],
+                    None => Vec::new(),
+                };
+                out.push(PageRef {
+                    resources: resources.cloned(),
+                    media_box: media_box.cloned(),
+                    contents: contents
+                        .into_iter()
+                        .filter_map(|content| match content {
+                            Object::Stream(dict, data) => decode_stream(dict, data).map(Rc::new),
+                            _ => None,
+                        })
+                        .collect(),
+                });
+            }
+        }
+    }
+}
+
+/// Walks back from an `obj` keyword over `n g ` and returns `n`
+fn object_number(data: &[u8], obj: usize) -> Option<u32> {
+    let mut idx = obj;
+    let skip_digits = |idx: &mut usize, need_space: bool| -> Option<(usize, usize)> {
+        let end = *idx;
+        if need_space {
+            if end == 0 || !is_whitespace(data[end - 1]) {
+                return None;
+            }
+            while *idx > 0 && is_whitespace(data[*idx - 1]) {
+                *idx -= 1;
+            }
+        }
+        let digits_end = *idx;
+        while *idx > 0 && data[*idx - 1].is_ascii_digit() {
+            *idx -= 1;
+        }
+        (*idx < digits_end).then_some((*idx, digits_end))
+    };
+
+    skip_digits(&mut idx, true)?;
+    let (start, end) = skip_digits(&mut idx, true)?;
+    if start > 0 && !is_whitespace(data[start - 1]) && !is_delimiter(data[start - 1]) {
+        return None;
+    }
+    std::str::from_utf8(&data[start..end]).ok()?.parse().ok()
+}
+
+/// Applies the stream's filters. Returns `None` for filters other than `FlateDecode`
+/// and `ASCIIHexDecode`.
+fn decode_stream(dict: &Dict, data: &[u8]) -> Option<Vec<u8>> {
+    let filters = match dict.get("Filter") {
+        Some(Object::Name(name)) => vec![name.clone()],
+        Some(Object::Array(names)) => names
+            .iter()
+            .filter_map(|name| name.as_name().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let params = match dict.get("DecodeParms") {
+        Some(Object::Dict(params)) => Some(params),
+        Some(Object::Array(params)) => params.first().and_then(Object::as_dict),
+        _ => None,
+    };
+
+    let mut data = data.to_vec();
+    for filter in filters {
+        data = match filter.as_str() {
+            "FlateDecode" | "Fl" => {
+                let mut out = Vec::new();
+                // Keeps what was inflated from streams with a corrupt tail, but nothing
+                // from streams that inflate past the size limit
+                match read_limited_into(
+                    ZlibDecoder::new(data.as_slice()),
+                    Archive::MAX_SIZE,
+                    &mut out,
+                ) {
+                    Ok(()) => {}
+                    Err(e) if e.is::<SizeLimit>() => return None,
+                    Err(_) if out.is_empty() => return None,
+                    Err(_) => {}
+                }
+                match params {
+                    Some(params) => undo_predictor(params, out)?,
+                    None => out,
+                }
+            }
+            "ASCIIHexDecode" | "AHx" => {
+                let mut hex = data.clone();
+                hex.insert(0, b'<');
+                Lexer::new(&hex, 0).hex_string()
+            }
+            _ => return None,
+        };
+    }
+    Some(data)
+}
+
+/// Reverses PNG row predictors, used by some writers for object streams
+fn undo_predictor(params: &Dict, data: Vec<u8>) -> Option<Vec<u8>> {
+    let predictor = params
+        .get("Predictor")
+        .and_then(Object::as_number)
+        .unwrap_or(1.0);
+    if predictor < 10.0 {
+        return Some(data);
+    }
+    let columns = params
+        .get("Columns")
+        .and_then(Object::as_number)
+        .unwrap_or(1.0) as usize;
+    let colors = params
+        .get("Colors")
+        .and_then(Object::as_number)
+        .unwrap_or(1.0) as usize;
+    let bits = params
+        .get("BitsPerComponent")
+        .and_then(Object::as_number)
+        .unwrap_or(8.0) as usize;
+    let bits_per_pixel = colors.checked_mul(bits)?;
+    let bpp = bits_per_pixel.div_ceil(8).max(1);
+    // A row can be no longer than the data it is read from
+    let row_len = columns
+        .checked_mul(bits_per_pixel)?
+        .div_ceil(8)
+        .min(data.len());
+
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_len];
+    for row in data.chunks(row_len + 1) {
+        let (&kind, row) = row.split_first()?;
+        let mut current = row.to_vec();
+        current.resize(row_len, 0);
+        for idx in 0..row_len {
+            let left = if idx >= bpp { current[idx - bpp] } else { 0 };
+            let up = previous[idx];
+            let up_left = if idx >= bpp { previous[idx - bpp] } else { 0 };
+            current[idx] = match kind {
+                1 => current[idx].wrapping_add(left),
+                2 => current[idx].wrapping_add(up),
+                3 => current[idx].wrapping_add(((u16::from(left) + u16::from(up)) / 2) as u8),
+                4 => {
+                    let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+                    let (pa, pb, pc) = (
+                        (p - i16::from(left)).abs(),
+                        (p - i16::from(up)).abs(),
+                        (p - i16::from(up_left)).abs(),
+                    );
+                    let predicted = if pa <= pb && pa <= pc {
+                        left
+                    } else if pb <= pc {
+                        up
+                    } else {
+                        up_left
+                    };
+                    current[idx].wrapping_add(predicted)
+                }
+                _ => current[idx],
+            };
+        }
+        out.extend_from_slice(&current);
+        previous = current;
+    }
+    Some(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Matrix([f64; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn from_operands(operands: &[Object]) -> Option<Self> {
+        let values: Vec<f64> = operands.iter().filter_map(Object::as_number).collect();
+        let values: [f64; 6] = values
+            .get(values.len().checked_sub(6)?..)?
+            .try_into()
+            .ok()?;
+        Some(Matrix(values))
+    }
+
+    fn translate(x: f64, y: f64) -> Self {
+        Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    /// `self` applied first, then `other`
+    fn then(&self, other: &Matrix) -> Matrix {
+        let [a1, b1, c1, d1, e1, f1] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a1 * a2 + b1 * c2,
+            a1 * b2 + b1 * d2,
+            c1 * a2 + d1 * c2,
+            c1 * b2 + d1 * d2,
+            e1 * a2 + f1 * c2 + e2,
+            e1 * b2 + f1 * d2 + f2,
+        ])
+    }
+}
+
+/// A font as far as text extraction needs it: how to split strings into character codes,
+/// what the codes mean and how wide they are
+struct Font {
+    /// Type0 fonts use multi byte codes
+    composite: bool,
+    to_unicode: Option<CMap>,
+    encoding: Option<[Option<char>; 256]>,
+    /// Widths in thousandths of the font size
+    widths: HashMap<u32, f64>,
+    default_width: f64,
+}
+
+impl Font {
+    /// A stand-in for missing fonts, reading bytes as Latin-1
+    fn fallback() -> Self {
+        Self {
+            composite: false,
+            to_unicode: None,
+            encoding: None,
+            widths: HashMap::new(),
+            default_width: 500.0,
+        }
+    }
+
+    fn load(doc: &Document, dict: &Dict) -> Self {
+        let subtype = doc.get(dict, "Subtype").and_then(Object::as_name);
+        let composite = subtype == Some("Type0");
+        let to_unicode = match doc.get(dict, "ToUnicode") {
+            Some(Object::Stream(stream, data)) => {
+                decode_stream(stream, data).map(|data| CMap::parse(&data))
+            }
+            _ => None,
+        };
+
+        let mut widths = HashMap::new();
+        let default_width;
+        if composite {
+            let descendant = match doc.get(dict, "DescendantFonts") {
+                Some(Object::Array(fonts)) => fonts.first().map(|font| doc.resolve(font)),
+                _ => None,
+            }
+            .and_then(Object::as_dict);
+            default_width = descendant
+                .and_then(|font| doc.get(font, "DW"))
+                .and_then(Object::as_number)
+                .unwrap_or(1000.0);
+            if let Some(Object::Array(w)) = descendant.and_then(|font| doc.get(font, "W")) {
+                let w: Vec<&Object> = w.iter().map(|item| doc.resolve(item)).collect();
+                let mut idx = 0;
+                while idx + 1 < w.len() {
+                    let Some(first) = w[idx].as_number() else {
+                        break;
+                    };
+                    match w[idx + 1] {
+                        Object::Array(list) => {
+                            for (offset, width) in list.iter().enumerate() {
+                                if let Some(width) = doc.resolve(width).as_number() {
+                                    widths.insert(first as u32 + offset as u32, width);
+                                }
+                            }
+                            idx += 2;
+                        }
+                        Object::Number(last) => {
+                            if let Some(width) = w.get(idx + 2).and_then(|w| w.as_number()) {
+                                for code in first as u32..=(*last as u32).min(first as u32 + 65535)
+                                {
+                                    widths.insert(code, width);
+                                }
+                            }
+                            idx += 3;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        } else {
+            let first = doc
+                .get(dict, "FirstChar")
+                .and_then(Object::as_number)
+                .unwrap_or(0.0) as u32;
+            if let Some(Object::Array(list)) = doc.get(dict, "Widths") {
+                for (offset, width) in list.iter().enumerate() {
+                    if let Some(width) = doc.resolve(width).as_number() {
+                        widths.insert(first + offset as u32, width);
+                    }
+                }
+            }
+            default_width = doc
+                .get_dict(dict, "FontDescriptor")
+                .and_then(|descriptor| doc.get(descriptor, "MissingWidth"))
+                .and_then(Object::as_number)
+                .filter(|width| *width > 0.0)
+                .unwrap_or(500.0);
+        }
+
+        let encoding = (!composite).then(|| simple_encoding(doc, doc.get(dict, "Encoding")));
+
+        Self {
+            composite,
+            to_unicode,
+            encoding,
+            widths,
+            default_width,
+        }
+    }
+
+    /// Splits a string into character codes with their text and width
+    fn decode(&self, bytes: &[u8]) -> Vec<(u32, String, f64)> {
+        let mut out = Vec::new();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            let len = match &self.to_unicode {
+                Some(cmap) if self.composite => cmap.code_length(&bytes[idx..]),
+                _ if self.composite => 2,
+                _ => 1,
+            }
+            .min(bytes.len() - idx);
+            let code = bytes[idx..idx + len]
+                .iter()
+                .fold(0u32, |code, b| code << 8 | u32::from(*b));
+            idx += len;
+
+            let text = self
+                .to_unicode
+                .as_ref()
+                .and_then(|cmap| cmap.map.get(&code).cloned())
+                .or_else(|| match &self.encoding {
+                    Some(encoding) => encoding[code as usize & 0xff].map(String::from),
+                    None if !self.composite => char::from_u32(code).map(String::from),
+                    None => None,
+                })
+                .unwrap_or_default();
+            let width = self
+                .widths
+                .get(&code)
+                .copied()
+                .unwrap_or(self.default_width);
+            out.push((code, text, width));
+        }
+        out
+    }
+}
+
+/// The parts of a `ToUnicode` CMap that map codes to text
+#[derive(Default)]
+struct CMap {
+    /// Byte lengths and ranges of valid codes
+    codespaces: Vec<(usize, u32, u32)>,
+    map: HashMap<u32, String>,
+}
+
+impl CMap {
+    fn parse(data: &[u8]) -> Self {
+        let mut cmap = CMap::default();
+        let mut lexer = Lexer::new(data, 0);
+        let mut operands: Vec<Object> = Vec::new();
+
+        while let Some(object) = lexer.object(false) {
+            let Object::Operator(op) = object else {
+                operands.push(object);
+                continue;
+            };
+            match op.as_str() {
+                "endcodespacerange" => {
+                    for pair in operands.chunks(2) {
+                        if let [Object::String(lo), Object::String(hi)] = pair {
+                            cmap.codespaces.push((lo.len(), code(lo), code(hi)));
+                        }
+                    }
+                }
+                "endbfchar" => {
+                    for pair in operands.chunks(2) {
+                        if let [Object::String(src), Object::String(dst)] = pair {
+                            cmap.map.insert(code(src), utf16(dst));
+                        }
+                    }
+                }
+                "endbfrange" => {
+                    for triple in operands.chunks(3) {
+                        let [Object::String(lo), Object::String(hi), dst] = triple else {
+                            continue;
+                        };
+                        let (lo, hi) = (code(lo), code(hi).min(code(lo) + 65535));
+                        match dst {
+                            Object::String(dst) => {
+                                let start: Vec<u16> = dst
+                                    .chunks(2)
+                                    .map(|pair| {
+                                        u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])
+                                    })
+                                    .collect();
+                                for (offset, src) in (lo..=hi).enumerate() {
+                                    let mut units = start.clone();
+                                    if let Some(last) = units.last_mut() {
+                                        *last = last.wrapping_add(offset as u16);
+                                    }
+                                    cmap.map.insert(src, String::from_utf16_lossy(&units));
+                                }
+                            }
+                            Object::Array(list) => {
+                                for (src, dst) in (lo..=hi).zip(list) {
+                                    if let Object::String(dst) = dst {
+                                        cmap.map.insert(src, utf16(dst));
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+        cmap
+    }
+
+    /// The byte length of the code at the start of `bytes`, from the codespace ranges
+    fn code_length(&self, bytes: &[u8]) -> usize {
+        self.codespaces
+            .iter()
+            .find(|(len, lo, hi)| {
+                bytes.len() >= *len && {
+                    let code = code(&bytes[..*len]);
+                    (*lo..=*hi).contains(&code)
+                }
+            })
+            .map_or(2, |(len, _, _)| *len)
+    }
+}
+
+fn code(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |code, b| code << 8 | u32::from(*b))
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The code to character table of a simple font: a base encoding with any
+/// `Differences` applied
+fn simple_encoding(doc: &Document, encoding: Option<&Object>) -> [Option<char>; 256] {
+    let (base, differences) = match encoding {
+        Some(Object::Name(name)) => (Some(name.as_str()), None),
+        Some(Object::Dict(dict)) => (
+            doc.get(dict, "BaseEncoding").and_then(Object::as_name),
+            match doc.get(dict, "Differences") {
+                Some(Object::Array(differences)) => Some(differences),
+                _ => None,
+            },
+        ),
+        _ => (None, None),
+    };
+
+    let mut table = [None; 256];
+    for (code, slot) in table.iter_mut().enumerate() {
+        *slot = match (base, code) {
+            (Some("WinAnsiEncoding") | None, 0x80..=0x9f) => WIN_ANSI_80[code - 0x80],
+            _ => char::from_u32(code as u32),
+        };
+    }
+
+    if let Some(differences) = differences {
+        let mut code = 0usize;
+        for item in differences {
+            match doc.resolve(item) {
+                Object::Number(n) => code = *n as usize,
+                Object::Name(name) => {
+                    if code < 256 {
+                        table[code] = glyph_to_char(name);
+                    }
+                    code += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    table
+}
+
+/// Characters for codes 0x80 to 0x9F in WinAnsiEncoding, which differs from Latin-1
+/// there
+const WIN_ANSI_80: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+/// Maps an Adobe glyph name to its character, for the names used in statements
+fn glyph_to_char(name: &str) -> Option<char> {
+    if let Some(hex) = name.strip_prefix("uni").or_else(|| name.strip_prefix('u'))
+        && (4..=6).contains(&hex.len())
+        && let Ok(code) = u32::from_str_radix(hex, 16)
+    {
+        return char::from_u32(code);
+    }
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c);
+    }
+
+    Some(match name {
+        "space" | "nbspace" => ' ',
+        "exclam" => '!',
+        "quotedbl" => '"',
+        "numbersign" => '#',
+        "dollar" => '$',
+        "percent" => '%',
+        "ampersand" => '&',
+        "quotesingle" => '\'',
+        "parenleft" => '(',
+        "parenright" => ')',
+        "asterisk" => '*',
+        "plus" => '+',
+        "comma" => ',',
+        "hyphen" | "minus" => '-',
+        "period" => '.',
+        "slash" => '/',
+        "zero" => '0',
+        "one" => '1',
+        "two" => '2',
+        "three" => '3',
+        "four" => '4',
+        "five" => '5',
+        "six" => '6',
+        "seven" => '7',
+        "eight" => '8',
+        "nine" => '9',
+        "colon" => ':',
+        "semicolon" => ';',
+        "less" => '<',
+        "equal" => '=',
+        "greater" => '>',
+        "question" => '?',
+        "at" => '@',
+        "bracketleft" => '[',
+        "backslash" => '\\',
+        "bracketright" => ']',
+        "asciicircum" => '^',
+        "underscore" => '_',
+        "grave" => '`',
+        "braceleft" => '{',
+        "bar" => '|',
+        "braceright" => '}',
+        "asciitilde" => '~',
+        "quoteleft" => '‘',
+        "quoteright" => '’',
+        "quotedblleft" => '“',
+        "quotedblright" => '”',
+        "bullet" => '•',
+        "endash" => '–',
+        "emdash" => '—',
+        "ellipsis" => '…',
+        "Euro" => '€',
+        "sterling" => '£',
+        "yen" => '¥',
+        "cent" => '¢',
+        "section" => '§',
+        "degree" => '°',
+        "copyright" => '©',
+        "registered" => '®',
+        "trademark" => '™',
+        "germandbls" => 'ß',
+        "adieresis" => 'ä',
+        "odieresis" => 'ö',
+        "udieresis" => 'ü',
+        "Adieresis" => 'Ä',
+        "Odieresis" => 'Ö',
+        "Udieresis" => 'Ü',
+        "aacute" => 'á',
+        "eacute" => 'é',
+        "iacute" => 'í',
+        "oacute" => 'ó',
+        "uacute" => 'ú',
+        "agrave" => 'à',
+        "egrave" => 'è',
+        "ecircumflex" => 'ê',
+        "ccedilla" => 'ç',
+        "ntilde" => 'ñ',
+        "Eacute" => 'É',
+        _ => return None,
+    })
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Rc<Font>,
+    font_size: f64,
+    char_spacing: f64,
+    word_spacing: f64,
+    horizontal_scale: f64,
+    leading: f64,
+    rise: f64,
+}
+
+/// Runs content streams, recording where text is drawn
+struct Interpreter<'a> {
+    doc: &'a Document,
+    fonts: HashMap<*const Dict, Rc<Font>>,
+    spans: Vec<TextSpan>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(doc: &'a Document) -> Self {
+        Self {
+            doc,
+            fonts: HashMap::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    fn font(&mut self, resources: Option<&Object>, name: &str) -> Rc<Font> {
+        let doc = self.doc;
+        let Some(dict) = resources
+            .and_then(Object::as_dict)
+            .and_then(|resources| doc.get_dict(resources, "Font"))
+            .and_then(|fonts| doc.get_dict(fonts, name))
+        else {
+            return Rc::new(Font::fallback());
+        };
+        self.fonts
+            .entry(dict as *const Dict)
+            .or_insert_with(|| Rc::new(Font::load(doc, dict)))
+            .clone()
+    }
+
+    fn run(&mut self, content: &[u8], resources: Option<&Object>, ctm: Matrix, depth: usize) {
+        let mut state = GraphicsState {
+            ctm,
+            font: Rc::new(Font::fallback()),
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        };
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut text_matrix = Matrix::IDENTITY;
+        let mut line_matrix = Matrix::IDENTITY;
+        let mut operands: Vec<Object> = Vec::new();
+        let mut lexer = Lexer::new(content, 0);
+
+        while let Some(object) = lexer.object(false) {
+            let Object::Operator(op) = object else {
+                operands.push(object);
+                continue;
+            };
+            let number = |idx: usize| {
+                operands
+                    .len()
+                    .checked_sub(idx + 1)
+                    .and_then(|idx| operands[idx].as_number())
+                    .unwrap_or(0.0)
+            };
+
+            match op.as_str() {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(saved) = stack.pop() {
+                        state = saved;
+                    }
+                }
+                "cm" => {
+                    if let Some(matrix) = Matrix::from_operands(&operands) {
+                        state.ctm = matrix.then(&state.ctm);
+                    }
+                }
+                "BT" => {
+                    text_matrix = Matrix::IDENTITY;
+                    line_matrix = Matrix::IDENTITY;
+                }
+                "Tf" => {
+                    state.font_size = number(0);
+                    if let Some(Object::Name(name)) = operands.iter().rev().nth(1) {
+                        state.font = self.font(resources, name);
+                    }
+                }
+                "Tc" => state.char_spacing = number(0),
+                "Tw" => state.word_spacing = number(0),
+                "Tz" => state.horizontal_scale = number(0) / 100.0,
+                "TL" => state.leading = number(0),
+                "Ts" => state.rise = number(0),
+                "Td" | "TD" => {
+                    if op == "TD" {
+                        state.leading = -number(0);
+                    }
+                    line_matrix = Matrix::translate(number(1), number(0)).then(&line_matrix);
+                    text_matrix = line_matrix;
+                }
+                "Tm" => {
+                    if let Some(matrix) = Matrix::from_operands(&operands) {
+                        line_matrix = matrix;
+                        text_matrix = matrix;
+                    }
+                }
+                "T*" | "'" | "\"" => {
+                    if op == "\"" {
+                        state.word_spacing = number(2);
+                        state.char_spacing = number(1);
+                    }
+                    line_matrix = Matrix::translate(0.0, -state.leading).then(&line_matrix);
+                    text_matrix = line_matrix;
+                    if op != "T*"
+                        && let Some(Object::String(bytes)) = operands.last()
+                    {
+                        self.show(&state, &mut text_matrix, &[Object::String(bytes.clone())]);
+                    }
+                }
+                "Tj" => {
+                    if let Some(Object::String(bytes)) = operands.last() {
+                        self.show(&state, &mut text_matrix, &[Object::String(bytes.clone())]);
+                    }
+                }
+                "TJ" => {
+                    if let Some(Object::Array(items)) = operands.last() {
+                        let items = items.clone();
+                        self.show(&state, &mut text_matrix, &items);
+                    }
+                }
+                "Do" if depth < 8 => {
+                    if let Some(Object::Name(name)) = operands.last() {
+                        self.form(resources, name, &state, depth);
+                    }
+                }
+                "ID" => {
+                    // Skips inline image data up to `EI`
+                    let mut from = lexer.pos;
+                    lexer.pos = content.len();
+                    while let Some(idx) = find(content, b"EI", from) {
+                        from = idx + 2;
+                        if is_whitespace(content[idx - 1])
+                            && content.get(from).is_none_or(|b| is_whitespace(*b))
+                        {
+                            lexer.pos = from;
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+    }
+
+    /// Runs a form XObject's content with its own resources and matrix
+    fn form(
+        &mut self,
+        resources: Option<&Object>,
+        name: &str,
+        state: &GraphicsState,
+        depth: usize,
+    ) {
+        let doc = self.doc;
+        let Some(Object::Stream(dict, data)) = resources
+            .and_then(Object::as_dict)
+            .and_then(|resources| doc.get_dict(resources, "XObject"))
+            .and_then(|xobjects| doc.get(xobjects, name))
+        else {
+            return;
+        };
+        if doc.get(dict, "Subtype").and_then(Object::as_name) != Some("Form") {
+            return;
+        }
+        let Some(content) = decode_stream(dict, data) else {
+            return;
+        };
+
+        let matrix = doc
+            .get(dict, "Matrix")
+            .and_then(|matrix| match matrix {
+                Object::Array(items) => Matrix::from_operands(items),
+                _ => None,
+            })
+            .unwrap_or(Matrix::IDENTITY);
+        let form_resources = doc.get(dict, "Resources").or(resources);
+        self.run(&content, form_resources, matrix.then(&state.ctm), depth + 1);
+    }
+
+    /// Records one span for a `Tj`, `'`, `"` or `TJ` and advances the text matrix
+    fn show(&mut self, state: &GraphicsState, text_matrix: &mut Matrix, items: &[Object]) {
+        let rendering = Matrix([
+            state.font_size * state.horizontal_scale,
+            0.0,
+            0.0,
+            state.font_size,
+            0.0,
+            state.rise,
+        ])
+        .then(text_matrix)
+        .then(&state.ctm);
+        let start = *text_matrix;
+
+        let mut text = String::new();
+        let mut advance = 0.0;
+        for item in items {
+            match item {
+                Object::String(bytes) => {
+                    for (code, chars, width) in state.font.decode(bytes) {
+                        text.push_str(&chars);
+                        let spacing = if code == 32 && !state.font.composite {
+                            state.word_spacing
+                        } else {
+                            0.0
+                        };
+                        advance +=
+                            (width / 1000.0 * state.font_size + state.char_spacing + spacing)
+                                * state.horizontal_scale;
+                    }
+                }
+                Object::Number(adjustment) => {
+                    // Large negative adjustments are how many writers draw spaces
+                    if *adjustment < -200.0 && !text.is_empty() && !text.ends_with(' ') {
+                        text.push(' ');
+                    }
+                    advance -= adjustment / 1000.0 * state.font_size * state.horizontal_scale;
+                }
+                _ => {}
+            }
+        }
+        *text_matrix = Matrix::translate(advance, 0.0).then(text_matrix);
+
+        if text.is_empty() {
+            return;
+        }
+        let user = start.then(&state.ctm);
+        let scale_x = user.0[0].hypot(user.0[1]);
+        self.spans.push(TextSpan {
+            text,
+            x: rendering.0[4],
+            y: rendering.0[5],
+            width: advance * scale_x,
+            font_size: rendering.0[2].hypot(rendering.0[3]),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::ZlibEncoder};
+
+    use super::*;
+    use crate::csv::{CsvReader, RowMatch};
+
+    /// Writes objects 1.. into a PDF with a cross reference table
+    fn pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::new();
+        for (idx, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend(format!("{} 0 obj\n", idx + 1).as_bytes());
+            out.extend(object);
+            out.extend(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+        out
+    }
+
+    fn stream(dict: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+        out.extend(data);
+        out.extend(b"\nendstream");
+        out
+    }
+
+    fn statement() -> Vec<u8> {
+        let page_one = br"BT /F1 12 Tf 72 720 Td (Statement March 2024) Tj ET
+BT /F1 10 Tf 72 690 Td (Date) Tj 100 0 Td (Description) Tj 200 0 Td (Amount) Tj ET
+BT /F1 10 Tf 1 0 0 1 72 675 Tm (2024-03-01) Tj 1 0 0 1 172 675 Tm [(Caf) 20 (\351 au lait)] TJ
+1 0 0 1 372 675 Tm (-3.50) Tj ET
+BT /F1 10 Tf 72 660 Td (2024-03-02) Tj 100 0 Td [(Salary) -3000 (March)] TJ 200 0 Td (2,500.00) Tj ET
+q 1 0 0 1 0 -20 cm /Fm1 Do Q";
+        let mut page_two = ZlibEncoder::new(Vec::new(), Compression::default());
+        page_two
+            .write_all(b"BT /F2 11 Tf 2 0 0 2 50 100 Tm <00480049> Tj ET")
+            .unwrap();
+        let page_two = page_two.finish().unwrap();
+        let cmap = b"/CIDInit /ProcSet findresource begin 12 dict begin begincmap
+1 begincodespacerange <0000> <FFFF> endcodespacerange
+1 beginbfchar <0003> <0020> endbfchar
+1 beginbfrange <0041> <005A> <0041> endbfrange
+endcmap CMapName currentdict /CMap defineresource pop end end";
+
+        pdf(&[
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R 6 0 R] /Count 2 /MediaBox [0 0 612 792]
+/Resources << /Font << /F1 4 0 R /F2 7 0 R >> /XObject << /Fm1 10 0 R >> >> >>"
+                .to_vec(),
+            b"<< /Type /Page /Parent 2 0 R /Contents 5 0 R >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+            stream("", page_one),
+            b"<< /Type /Page /Parent 2 0 R /Contents 8 0 R /MediaBox [0 0 595 842] >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type0 /BaseFont /Custom /Encoding /Identity-H
+/DescendantFonts [11 0 R] /ToUnicode 9 0 R >>"
+                .to_vec(),
+            stream("/Filter /FlateDecode", &page_two),
+            stream("", cmap),
+            stream(
+                "/Type /XObject /Subtype /Form /BBox [0 0 612 792] /Resources << /Font << /F1 4 0 R >> >>",
+                b"BT /F1 10 Tf 72 660 Td (Closing balance) Tj ET",
+            ),
+            b"<< /Type /Font /Subtype /CIDFontType2 /BaseFont /Custom /DW 600 /W [72 [700 300]] >>"
+                .to_vec(),
+        ])
+    }
+
+    #[test]
+    fn test_page_text() {
+        let pdf = Pdf::parse(&statement()).unwrap();
+        assert_eq!(pdf.pages().len(), 2);
+
+        let page = &pdf.pages()[0];
+        assert_eq!((page.width, page.height), (612.0, 792.0));
+        assert_eq!(
+            page.text(),
+            "Statement March 2024\nDate Description Amount\n2024-03-01 Café au lait -3.50\n2024-03-02 Salary March 2,500.00\nClosing balance"
+        );
+
+        let description = page
+            .spans
+            .iter()
+            .find(|span| span.text == "Description")
+            .unwrap();
+        assert_eq!((description.x, description.y), (172.0, 690.0));
+        assert_eq!(description.font_size, 10.0);
+        // The form is drawn 20 units lower than its own content
+        assert_eq!(page.lines().last().unwrap().y, 640.0);
+
+        let page = &pdf.pages()[1];
+        assert_eq!((page.width, page.height), (595.0, 842.0));
+        assert_eq!(page.text(), "HI");
+        assert_eq!(page.spans[0].font_size, 22.0);
+        assert_eq!(page.spans[0].x, 50.0);
+        assert_eq!(page.spans[0].width, 22.0);
+        assert_eq!(pdf.text(), format!("{}\n\nHI", pdf.pages()[0].text()));
+    }
+
+    #[test]
+    fn test_table() {
+        let pdf = Pdf::parse(&statement()).unwrap();
+        let rows = pdf.pages()[0].table(&[150.0, 350.0]);
+        assert_eq!(
+            rows,
+            vec![
+                vec!["Statement March 2024", "", ""],
+                vec!["Date", "Description", "Amount"],
+                vec!["2024-03-01", "Café au lait", "-3.50"],
+                vec!["2024-03-02", "Salary March", "2,500.00"],
+                vec!["Closing balance", "", ""],
+            ]
+        );
+
+        let table = CsvReader::new()
+            .header(RowMatch::columns(["Date", "Amount"]))
+            .footer(RowMatch::StartsWith("Closing".to_string()))
+            .read_rows(rows)
+            .unwrap();
+        assert_eq!(table.preamble, vec![vec!["Statement March 2024", "", ""]]);
+        assert_eq!(table.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_object_stream() {
+        let objects =
+            b"1 0 2 34 << /Type /Catalog /Pages 2 0 R >> << /Type /Pages /Kids [3 0 R] /Count 1 >>";
+        let mut compressed = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressed.write_all(objects).unwrap();
+        let compressed = compressed.finish().unwrap();
+
+        let mut bytes = b"%PDF-1.5\n".to_vec();
+        bytes.extend(b"3 0 obj\n<< /Type /Page /Parent 2 0 R /Contents 4 0 R >>\nendobj\n");
+        bytes.extend(b"4 0 obj\n");
+        bytes.extend(stream("", b"BT /F9 9 Tf 10 10 Td (Compressed) Tj ET"));
+        bytes.extend(b"\nendobj\n5 0 obj\n");
+        bytes.extend(stream(
+            "/Type /ObjStm /N 2 /First 9 /Filter /FlateDecode",
+            &compressed,
+        ));
+        bytes.extend(b"\nendobj\n6 0 obj\n");
+        bytes.extend(stream("/Type /XRef /Root 1 0 R /Size 7", b""));
+        bytes.extend(b"\nendobj\nstartxref\n0\n%%EOF\n");
+
+        let pdf = Pdf::parse(&bytes).unwrap();
+        assert_eq!(pdf.text(), "Compressed");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Pdf::parse(b"not a pdf").unwrap_err().to_string(),
+            "Data is not a PDF"
+        );
+        let encrypted = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog >>\nendobj\ntrailer\n<< /Root 1 0 R /Encrypt 2 0 R >>\n";
+        assert_eq!(
+            Pdf::parse(encrypted).unwrap_err().to_string(),
+            "Encrypted PDFs are not supported"
+        );
+    }
+
+    #[test]
+    fn test_malformed_objects() {
+        for data in [
+            &b"%PDF-1.4\n1 0 obj\n<< /A << /B 1"[..],
+            b"%PDF-1.4\n1 0 obj\n<< /A [1 2",
+            b"%PDF-1.4\n1 0 obj\n<< /A",
+            b"%PDF-1.4\n1 0 obj\n<<",
+            b"%PDF-1.4\ntrailer\n<< /Root",
+        ] {
+            assert!(Pdf::parse(data).is_err());
+        }
+
+        let mut lexer = Lexer::new(b"<< /A [1 2", 0);
+        assert_eq!(lexer.object(true), None);
+        let mut lexer = Lexer::new(b"] ] > } << /A [1 (x)] >>", 0);
+        assert!(matches!(lexer.object(true), Some(Object::Dict(dict)) if dict.len() == 1));
+        assert_eq!(Lexer::new(b"1", 5).object(true), None);
+    }
+
+    #[test]
+    fn test_untrusted_lengths() {
+        let mut bytes = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n".to_vec();
+        bytes.extend(b"2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n");
+        bytes.extend(b"3 0 obj\n<< /Type /Page /Parent 2 0 R /Contents 4 0 R >>\nendobj\n");
+        bytes.extend(b"4 0 obj\n<< /Length 18446744073709551615 >>\nstream\n");
+        bytes.extend(b"BT /F9 9 Tf 10 10 Td (Length) Tj ET\nendstream\nendobj\n");
+        bytes.extend(b"5 0 obj\n");
+        bytes.extend(stream(
+            "/Type /ObjStm /N 1 /First 18446744073709551615",
+            b"6 0",
+        ));
+        bytes.extend(b"\nendobj\ntrailer\n<< /Root 1 0 R >>\n");
+        let pdf = Pdf::parse(&bytes).unwrap();
+        assert_eq!(pdf.text(), "Length");
+
+        let mut compressed = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressed.write_all(&[2, 1, 2, 1, 2]).unwrap();
+        let compressed = compressed.finish().unwrap();
+        for (params, expected) in [
+            ("/Columns 18446744073709551615", None),
+            ("/Colors 4294967296 /BitsPerComponent 4294967296", None),
+            ("/Columns 1000000000", Some(vec![1, 2, 1, 2, 0])),
+        ] {
+            let dict = format!(
+                "<< /Filter /FlateDecode /DecodeParms << /Predictor 12 {} >> >>",
+                params
+            );
+            let Some(Object::Dict(dict)) = Lexer::new(dict.as_bytes(), 0).object(false) else {
+                panic!("{} is not a dictionary", dict);
+            };
+            assert_eq!(decode_stream(&dict, &compressed), expected, "{}", params);
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Lexer::new(nested.as_bytes(), 0).object(false).is_some());
+
+        let data = format!("%PDF-1.4\n1 0 obj\n{}\nendobj\n", "[".repeat(10_000));
+        assert!(Pdf::parse(data.as_bytes()).is_err());
+        let too_deep = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        assert_eq!(Lexer::new(too_deep.as_bytes(), 0).object(false), None);
+    }
+}
@@ -0,0 +1,700 @@
+use anyhow::{Result, anyhow, bail};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::inputs::{Effective, EntryInput, LineInput, ResourceSelector, TagSelector};
+use crate::statement::parse_amount;
+
+/// Tag type used for QIF categories
+pub const CATEGORY_TAG_TYPE: &str = "Category";
+
+/// A parser for Quicken Interchange Format (QIF) exports.
+///
+/// Bank, cash, credit card and asset/liability transactions are read, together with the
+/// `!Account` records that name them. Category, class, memorized and investment lists
+/// are skipped.
+pub struct Qif;
+
+/// The order of the day and month in QIF dates, which depends on the locale of the
+/// program that wrote the file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QifDateOrder {
+    /// Picks day first if any date in the file only makes sense that way, and month
+    /// first otherwise
+    #[default]
+    Auto,
+    /// `MM/DD/YY`, as written by US versions of Quicken
+    MonthFirst,
+    /// `DD/MM/YY`
+    DayFirst,
+}
+
+impl Qif {
+    /// Parses a QIF file, detecting the order of the day and month in its dates
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transaction has no date or amount, or a date or amount is
+    /// malformed
+    pub fn parse(bytes: &[u8]) -> Result<Vec<QifAccount>> {
+        Self::parse_with_date_order(bytes, QifDateOrder::Auto)
+    }
+
+    /// Parses a QIF file with a known order of the day and month
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transaction has no date or amount, or a date or amount is
+    /// malformed
+    pub fn parse_with_date_order(bytes: &[u8], order: QifDateOrder) -> Result<Vec<QifAccount>> {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => bytes.iter().map(|&b| b as char).collect(),
+        };
+        let sections = Section::split(text.trim_start_matches('\u{feff}'))?;
+
+        let order = match order {
+            QifDateOrder::Auto => Self::detect_date_order(&sections),
+            order => order,
+        };
+
+        let mut accounts = Vec::new();
+        for section in sections {
+            let SectionKind::Transactions(account_type) = section.kind else {
+                continue;
+            };
+            let (name, description) = section
+                .account
+                .map(|account| (account.field('N'), account.field('D')))
+                .unwrap_or_default();
+
+            let mut transactions: Vec<QifTransaction> = Vec::new();
+            for record in &section.records {
+                let mut transaction = QifTransaction::from_record(record, order)?;
+                let position = transactions
+                    .iter()
+                    .filter(|t| t.date == transaction.date)
+                    .count()
+                    + 1;
+                transaction.source_key = match &name {
+                    Some(name) => format!("{}/{}/{}", name, transaction.date, position),
+                    None => format!("{}/{}", transaction.date, position),
+                };
+                transactions.push(transaction);
+            }
+
+            accounts.push(QifAccount {
+                name,
+                account_type,
+                description,
+                transactions,
+            });
+        }
+        Ok(accounts)
+    }
+
+    fn detect_date_order(sections: &[Section]) -> QifDateOrder {
+        let dates = sections
+            .iter()
+            .filter(|section| matches!(section.kind, SectionKind::Transactions(_)))
+            .flat_map(|section| &section.records)
+            .filter_map(|record| record.field('D'));
+
+        for date in dates {
+            if let Some((first, second, _)) = date_parts(&date)
+                && first > 12
+                && second <= 12
+            {
+                return QifDateOrder::DayFirst;
+            }
+        }
+        QifDateOrder::MonthFirst
+    }
+}
+
+/// The transactions of one account. Files without `!Account` records have a single
+/// unnamed account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QifAccount {
+    pub name: Option<String>,
+    /// The `!Type:` of the transactions, for example `Bank`, `CCard` or `Cash`
+    pub account_type: String,
+    pub description: Option<String>,
+    pub transactions: Vec<QifTransaction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QifTransaction {
+    pub date: NaiveDate,
+    /// Positive amounts are paid into the account
+    pub amount: Decimal,
+    /// `*` or `c` when cleared, `X` or `R` when reconciled
+    pub cleared: Option<String>,
+    /// Check or reference number (`N`)
+    pub number: Option<String>,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+    pub address: Vec<String>,
+    pub category: Option<QifCategory>,
+    pub splits: Vec<QifSplit>,
+    /// QIF has no transaction ids, so this is the account name, the date and the
+    /// position of the transaction among those on the same date
+    pub source_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QifSplit {
+    pub category: Option<QifCategory>,
+    pub memo: Option<String>,
+    pub amount: Decimal,
+}
+
+/// The `L` or `S` field of a transaction, `Category:Subcategory/Class` or
+/// `[Transfer account]/Class`
+#[derive(Debug, Clone, PartialEq)]
+pub enum QifCategory {
+    Category {
+        name: String,
+        class: Option<String>,
+    },
+    Transfer {
+        account: String,
+        class: Option<String>,
+    },
+}
+
+impl QifCategory {
+    fn parse(s: &str) -> Option<Self> {
+        let (name, class) = match s.split_once('/') {
+            Some((name, class)) => (name.trim(), Some(class.trim().to_string())),
+            None => (s.trim(), None),
+        };
+        let class = class.filter(|class| !class.is_empty());
+
+        if let Some(account) = name.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            Some(Self::Transfer {
+                account: account.to_string(),
+                class,
+            })
+        } else if name.is_empty() {
+            None
+        } else {
+            Some(Self::Category {
+                name: name.to_string(),
+                class,
+            })
+        }
+    }
+
+    /// A [`TagSelector::SelectOrCreate`] of [`CATEGORY_TAG_TYPE`] for categories. Transfers
+    /// have no tag.
+    pub fn tag(&self) -> Option<TagSelector> {
+        match self {
+            Self::Category { name, .. } => Some(TagSelector::SelectOrCreate {
+                tag_type: CATEGORY_TAG_TYPE.to_string(),
+                source_key: name.clone(),
+                name: Some(name.clone()),
+                data_type: None,
+            }),
+            Self::Transfer { .. } => None,
+        }
+    }
+}
+
+impl QifTransaction {
+    fn from_record(record: &Record, order: QifDateOrder) -> Result<Self> {
+        let date = record
+            .field('D')
+            .ok_or_else(|| anyhow!("QIF transaction has no date"))?;
+        let amount = record
+            .field('T')
+            .or_else(|| record.field('U'))
+            .ok_or_else(|| anyhow!("QIF transaction on {} has no amount", date))?;
+
+        let mut splits: Vec<QifSplit> = Vec::new();
+        for (code, value) in &record.fields {
+            match code {
+                'S' => splits.push(QifSplit {
+                    category: QifCategory::parse(value),
+                    memo: None,
+                    amount: Decimal::ZERO,
+                }),
+                'E' => {
+                    if let Some(split) = splits.last_mut() {
+                        split.memo = Some(value.clone()).filter(|memo| !memo.is_empty());
+                    }
+                }
+                '$' => {
+                    if let Some(split) = splits.last_mut() {
+                        split.amount = parse_amount(value, "QIF")?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            date: parse_date(&date, order)?,
+            amount: parse_amount(&amount, "QIF")?,
+            cleared: record.field('C'),
+            number: record.field('N'),
+            payee: record.field('P'),
+            memo: record.field('M'),
+            address: record
+                .fields
+                .iter()
+                .filter(|(code, _)| *code == 'A')
+                .map(|(_, value)| value.clone())
+                .collect(),
+            category: record.field('L').and_then(|l| QifCategory::parse(&l)),
+            splits,
+            source_key: String::new(),
+        })
+    }
+
+    fn description(&self) -> Option<String> {
+        match (&self.payee, &self.memo) {
+            (Some(payee), Some(memo)) => Some(format!("{} - {}", payee, memo)),
+            (Some(payee), None) => Some(payee.clone()),
+            (None, memo) => memo.clone(),
+        }
+    }
+
+    /// Maps the transaction to an [`EntryInput`] keyed by [`QifTransaction::source_key`].
+    ///
+    /// A transaction without splits is balanced against `offset` with its category as a
+    /// tag. A split transaction has one line for the full amount on `account` and one
+    /// line on `offset` per split, tagged with the split's category and described by its
+    /// memo. Any difference between the splits and the total goes on an untagged
+    /// `offset` line.
+    pub fn to_entry_input(
+        &self,
+        entry_type: String,
+        account: ResourceSelector,
+        offset: ResourceSelector,
+    ) -> EntryInput {
+        let description = self.description();
+        let lines = if self.splits.is_empty() {
+            let tags = self.category.iter().filter_map(QifCategory::tag).collect();
+            LineInput::transfer(account, offset, self.amount, description, tags)
+        } else {
+            let (debit, credit) = debit_credit(self.amount);
+            let mut lines = vec![LineInput::new(
+                account,
+                debit,
+                credit,
+                Decimal::ONE,
+                description.clone(),
+                Vec::new(),
+            )];
+
+            for split in &self.splits {
+                let (debit, credit) = debit_credit(split.amount);
+                lines.push(LineInput::new(
+                    offset.clone(),
+                    credit,
+                    debit,
+                    Decimal::ONE,
+                    split.memo.clone().or(description.clone()),
+                    split.category.iter().filter_map(QifCategory::tag).collect(),
+                ));
+            }
+
+            let remainder = self.amount - self.splits.iter().map(|s| s.amount).sum::<Decimal>();
+            if !remainder.is_zero() {
+                let (debit, credit) = debit_credit(remainder);
+                lines.push(LineInput::new(
+                    offset,
+                    credit,
+                    debit,
+                    Decimal::ONE,
+                    description,
+                    Vec::new(),
+                ));
+            }
+            lines
+        };
+
+        EntryInput::new(
+            Effective::Date(self.date),
+            self.source_key.clone(),
+            entry_type,
+            lines,
+        )
+    }
+}
+
+fn debit_credit(amount: Decimal) -> (Decimal, Decimal) {
+    if amount.is_sign_negative() {
+        (Decimal::ZERO, amount.abs())
+    } else {
+        (amount, Decimal::ZERO)
+    }
+}
+
+enum SectionKind {
+    Account,
+    Transactions(String),
+    /// Category, class, memorized transaction, price and investment lists
+    Other,
+}
+
+struct Section {
+    kind: SectionKind,
+    /// The last `!Account` record before a transaction section
+    account: Option<Record>,
+    records: Vec<Record>,
+}
+
+#[derive(Default)]
+struct Record {
+    fields: Vec<(char, String)>,
+}
+
+impl Record {
+    fn field(&self, code: char) -> Option<String> {
+        self.fields
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+impl Section {
+    fn split(text: &str) -> Result<Vec<Section>> {
+        let mut sections: Vec<Section> = Vec::new();
+        let mut record = Record::default();
+        let mut account: Option<Record> = None;
+
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('!') {
+                let header = header.trim();
+                let kind = if header.eq_ignore_ascii_case("Account") {
+                    SectionKind::Account
+                } else if let Some(account_type) = header
+                    .get(..5)
+                    .filter(|prefix| prefix.eq_ignore_ascii_case("Type:"))
+                    .map(|_| header[5..].trim())
+                {
+                    match account_type.to_ascii_lowercase().as_str() {
+                        "bank" | "cash" | "ccard" | "oth a" | "oth l" => {
+                            SectionKind::Transactions(account_type.to_string())
+                        }
+                        _ => SectionKind::Other,
+                    }
+                } else {
+                    // `!Option:AutoSwitch` and `!Clear:AutoSwitch` only bracket the
+                    // account list
+                    continue;
+                };
+
+                let account = match kind {
+                    SectionKind::Transactions(_) => account.take(),
+                    _ => None,
+                };
+                sections.push(Section {
+                    kind,
+                    account,
+                    records: Vec::new(),
+                });
+                continue;
+            }
+
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| anyhow!("QIF data has no !Type header"))?;
+            if line.starts_with('^') {
+                let record = std::mem::take(&mut record);
+                match section.kind {
+                    SectionKind::Account => account = Some(record),
+                    SectionKind::Transactions(_) if !record.fields.is_empty() => {
+                        section.records.push(record)
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let mut chars = line.chars();
+            let Some(code) = chars.next() else {
+                continue;
+            };
+            record
+                .fields
+                .push((code, chars.as_str().trim().to_string()));
+        }
+
+        if !record.fields.is_empty()
+            && let Some(Section {
+                kind: SectionKind::Transactions(_),
+                records,
+                ..
+            }) = sections.last_mut()
+        {
+            records.push(record);
+        }
+
+        if sections.is_empty() {
+            bail!("QIF data has no !Type header");
+        }
+        Ok(sections)
+    }
+}
+
+/// Splits a date into its two leading numbers and the year. Quicken writes years from
+/// 2000 as `'YY`, as in `1/ 2'24`, and two digit years before 70 are taken as 20YY.
+fn date_parts(s: &str) -> Option<(u32, u32, i32)> {
+    let apostrophe = s.contains('\'');
+    let parts: Vec<&str> = s
+        .split(['/', '-', '.', '\''])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+    let [first, second, year] = parts.as_slice() else {
+        return None;
+    };
+
+    // ISO dates have the year first
+    if first.len() == 4 {
+        return Some((
+            second.parse().ok()?,
+            year.parse().ok()?,
+            first.parse().ok()?,
+        ));
+    }
+
+    let mut year: i32 = year.parse().ok()?;
+    if year < 100 {
+        year += if apostrophe || year < 70 { 2000 } else { 1900 };
+    }
+    Some((first.parse().ok()?, second.parse().ok()?, year))
+}
+
+fn parse_date(s: &str, order: QifDateOrder) -> Result<NaiveDate> {
+    let invalid = || anyhow!("Invalid QIF date {}", s);
+    let (first, second, year) = date_parts(s).ok_or_else(invalid)?;
+
+    let iso = s
+        .split(['/', '-', '.'])
+        .next()
+        .is_some_and(|part| part.trim().len() == 4);
+    let (month, day) = if iso || order != QifDateOrder::DayFirst {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const QIF: &str = "!Option:AutoSwitch
+!Account
+NChecking
+TBank
+^
+NSavings
+TBank
+^
+!Clear:AutoSwitch
+!Account
+NChecking
+DMain account
+TBank
+^
+!Type:Bank
+D1/ 2'24
+T-1,234.56
+CX
+N1001
+PLandlord
+MRent January
+LHousing:Rent
+^
+D1/15'24
+T-150.00
+PSupermarket
+LFood
+SFood:Groceries
+EWeekly shop
+$-120.00
+SHousehold/Home
+$-25.00
+^
+D1/15'24
+U500.00
+PSavings transfer
+L[Savings]
+^
+!Type:Cat
+NFood
+E
+^
+";
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_qif() {
+        let accounts = Qif::parse(QIF.as_bytes()).unwrap();
+        assert_eq!(accounts.len(), 1);
+
+        let account = &accounts[0];
+        assert_eq!(account.name.as_deref(), Some("Checking"));
+        assert_eq!(account.description.as_deref(), Some("Main account"));
+        assert_eq!(account.account_type, "Bank");
+        assert_eq!(account.transactions.len(), 3);
+
+        let rent = &account.transactions[0];
+        assert_eq!(rent.date, date(2024, 1, 2));
+        assert_eq!(rent.amount, dec!(-1234.56));
+        assert_eq!(rent.cleared.as_deref(), Some("X"));
+        assert_eq!(rent.number.as_deref(), Some("1001"));
+        assert_eq!(
+            rent.category,
+            Some(QifCategory::Category {
+                name: "Housing:Rent".to_string(),
+                class: None
+            })
+        );
+        assert_eq!(rent.source_key, "Checking/2024-01-02/1");
+
+        let groceries = &account.transactions[1];
+        assert_eq!(
+            groceries.splits,
+            vec![
+                QifSplit {
+                    category: Some(QifCategory::Category {
+                        name: "Food:Groceries".to_string(),
+                        class: None
+                    }),
+                    memo: Some("Weekly shop".to_string()),
+                    amount: dec!(-120.00),
+                },
+                QifSplit {
+                    category: Some(QifCategory::Category {
+                        name: "Household".to_string(),
+                        class: Some("Home".to_string())
+                    }),
+                    memo: None,
+                    amount: dec!(-25.00),
+                },
+            ]
+        );
+
+        let transfer = &account.transactions[2];
+        assert_eq!(transfer.amount, dec!(500.00));
+        assert_eq!(
+            transfer.category,
+            Some(QifCategory::Transfer {
+                account: "Savings".to_string(),
+                class: None
+            })
+        );
+        assert_eq!(transfer.source_key, "Checking/2024-01-15/2");
+    }
+
+    #[test]
+    fn test_split_entry_input() {
+        let accounts = Qif::parse(QIF.as_bytes()).unwrap();
+        let account = ResourceSelector::SourceKey {
+            resource_type: "BankAccount".to_string(),
+            source_key: "checking".to_string(),
+        };
+        let offset = ResourceSelector::SourceKey {
+            resource_type: "Expense".to_string(),
+            source_key: "spending".to_string(),
+        };
+
+        let input = accounts[0].transactions[1].to_entry_input(
+            "BankTransaction".to_string(),
+            account.clone(),
+            offset.clone(),
+        );
+        assert_eq!(input.effective, Effective::Date(date(2024, 1, 15)));
+        assert_eq!(input.lines.len(), 4);
+        assert_eq!(input.lines[0].credit, dec!(150.00));
+        assert_eq!(input.lines[1].debit, dec!(120.00));
+        assert_eq!(input.lines[1].description.as_deref(), Some("Weekly shop"));
+        assert_eq!(
+            input.lines[1].tags,
+            vec![TagSelector::SelectOrCreate {
+                tag_type: CATEGORY_TAG_TYPE.to_string(),
+                source_key: "Food:Groceries".to_string(),
+                name: Some("Food:Groceries".to_string()),
+                data_type: None,
+            }]
+        );
+        assert_eq!(input.lines[2].debit, dec!(25.00));
+        // The splits leave 5.00 unassigned
+        assert_eq!(input.lines[3].debit, dec!(5.00));
+        assert!(input.lines[3].tags.is_empty());
+
+        let total_debit: Decimal = input.lines.iter().map(|line| line.debit).sum();
+        let total_credit: Decimal = input.lines.iter().map(|line| line.credit).sum();
+        assert_eq!(total_debit, total_credit);
+
+        let rent = accounts[0].transactions[0].to_entry_input(
+            "BankTransaction".to_string(),
+            account,
+            offset,
+        );
+        assert_eq!(rent.lines.len(), 2);
+        assert_eq!(
+            rent.lines[0].description.as_deref(),
+            Some("Landlord - Rent January")
+        );
+        assert_eq!(rent.lines[0].tags.len(), 1);
+    }
+
+    #[test]
+    fn test_date_orders() {
+        let qif = "!Type:CCard\nD03/04/2024\nT-1.00\n^\nD25/04/2024\nT-2,50\n^\n";
+        let accounts = Qif::parse(qif.as_bytes()).unwrap();
+        assert_eq!(accounts[0].name, None);
+        assert_eq!(accounts[0].transactions[0].date, date(2024, 4, 3));
+        assert_eq!(accounts[0].transactions[1].date, date(2024, 4, 25));
+        assert_eq!(accounts[0].transactions[1].amount, dec!(-2.50));
+
+        let accounts =
+            Qif::parse_with_date_order(b"!Type:Bank\nD03/04/24\nT1\n^\n", QifDateOrder::MonthFirst)
+                .unwrap();
+        assert_eq!(accounts[0].transactions[0].date, date(2024, 3, 4));
+
+        let accounts = Qif::parse(b"!Type:Bank\nD2024-03-04\nT1\n^\nD12/31/99\nT1\n^").unwrap();
+        assert_eq!(accounts[0].transactions[0].date, date(2024, 3, 4));
+        assert_eq!(accounts[0].transactions[1].date, date(1999, 12, 31));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Qif::parse(b"D1/1/24\nT1\n^").unwrap_err().to_string(),
+            "QIF data has no !Type header"
+        );
+        assert_eq!(
+            Qif::parse(b"!Type:Bank\nD1/1/24\n^")
+                .unwrap_err()
+                .to_string(),
+            "QIF transaction on 1/1/24 has no amount"
+        );
+        assert_eq!(
+            Qif::parse(b"!Type:Bank\nD13/13/24\nT1\n^")
+                .unwrap_err()
+                .to_string(),
+            "Invalid QIF date 13/13/24"
+        );
+    }
+}
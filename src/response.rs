@@ -1,8 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use extism_pdk::{Json, ToBytes};
 use serde::{Deserialize, Serialize};
 
-use crate::inputs::{EntryInput, ObservationInput, ResourceInput, TagInput};
+use crate::{
+    command::Cron,
+    inputs::{EntryInput, ObservationInput, ResourceInput, TagInput},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToBytes)]
 #[encoding(Json)]
@@ -11,6 +14,156 @@ pub struct ExtractResponse {
     pub end_date: DateTime<Utc>,
 }
 
+/// Reports a run, or one of its windows, as extracted
+impl From<&Cron> for ExtractResponse {
+    fn from(cron: &Cron) -> Self {
+        Self {
+            start_date: cron.from,
+            end_date: cron.until,
+        }
+    }
+}
+
+/// What an extract run did, with the range that was extracted and the optional details
+/// an [`ExtractResponse`] cannot hold. Extract handlers can return either. The JSON is
+/// an `ExtractResponse` with the details that are set written next to the range.
+///
+/// ```
+/// use contour_rust_pdk::{command::Cron, response::ExtractReport};
+///
+/// fn report(cron: &Cron, fetched: u64, skipped: usize) -> ExtractReport {
+///     let report = ExtractReport::from(cron).records_fetched(fetched);
+///     if skipped > 0 {
+///         return report.warning(format!("Skipped {} rows without an amount", skipped));
+///     }
+///     report
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToBytes)]
+#[encoding(Json)]
+pub struct ExtractReport {
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>,
+    /// Set when only part of the requested range was extracted, with `end_date` as far
+    /// as the plugin got. The host runs the command again for the rest of the range,
+    /// passing the token back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
+    /// Records read from the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_fetched: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_upserted: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_deleted: Option<u64>,
+    /// Problems that did not stop the run, such as skipped rows, shown to the user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// When the plugin would like to run next, instead of its usual schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<NextRun>,
+    /// More data is available now, so the host can run the plugin again straight away
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_more: bool,
+}
+
+/// A requested next run time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NextRun {
+    At(DateTime<Utc>),
+    /// Backs off for this many seconds from the end of the run, for example after
+    /// being rate limited
+    After(u64),
+}
+
+impl ExtractReport {
+    pub fn new(start_date: DateTime<Utc>, end_date: DateTime<Utc>) -> Self {
+        Self {
+            start_date,
+            end_date,
+            resume_token: None,
+            records_fetched: None,
+            records_upserted: None,
+            records_deleted: None,
+            warnings: Vec::new(),
+            next_run: None,
+            has_more: false,
+        }
+    }
+
+    /// Reports that extraction stopped at `end_date` and should resume from `token`
+    pub fn partial(start_date: DateTime<Utc>, end_date: DateTime<Utc>, token: String) -> Self {
+        Self {
+            resume_token: Some(token),
+            ..Self::new(start_date, end_date)
+        }
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.resume_token.is_some()
+    }
+
+    pub fn records_fetched(mut self, count: u64) -> Self {
+        self.records_fetched = Some(count);
+        self
+    }
+
+    pub fn records_upserted(mut self, count: u64) -> Self {
+        self.records_upserted = Some(count);
+        self
+    }
+
+    pub fn records_deleted(mut self, count: u64) -> Self {
+        self.records_deleted = Some(count);
+        self
+    }
+
+    pub fn warning(mut self, warning: impl Into<String>) -> Self {
+        self.warnings.push(warning.into());
+        self
+    }
+
+    pub fn next_run_at(mut self, at: DateTime<Utc>) -> Self {
+        self.next_run = Some(NextRun::At(at));
+        self
+    }
+
+    /// Asks the host to wait `backoff` before the next run. Negative durations are
+    /// treated as zero.
+    pub fn backoff(mut self, backoff: TimeDelta) -> Self {
+        self.next_run = Some(NextRun::After(backoff.num_seconds().max(0) as u64));
+        self
+    }
+
+    pub fn has_more(mut self, has_more: bool) -> Self {
+        self.has_more = has_more;
+        self
+    }
+}
+
+impl From<ExtractResponse> for ExtractReport {
+    fn from(response: ExtractResponse) -> Self {
+        Self::new(response.start_date, response.end_date)
+    }
+}
+
+/// Reports a run, or one of its windows, as extracted
+impl From<&Cron> for ExtractReport {
+    fn from(cron: &Cron) -> Self {
+        Self::new(cron.from, cron.until)
+    }
+}
+
+/// The types an extract handler can return, [`ExtractResponse`] and [`ExtractReport`].
+/// The `extract_fn` macro checks the handler's return type against it.
+#[diagnostic::on_unimplemented(
+    message = "extract handlers return `ExtractResponse` or `ExtractReport`, not `{Self}`"
+)]
+pub trait ExtractOutput {}
+
+impl ExtractOutput for ExtractResponse {}
+impl ExtractOutput for ExtractReport {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToBytes)]
 #[encoding(Json)]
 pub enum TransformResponse<I> {
@@ -20,3 +173,77 @@ pub enum TransformResponse<I> {
     ResourceInput(ResourceInput<I>),
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn range() -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            "2024-03-01T00:00:00Z".parse().unwrap(),
+            "2024-03-02T00:00:00Z".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_minimal_json() {
+        let (start, end) = range();
+        let json = serde_json::to_value(ExtractReport::new(start, end)).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "start_date": "2024-03-01T00:00:00Z",
+                "end_date": "2024-03-02T00:00:00Z",
+            })
+        );
+
+        // A report without details is written like a response, and either parses as
+        // the other
+        let response = ExtractResponse {
+            start_date: start,
+            end_date: end,
+        };
+        assert_eq!(serde_json::to_value(&response).unwrap(), json);
+        let report: ExtractReport = serde_json::from_value(json).unwrap();
+        assert_eq!(report, ExtractReport::from(response));
+        assert_eq!(report.records_fetched, None);
+        assert!(!report.has_more);
+    }
+
+    #[test]
+    fn test_full_json() {
+        let (start, end) = range();
+        let report = ExtractReport::new(start, end)
+            .records_fetched(120)
+            .records_upserted(118)
+            .records_deleted(2)
+            .warning("Skipped 2 rows without an amount")
+            .backoff(TimeDelta::minutes(15))
+            .has_more(true);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "start_date": "2024-03-01T00:00:00Z",
+                "end_date": "2024-03-02T00:00:00Z",
+                "records_fetched": 120,
+                "records_upserted": 118,
+                "records_deleted": 2,
+                "warnings": ["Skipped 2 rows without an amount"],
+                "next_run": { "After": 900 },
+                "has_more": true,
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<ExtractReport>(json.clone()).unwrap(),
+            report
+        );
+        let response: ExtractResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(response.end_date, end);
+
+        let report = report.next_run_at(end);
+        assert_eq!(report.next_run, Some(NextRun::At(end)));
+    }
+}
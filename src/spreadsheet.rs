@@ -0,0 +1,629 @@
+use std::{collections::HashMap, io::Cursor};
+
+use anyhow::{Context, Result, anyhow, bail};
+use calamine::{Data, Dimensions, Reader, Sheets, open_workbook_auto_from_rs};
+use chrono::Timelike;
+use roxmltree::Document;
+use serde::de::DeserializeOwned;
+
+use crate::archive::Archive;
+use crate::csv::{CsvReader, CsvTable};
+
+/// A workbook read from the bytes of an XLSX, XLS, XLSB or ODS file.
+///
+/// Every cell is converted to text so sheets can be windowed and deserialized with a
+/// [`CsvReader`], the same way as CSV data:
+///
+/// * Dates stored as serial numbers are written as `YYYY-MM-DD`, or
+///   `YYYY-MM-DDTHH:MM:SS` when they have a time
+/// * Durations are written as `HH:MM:SS`
+/// * Whole numbers are written without a decimal point
+/// * Merged cells repeat their value across the merged area
+/// * Rows and columns start at the first used cell, so leading empty ones are left out
+pub struct Spreadsheet {
+    sheets: Vec<Sheet>,
+}
+
+/// A single worksheet of a [`Spreadsheet`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sheet {
+    pub name: String,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Spreadsheet {
+    /// The most columns a sheet may have
+    pub const MAX_COLUMNS: usize = 1024;
+
+    /// The most cells a sheet may have, counting empty cells between used ones
+    pub const MAX_CELLS: usize = 10_000_000;
+
+    /// Reads all sheets of a workbook. The file format is detected from the contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a supported spreadsheet, a sheet cannot be
+    /// read, or a sheet is larger than [`Spreadsheet::MAX_COLUMNS`] or
+    /// [`Spreadsheet::MAX_CELLS`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut workbook =
+            open_workbook_auto_from_rs(Cursor::new(bytes)).context("Failed to open spreadsheet")?;
+
+        let sheets = workbook
+            .sheet_names()
+            .into_iter()
+            .map(|name| {
+                let range = workbook
+                    .worksheet_range(&name)
+                    .with_context(|| format!("Failed to read sheet {}", name))?;
+                let mut rows = Self::to_rows(&name, &range)?;
+                let origin = range.start().unwrap_or_default();
+                for region in Self::merged_regions(&mut workbook, bytes, &name)? {
+                    Self::fill_merged(&mut rows, origin, region);
+                }
+                Ok(Sheet { name, rows })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { sheets })
+    }
+
+    pub fn sheets(&self) -> &[Sheet] {
+        &self.sheets
+    }
+
+    pub fn sheet_names(&self) -> Vec<&str> {
+        self.sheets
+            .iter()
+            .map(|sheet| sheet.name.as_str())
+            .collect()
+    }
+
+    /// Looks up a sheet by name, ignoring case
+    pub fn sheet(&self, name: &str) -> Option<&Sheet> {
+        self.sheets
+            .iter()
+            .find(|sheet| sheet.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The first sheet, or an error if the workbook has none
+    pub fn first_sheet(&self) -> Result<&Sheet> {
+        self.sheets
+            .first()
+            .ok_or_else(|| anyhow!("Spreadsheet has no sheets"))
+    }
+
+    fn to_rows(name: &str, range: &calamine::Range<Data>) -> Result<Vec<Vec<String>>> {
+        let (height, width) = range.get_size();
+        if width > Self::MAX_COLUMNS {
+            bail!(
+                "Sheet {} has {} columns, more than the limit of {}",
+                name,
+                width,
+                Self::MAX_COLUMNS
+            );
+        }
+        if height.saturating_mul(width) > Self::MAX_CELLS {
+            bail!(
+                "Sheet {} has {} rows of {} columns, more than the limit of {} cells",
+                name,
+                height,
+                width,
+                Self::MAX_CELLS
+            );
+        }
+
+        let mut rows = vec![vec![String::new(); width]; height];
+        for (row, col, cell) in range.used_cells() {
+            rows[row][col] = Self::cell_to_string(cell);
+        }
+        Ok(rows)
+    }
+
+    fn cell_to_string(cell: &Data) -> String {
+        match cell {
+            Data::Int(i) => i.to_string(),
+            Data::Float(f) => f.to_string(),
+            Data::String(s) => s.clone(),
+            Data::Bool(b) => b.to_string(),
+            Data::DateTime(dt) if dt.is_duration() => dt
+                .as_duration()
+                .map(|d| {
+                    let secs = d.num_seconds();
+                    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+                })
+                .unwrap_or_else(|| dt.as_f64().to_string()),
+            Data::DateTime(dt) => match dt.as_datetime() {
+                Some(dt) if dt.num_seconds_from_midnight() == 0 => {
+                    dt.format("%Y-%m-%d").to_string()
+                }
+                Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                None => dt.as_f64().to_string(),
+            },
+            Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+            Data::Error(e) => e.to_string(),
+            Data::Empty => String::new(),
+        }
+    }
+
+    /// The merged regions of a sheet, indexed from `A1`. calamine only reads them for
+    /// XLSX and XLS, so XLSB and ODS files are read here.
+    fn merged_regions(
+        workbook: &mut Sheets<Cursor<&[u8]>>,
+        bytes: &[u8],
+        name: &str,
+    ) -> Result<Vec<Dimensions>> {
+        Ok(match workbook {
+            Sheets::Xlsx(xlsx) => xlsx
+                .worksheet_merge_cells(name)
+                .transpose()
+                .with_context(|| format!("Failed to read merged cells of sheet {}", name))?
+                .unwrap_or_default(),
+            Sheets::Xls(xls) => xls.worksheet_merge_cells(name).unwrap_or_default(),
+            Sheets::Xlsb(_) => xlsb_merged_regions(bytes, name)
+                .with_context(|| format!("Failed to read merged cells of sheet {}", name))?,
+            Sheets::Ods(_) => ods_merged_regions(bytes, name)
+                .with_context(|| format!("Failed to read merged cells of sheet {}", name))?,
+        })
+    }
+
+    /// Copies the top left value of a merged region into the rest of the region.
+    /// `origin` is the position of the first row and column of `rows`.
+    fn fill_merged(rows: &mut [Vec<String>], origin: (u32, u32), region: Dimensions) {
+        let offset = |pos: u32, origin: u32| pos.saturating_sub(origin) as usize;
+        let (start_row, start_col) = (
+            offset(region.start.0, origin.0),
+            offset(region.start.1, origin.1),
+        );
+        let (end_row, end_col) = (
+            offset(region.end.0, origin.0),
+            offset(region.end.1, origin.1),
+        );
+        let Some(value) = rows
+            .get(start_row)
+            .and_then(|row| row.get(start_col))
+            .cloned()
+        else {
+            return;
+        };
+
+        for row in rows.iter_mut().take(end_row + 1).skip(start_row) {
+            for cell in row.iter_mut().take(end_col + 1).skip(start_col) {
+                cell.clone_from(&value);
+            }
+        }
+    }
+}
+
+const TABLE_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:table:1.0";
+
+/// Reads the merged regions of an ODS sheet from the spans of its cells
+fn ods_merged_regions(bytes: &[u8], name: &str) -> Result<Vec<Dimensions>> {
+    let content = Archive::extract_zip_file(bytes, "content.xml", None)?;
+    let text = std::str::from_utf8(&content).context("content.xml is not UTF-8")?;
+    let doc = Document::parse(text).context("Failed to parse content.xml")?;
+    let Some(table) = doc.descendants().find(|node| {
+        node.has_tag_name((TABLE_NS, "table")) && node.attribute((TABLE_NS, "name")) == Some(name)
+    }) else {
+        return Ok(Vec::new());
+    };
+    let count = |node: roxmltree::Node, attribute: &str| {
+        node.attribute((TABLE_NS, attribute))
+            .and_then(|count| count.parse::<u32>().ok())
+            .unwrap_or(1)
+    };
+
+    let mut regions = Vec::new();
+    let mut row = 0u32;
+    // Rows may be grouped, but cells of nested tables belong to those tables
+    let rows = table.descendants().filter(|node| {
+        node.has_tag_name((TABLE_NS, "table-row"))
+            && node
+                .ancestors()
+                .find(|ancestor| ancestor.has_tag_name((TABLE_NS, "table")))
+                == Some(table)
+    });
+    for node in rows {
+        let mut col = 0u32;
+        for cell in node.children().filter(|cell| {
+            cell.has_tag_name((TABLE_NS, "table-cell"))
+                || cell.has_tag_name((TABLE_NS, "covered-table-cell"))
+        }) {
+            let (rows, cols) = (
+                count(cell, "number-rows-spanned"),
+                count(cell, "number-columns-spanned"),
+            );
+            if rows > 1 || cols > 1 {
+                regions.push(Dimensions::new(
+                    (row, col),
+                    (
+                        row.saturating_add(rows.max(1) - 1),
+                        col.saturating_add(cols.max(1) - 1),
+                    ),
+                ));
+            }
+            col = col.saturating_add(count(cell, "number-columns-repeated"));
+        }
+        row = row.saturating_add(count(node, "number-rows-repeated"));
+    }
+    Ok(regions)
+}
+
+/// Reads the merged regions of an XLSB sheet from its `BrtMergeCell` records
+fn xlsb_merged_regions(bytes: &[u8], name: &str) -> Result<Vec<Dimensions>> {
+    let rels = Archive::extract_zip_file(bytes, "xl/_rels/workbook.bin.rels", None)?;
+    let rels = std::str::from_utf8(&rels).context("Workbook relationships are not UTF-8")?;
+    let rels = Document::parse(rels).context("Failed to parse workbook relationships")?;
+    let targets: HashMap<&str, &str> = rels
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Relationship")
+        .filter_map(|node| Some((node.attribute("Id")?, node.attribute("Target")?)))
+        .collect();
+
+    let workbook = Archive::extract_zip_file(bytes, "xl/workbook.bin", None)?;
+    let mut path = None;
+    for (kind, data) in BinaryRecords(&workbook) {
+        match kind {
+            // BrtBundleSh: state, tab id, relationship id and name
+            0x009C => {
+                let mut pos = 8;
+                let (Some(id), Some(sheet)) =
+                    (wide_string(data, &mut pos), wide_string(data, &mut pos))
+                else {
+                    continue;
+                };
+                if sheet == name {
+                    path = targets
+                        .get(id.as_str())
+                        .map(|target| match target.strip_prefix('/') {
+                            Some(target) => target.to_string(),
+                            None => format!("xl/{}", target),
+                        });
+                    break;
+                }
+            }
+            // BrtEndBundleShs
+            0x0090 => break,
+            _ => {}
+        }
+    }
+    let Some(path) = path else {
+        return Ok(Vec::new());
+    };
+
+    let sheet = Archive::extract_zip_file(bytes, &path, None)?;
+    Ok(BinaryRecords(&sheet)
+        .filter(|(kind, _)| *kind == 0x00B0)
+        .filter_map(|(_, data)| {
+            let field = |idx: usize| {
+                data.get(idx * 4..idx * 4 + 4)
+                    .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            };
+            // BrtMergeCell: first row, last row, first column, last column
+            Some(Dimensions::new(
+                (field(0)?, field(2)?),
+                (field(1)?, field(3)?),
+            ))
+        })
+        .collect())
+}
+
+/// Reads a length-prefixed UTF-16 string of an XLSB record, advancing `pos` past it
+fn wide_string(data: &[u8], pos: &mut usize) -> Option<String> {
+    let len = data.get(*pos..*pos + 4)?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let start = *pos + 4;
+    let end = start.checked_add(len.checked_mul(2)?)?;
+    let units: Vec<u16> = data
+        .get(start..end)?
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    *pos = end;
+    String::from_utf16(&units).ok()
+}
+
+/// The records of an XLSB part as `(type, data)`. Types and sizes are stored in
+/// little-endian groups of 7 bits, the high bit marking that another byte follows.
+struct BinaryRecords<'a>(&'a [u8]);
+
+impl<'a> Iterator for BinaryRecords<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut pos = 0;
+        let mut varint = |max_bytes: usize| {
+            let mut value = 0usize;
+            for idx in 0..max_bytes {
+                let byte = *self.0.get(pos)?;
+                pos += 1;
+                value |= usize::from(byte & 0x7F) << (7 * idx);
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            Some(value)
+        };
+        let kind = varint(2)?;
+        let len = varint(4)?;
+        let end = pos.checked_add(len)?;
+        let Some(data) = self.0.get(pos..end) else {
+            self.0 = &[];
+            return None;
+        };
+        self.0 = &self.0[end..];
+        Some((kind as u16, data))
+    }
+}
+
+impl Sheet {
+    /// Applies the window, header and footer of `reader` to this sheet. Returns rows in
+    /// the same shape as [`CsvReader::parse`].
+    pub fn parse(&self, reader: &CsvReader) -> Result<Vec<Vec<String>>> {
+        reader.parse_rows(self.rows.iter().cloned())
+    }
+
+    /// Splits this sheet into its preamble, header, data rows and footer
+    pub fn read(&self, reader: &CsvReader) -> Result<CsvTable> {
+        reader.read_rows(self.rows.iter().cloned())
+    }
+
+    /// Deserializes the data rows of this sheet with [`CsvTable::deserialize`]
+    pub fn deserialize<T: DeserializeOwned>(&self, reader: &CsvReader) -> Result<Vec<T>> {
+        self.read(reader)?.deserialize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::csv::RowMatch;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Transaction {
+        #[serde(rename = "Date")]
+        date: NaiveDate,
+        #[serde(rename = "Description")]
+        description: String,
+        #[serde(rename = "Amount")]
+        amount: Decimal,
+        #[serde(rename = "Reference")]
+        reference: Option<String>,
+    }
+
+    fn statement() -> Vec<u8> {
+        let mut workbook = Workbook::new();
+        let date = Format::new().set_num_format("yyyy-mm-dd");
+        let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+        let sheet = workbook.add_worksheet().set_name("Statement").unwrap();
+        sheet
+            .merge_range(0, 0, 0, 3, "Account 12345", &Format::new())
+            .unwrap();
+        sheet.write(1, 0, "Generated").unwrap();
+        sheet
+            .write_datetime_with_format(
+                1,
+                1,
+                ExcelDateTime::parse_from_str("2024-04-01T08:30:00").unwrap(),
+                &datetime,
+            )
+            .unwrap();
+        for (col, header) in ["Date", "Description", "Amount", "Reference"]
+            .iter()
+            .enumerate()
+        {
+            sheet.write(3, col as u16, *header).unwrap();
+        }
+        for (row, (day, description, amount, reference)) in
+            [(1, "Coffee", -3.5, Some("A1")), (2, "Salary", 2500.0, None)]
+                .into_iter()
+                .enumerate()
+        {
+            let row = row as u32 + 4;
+            sheet
+                .write_datetime_with_format(
+                    row,
+                    0,
+                    ExcelDateTime::from_ymd(2024, 3, day).unwrap(),
+                    &date,
+                )
+                .unwrap();
+            sheet.write(row, 1, description).unwrap();
+            sheet.write(row, 2, amount).unwrap();
+            if let Some(reference) = reference {
+                sheet.write(row, 3, reference).unwrap();
+            }
+        }
+        sheet.write(6, 0, "Total").unwrap();
+        sheet.write(6, 2, 2496.5).unwrap();
+
+        workbook.add_worksheet().set_name("Empty").unwrap();
+        workbook.save_to_buffer().unwrap()
+    }
+
+    #[test]
+    fn test_read_sheets() {
+        let spreadsheet = Spreadsheet::from_bytes(&statement()).unwrap();
+        assert_eq!(spreadsheet.sheet_names(), vec!["Statement", "Empty"]);
+        assert!(spreadsheet.sheet("empty").unwrap().rows.is_empty());
+
+        let sheet = spreadsheet.first_sheet().unwrap();
+        assert_eq!(sheet.rows[0], vec!["Account 12345"; 4]);
+        assert_eq!(sheet.rows[1][1], "2024-04-01T08:30:00");
+        assert_eq!(sheet.rows[4], vec!["2024-03-01", "Coffee", "-3.5", "A1"]);
+        assert_eq!(sheet.rows[5], vec!["2024-03-02", "Salary", "2500", ""]);
+    }
+
+    #[test]
+    fn test_deserialize_sheet() {
+        let spreadsheet = Spreadsheet::from_bytes(&statement()).unwrap();
+        let reader = CsvReader::new()
+            .header(RowMatch::columns(["Date", "Amount"]))
+            .footer(RowMatch::StartsWith("Total".to_string()));
+
+        let table = spreadsheet.first_sheet().unwrap().read(&reader).unwrap();
+        assert_eq!(
+            table.preamble_value("Generated"),
+            Some("2024-04-01T08:30:00")
+        );
+
+        let transactions: Vec<Transaction> = table.deserialize().unwrap();
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                    description: "Coffee".to_string(),
+                    amount: dec!(-3.5),
+                    reference: Some("A1".to_string()),
+                },
+                Transaction {
+                    date: NaiveDate::from_ymd_opt(2024, 3, 2).unwrap(),
+                    description: "Salary".to_string(),
+                    amount: dec!(2500),
+                    reference: None,
+                },
+            ]
+        );
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+        use zip::{ZipWriter, write::SimpleFileOptions};
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_read_ods() {
+        let rows = [
+            r#"<table:table-row table:number-rows-repeated="2"><table:table-cell/></table:table-row>"#,
+            r#"<table:table-row><table:table-cell/>"#,
+            r#"<table:table-cell table:number-columns-spanned="3" office:value-type="string"><text:p>Account 12345</text:p></table:table-cell>"#,
+            r#"<table:covered-table-cell table:number-columns-repeated="2"/></table:table-row>"#,
+            r#"<table:table-row><table:table-cell/>"#,
+            r#"<table:table-cell office:value-type="string"><text:p>Date</text:p></table:table-cell>"#,
+            r#"<table:table-cell office:value-type="string"><text:p>Amount</text:p></table:table-cell>"#,
+            r#"<table:table-cell table:number-rows-spanned="2" office:value-type="string"><text:p>Card</text:p></table:table-cell>"#,
+            r#"</table:table-row><table:table-row><table:table-cell/>"#,
+            r#"<table:table-cell office:value-type="date" office:date-value="2024-03-01"><text:p>2024-03-01</text:p></table:table-cell>"#,
+            r#"<table:table-cell office:value-type="float" office:value="-3.5"><text:p>-3.5</text:p></table:table-cell>"#,
+            r#"<table:covered-table-cell/></table:table-row>"#,
+        ];
+        // calamine expects no whitespace between the cells of a row
+        let content = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:spreadsheet><table:table table:name="Statement">{}</table:table></office:spreadsheet></office:body>
+</office:document-content>"#,
+            rows.concat()
+        );
+        let manifest = br#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0">
+  <manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+  <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>"#;
+        let ods = zip(&[
+            (
+                "mimetype",
+                b"application/vnd.oasis.opendocument.spreadsheet",
+            ),
+            ("META-INF/manifest.xml", manifest),
+            ("content.xml", content.as_bytes()),
+        ]);
+
+        let spreadsheet = Spreadsheet::from_bytes(&ods).unwrap();
+        assert_eq!(
+            spreadsheet.first_sheet().unwrap().rows,
+            vec![
+                vec!["Account 12345"; 3],
+                vec!["Date", "Amount", "Card"],
+                vec!["2024-03-01", "-3.5", "Card"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xlsb_merged_regions() {
+        fn record(kind: u16, data: &[u8]) -> Vec<u8> {
+            let mut out = vec![0x80 | (kind & 0x7F) as u8, (kind >> 7) as u8];
+            out.extend([0x80 | (data.len() & 0x7F) as u8, (data.len() >> 7) as u8]);
+            out.extend(data);
+            out
+        }
+        fn wide(text: &str) -> Vec<u8> {
+            let mut out = (text.len() as u32).to_le_bytes().to_vec();
+            out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            out
+        }
+
+        let mut sheet = [0u32; 2].map(u32::to_le_bytes).concat();
+        sheet.extend(wide("rId1"));
+        sheet.extend(wide("Statement"));
+        let mut workbook = record(0x83, &[]);
+        workbook.extend(record(0x9C, &sheet));
+        workbook.extend(record(0x90, &[]));
+
+        let merge = [0u32, 0, 0, 3].map(u32::to_le_bytes).concat();
+        let mut worksheet = record(0x81, &[0; 200]);
+        worksheet.extend(record(0xB0, &merge));
+        let rels = br#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId1" Type="worksheet" Target="worksheets/sheet1.bin"/>
+</Relationships>"#;
+        let xlsb = zip(&[
+            ("xl/_rels/workbook.bin.rels", rels),
+            ("xl/workbook.bin", &workbook),
+            ("xl/worksheets/sheet1.bin", &worksheet),
+        ]);
+
+        assert_eq!(
+            xlsb_merged_regions(&xlsb, "Statement").unwrap(),
+            vec![Dimensions::new((0, 0), (0, 3))]
+        );
+        assert!(xlsb_merged_regions(&xlsb, "Other").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sheet_size() {
+        let read = |cells: &[(u32, u16)]| {
+            let mut workbook = Workbook::new();
+            let sheet = workbook.add_worksheet().set_name("Statement").unwrap();
+            for (row, col) in cells {
+                sheet.write(*row, *col, "x").unwrap();
+            }
+            Spreadsheet::from_bytes(&workbook.save_to_buffer().unwrap())
+        };
+
+        let spreadsheet = read(&[(1_048_575, 16_383)]).unwrap();
+        assert_eq!(spreadsheet.first_sheet().unwrap().rows, vec![vec!["x"]]);
+
+        assert_eq!(
+            read(&[(0, 0), (0, 2000)]).err().unwrap().to_string(),
+            "Sheet Statement has 2001 columns, more than the limit of 1024"
+        );
+        assert_eq!(
+            read(&[(0, 0), (1_000_000, 20)]).err().unwrap().to_string(),
+            "Sheet Statement has 1000001 rows of 21 columns, more than the limit of 10000000 cells"
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        assert!(Spreadsheet::from_bytes(b"a,b,c\n1,2,3").is_err());
+    }
+}
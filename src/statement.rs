@@ -0,0 +1,73 @@
+//! Definitions shared by the statement parsers, such as [`crate::camt`],
+//! [`crate::mt940`] and [`crate::ofx`].
+
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use rust_decimal::Decimal;
+
+/// Tag type used for the counterparty of an entry
+pub const COUNTERPARTY_TAG_TYPE: &str = "Counterparty";
+/// Tag type used for the remittance information of an entry
+pub const REMITTANCE_TAG_TYPE: &str = "Remittance";
+
+/// Parses an amount written with the separators banks commonly use: `1234.56`,
+/// `-1,234.56`, `1.234,56`, `1 234,56` or `1234,56`. When both `.` and `,` appear the
+/// last one is the decimal separator. A lone comma followed by one or two digits is a
+/// decimal comma, and any other comma groups thousands. A trailing separator, as in
+/// `100,`, is ignored.
+///
+/// Errors name the `format` being parsed, as in `Invalid OFX amount 1.2.3`.
+pub(crate) fn parse_amount(s: &str, format: &str) -> Result<Decimal> {
+    parse_decimal(s).ok_or_else(|| anyhow!("Invalid {} amount {}", format, s.trim()))
+}
+
+/// [`parse_amount`] without the error
+pub(crate) fn parse_decimal(s: &str) -> Option<Decimal> {
+    let number: String = s
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\'' | '\u{a0}'))
+        .collect();
+    let number = number.strip_suffix([',', '.']).unwrap_or(&number);
+
+    let normalized = match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) if comma > dot => number.replace('.', "").replace(',', "."),
+        (None, Some(comma))
+            if number.matches(',').count() == 1
+                && (1..=2).contains(&(number.len() - comma - 1)) =>
+        {
+            number.replace(',', ".")
+        }
+        _ => number.replace(',', ""),
+    };
+    Decimal::from_str(&normalized).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        let cases = [
+            ("1234.56", dec!(1234.56)),
+            ("-1,234.56", dec!(-1234.56)),
+            ("1.234,56", dec!(1234.56)),
+            ("1 234,5", dec!(1234.5)),
+            ("1,234", dec!(1234)),
+            ("100,", dec!(100)),
+            (" -0.01 ", dec!(-0.01)),
+        ];
+        for (s, amount) in cases {
+            assert_eq!(parse_amount(s, "test").unwrap(), amount, "{}", s);
+        }
+
+        assert_eq!(
+            parse_amount("1.2.3", "OFX").unwrap_err().to_string(),
+            "Invalid OFX amount 1.2.3"
+        );
+    }
+}
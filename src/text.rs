@@ -0,0 +1,73 @@
+//! Text helpers shared by the document parsers
+
+/// Decodes HTML and SGML character references, such as `&amp;`, `&euro;` and `&#8364;`.
+/// Unknown or malformed references are kept as written.
+pub(crate) fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                "euro" => '€',
+                "pound" => '£',
+                "yen" => '¥',
+                "cent" => '¢',
+                "copy" => '©',
+                "reg" => '®',
+                "ndash" => '–',
+                "mdash" => '—',
+                _ => {
+                    let code = match entity.strip_prefix('#') {
+                        Some(hex) if hex.starts_with(['x', 'X']) => {
+                            u32::from_str_radix(&hex[1..], 16).ok()
+                        }
+                        Some(dec) => dec.parse().ok(),
+                        None => None,
+                    }?;
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("Fish &amp; Chips &#8364;5 &euro;&#x41; &bogus; 5 & 6"),
+            "Fish & Chips €5 €A &bogus; 5 & 6"
+        );
+    }
+}
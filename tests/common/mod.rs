@@ -0,0 +1,168 @@
+//! A minimal in-process stand-in for the Extism kernel, so the exports generated by the
+//! macros can be called natively. Memory is a per-thread byte heap, which keeps tests
+//! isolated when they run in parallel.
+//!
+//! Only the imports the generated exports link against are provided. Natively these are
+//! plain C symbols, so names such as `free` must not be defined here.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+#[derive(Default)]
+struct Kernel {
+    input: Vec<u8>,
+    heap: Vec<u8>,
+    blocks: HashMap<u64, u64>,
+    output: Option<(u64, u64)>,
+    error: Option<u64>,
+    logs: Vec<String>,
+}
+
+thread_local! {
+    static KERNEL: RefCell<Kernel> = RefCell::new(Kernel {
+        // Offset 0 is reserved so no block is ever allocated there
+        heap: vec![0],
+        ..Kernel::default()
+    });
+}
+
+/// The result of calling a generated export
+#[derive(Debug)]
+pub struct Outcome {
+    pub code: i32,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub logs: Vec<String>,
+}
+
+/// Calls a generated export with `command` wrapped in a `HandlerInput`
+pub fn call<C: Serialize>(
+    export: unsafe extern "C" fn() -> i32,
+    command_type: &str,
+    command: C,
+) -> Outcome {
+    let input = serde_json::json!({
+        "command_type": command_type,
+        "command": command,
+    });
+    call_raw(export, serde_json::to_vec(&input).unwrap())
+}
+
+/// Calls a generated export with the given raw input bytes
+pub fn call_raw(export: unsafe extern "C" fn() -> i32, input: Vec<u8>) -> Outcome {
+    KERNEL.with_borrow_mut(|k| {
+        *k = Kernel {
+            input,
+            heap: vec![0],
+            ..Kernel::default()
+        }
+    });
+
+    let code = unsafe { export() };
+
+    KERNEL.with_borrow(|k| {
+        let read = |offs: u64, len: u64| {
+            String::from_utf8(k.heap[offs as usize..(offs + len) as usize].to_vec()).unwrap()
+        };
+        Outcome {
+            code,
+            output: k.output.map(|(offs, len)| read(offs, len)),
+            error: k.error.map(|offs| read(offs, k.blocks[&offs])),
+            logs: k.logs.clone(),
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn input_length() -> u64 {
+    KERNEL.with_borrow(|k| k.input.len() as u64)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn input_load_u8(offs: u64) -> u8 {
+    KERNEL.with_borrow(|k| k.input[offs as usize])
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn input_load_u64(offs: u64) -> u64 {
+    KERNEL.with_borrow(|k| {
+        let offs = offs as usize;
+        u64::from_le_bytes(k.input[offs..offs + 8].try_into().unwrap())
+    })
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn alloc(length: u64) -> u64 {
+    KERNEL.with_borrow_mut(|k| {
+        let offs = k.heap.len() as u64;
+        k.heap.resize(k.heap.len() + length as usize, 0);
+        k.blocks.insert(offs, length);
+        offs
+    })
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn store_u8(offs: u64, data: u8) {
+    KERNEL.with_borrow_mut(|k| k.heap[offs as usize] = data)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn store_u64(offs: u64, data: u64) {
+    KERNEL.with_borrow_mut(|k| {
+        let offs = offs as usize;
+        k.heap[offs..offs + 8].copy_from_slice(&data.to_le_bytes());
+    })
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn output_set(offs: u64, length: u64) {
+    KERNEL.with_borrow_mut(|k| k.output = Some((offs, length)))
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn error_set(offs: u64) {
+    KERNEL.with_borrow_mut(|k| k.error = Some(offs))
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn get_log_level() -> i32 {
+    // Trace, so every message is logged
+    0
+}
+
+fn log(level: &str, offs: u64) {
+    KERNEL.with_borrow_mut(|k| {
+        let len = k.blocks[&offs];
+        let message = String::from_utf8_lossy(&k.heap[offs as usize..(offs + len) as usize]);
+        let line = format!("{}: {}", level, message);
+        k.logs.push(line);
+    })
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn log_trace(offs: u64) {
+    log("trace", offs)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn log_debug(offs: u64) {
+    log("debug", offs)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn log_info(offs: u64) {
+    log("info", offs)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn log_warn(offs: u64) {
+    log("warn", offs)
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn log_error(offs: u64) {
+    log("error", offs)
+}
@@ -1,9 +1,20 @@
-use chrono::{DateTime, Utc};
-use contour_rust_pdk::command::{Command, Cron, EmptyJoins, Scraper, Transform};
-use contour_rust_pdk::response::{ExtractResponse, TransformResponse};
-use contour_rust_pdk::{extract_fn, transform_fn};
-use extism_pdk::FnResult;
+mod common;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use contour_rust_pdk::command::{
+    Backfill, Command, Cron, EmptyJoins, FileUpload, RecordChange, Scraper, Transform,
+};
+use contour_rust_pdk::csv::CsvReader;
+use contour_rust_pdk::error::PluginError;
+use contour_rust_pdk::inputs::{Effective, EntryInput, LineInput, ResourceSelector};
+use contour_rust_pdk::models::RecordAction;
+use contour_rust_pdk::response::{ExtractReport, ExtractResponse, TransformResponse};
+use contour_rust_pdk::{extract_fn, listener_fn, transform_fn};
+use extism_pdk::{FnResult, WithReturnCode};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
 
 #[test]
 fn test_extract_fn_macro_with_scraper() {
@@ -22,19 +33,94 @@ fn test_extract_fn_macro_with_scraper() {
     }
 
     // Test that the macro generates the correct extern function
-    assert_eq!(unsafe { extract_scraper_data() }, 0);
+    let outcome = common::call(
+        extract_scraper_data,
+        "scraper",
+        json!({ "items": [{ "id": "a", "value": 1 }] }),
+    );
+    assert_eq!(outcome.code, 0);
+    assert_eq!(outcome.output.as_deref(), Some(""));
 }
 
 #[test]
 fn test_extract_fn_macro_with_cron() {
+    // Responses are built with a literal, as plugins written before `ExtractReport` do
     #[extract_fn]
-    pub fn extract_cron_job(_cron: Cron) -> FnResult<Option<ExtractResponse>> {
-        // Process cron job
-        Ok(None)
+    pub fn extract_cron_job(cron: Cron) -> FnResult<Option<ExtractResponse>> {
+        Ok(Some(ExtractResponse {
+            start_date: cron.from,
+            end_date: cron.until,
+        }))
     }
 
     // Test that the macro generates the correct extern function
-    assert_eq!(unsafe { extract_cron_job() }, 0);
+    let outcome = common::call(
+        extract_cron_job,
+        "cron",
+        json!({
+            "from": "2024-01-01T00:00:00Z",
+            "until": "2024-01-02T00:00:00Z",
+            "first": true,
+        }),
+    );
+    assert_eq!(outcome.code, 0);
+    assert_eq!(
+        outcome.output.as_deref(),
+        Some(r#"{"start_date":"2024-01-01T00:00:00Z","end_date":"2024-01-02T00:00:00Z"}"#)
+    );
+}
+
+#[test]
+fn test_extract_fn_macro_with_report() {
+    #[extract_fn]
+    pub fn extract_backfill(backfill: Backfill) -> FnResult<Option<ExtractReport>> {
+        let window = backfill.next_window(TimeDelta::days(365))?;
+        Ok(Some(backfill.respond(&window)))
+    }
+
+    let outcome = common::call(
+        extract_backfill,
+        "backfill",
+        json!({
+            "from": "2022-01-01T00:00:00Z",
+            "until": "2024-01-01T00:00:00Z",
+        }),
+    );
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&outcome.output.unwrap()).unwrap(),
+        json!({
+            "start_date": "2022-01-01T00:00:00Z",
+            "end_date": "2023-01-01T00:00:00Z",
+            "resume_token": "2023-01-01T00:00:00+00:00",
+        })
+    );
+}
+
+#[test]
+fn test_extract_fn_macro_with_alias_and_qualified_path() {
+    type ExtractResult = FnResult<Option<ExtractResponse>>;
+
+    #[extract_fn]
+    pub fn extract_alias(_cron: Cron) -> ExtractResult {
+        Ok(None)
+    }
+
+    #[extract_fn]
+    pub fn extract_qualified(
+        _cron: contour_rust_pdk::command::Cron,
+    ) -> extism_pdk::FnResult<std::option::Option<contour_rust_pdk::response::ExtractResponse>>
+    {
+        Ok(None)
+    }
+
+    let cron = json!({
+        "from": "2024-01-01T00:00:00Z",
+        "until": "2024-01-02T00:00:00Z",
+        "first": false,
+    });
+    assert_eq!(common::call(extract_alias, "cron", cron.clone()).code, 0);
+    assert_eq!(common::call(extract_qualified, "cron", cron).code, 0);
 }
 
 #[test]
@@ -52,7 +138,95 @@ fn test_extract_fn_macro_with_command() {
     }
 
     // Test that the macro generates the correct extern function
-    assert_eq!(unsafe { extract_command() }, 0);
+    let outcome = common::call(
+        extract_command,
+        "manual",
+        json!({ "command": { "action": "sync", "params": [] } }),
+    );
+    assert_eq!(outcome.code, 0);
+}
+
+#[test]
+fn test_extract_fn_macro_decodes_command_by_type() {
+    // A manual command whose payload has a `command` field of its own
+    #[derive(Deserialize, Serialize)]
+    struct Nested {
+        command: String,
+    }
+
+    #[extract_fn]
+    pub fn extract_tagged(cmd: Command<Nested>) -> FnResult<Option<ExtractResponse>> {
+        match cmd {
+            Command::Manual(manual) if manual.command.command == "resync" => Ok(None),
+            _ => Err(anyhow::anyhow!("Unexpected command").into()),
+        }
+    }
+
+    let outcome = common::call(
+        extract_tagged,
+        "manual",
+        json!({ "command": { "command": "resync" } }),
+    );
+    assert_eq!(outcome.code, 0);
+
+    let outcome = common::call(extract_tagged, "cron", json!({ "command": "resync" }));
+    assert_eq!(outcome.code, 2);
+    assert!(outcome.error.unwrap().contains("Invalid cron command"));
+
+    // Older hosts send no `command_type`, so the command is decoded untagged
+    let command = json!({ "command": { "command": "resync" } });
+    let input = json!({ "command": command });
+    let outcome = common::call_raw(extract_tagged, serde_json::to_vec(&input).unwrap());
+    assert_eq!(outcome.code, 0);
+
+    let outcome = common::call(extract_tagged, "", command);
+    assert_eq!(outcome.code, 0);
+}
+
+#[test]
+fn test_extract_fn_macro_with_file_upload() {
+    #[derive(Deserialize)]
+    struct Row {
+        #[serde(rename = "Date")]
+        date: String,
+        #[serde(rename = "Amount")]
+        amount: f64,
+    }
+
+    #[extract_fn]
+    pub fn extract_upload(upload: FileUpload) -> FnResult<Option<ExtractResponse>> {
+        let rows: Vec<Row> = upload.file.table(&CsvReader::new())?.deserialize()?;
+        let account = upload.hints.account.as_deref().unwrap_or("Imported");
+        let total: f64 = rows.iter().map(|row| row.amount).sum();
+        if account != "Checking" || rows[1].date != "2024-03-02" || total != 96.5 {
+            return Err(anyhow::anyhow!("Unexpected upload").into());
+        }
+        Ok(None)
+    }
+
+    let upload = json!({
+        "filename": "march.csv",
+        "content_type": "text/csv",
+        // Date,Amount\n2024-03-01,-3.50\n2024-03-02,100.00\n
+        "data": "RGF0ZSxBbW91bnQKMjAyNC0wMy0wMSwtMy41MAoyMDI0LTAzLTAyLDEwMC4wMAo=",
+        "hints": { "account": "Checking" },
+    });
+    let outcome = common::call(extract_upload, "file_upload", upload.clone());
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
+
+    // The same upload reaches a `Command` handler as `Command::FileUpload`
+    #[extract_fn]
+    pub fn extract_any(cmd: Command<()>) -> FnResult<Option<ExtractResponse>> {
+        match cmd {
+            Command::FileUpload(upload) if upload.file.filename() == Some("march.csv".into()) => {
+                Ok(None)
+            }
+            _ => Err(anyhow::anyhow!("Unexpected command").into()),
+        }
+    }
+
+    let outcome = common::call(extract_any, "file_upload", upload);
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
 }
 
 #[test]
@@ -78,5 +252,235 @@ fn test_transform_fn_macro() {
     }
 
     // Test that the macro generates the correct extern function
-    assert_eq!(unsafe { transform_records() }, 0);
+    let outcome = common::call(transform_records, "transform", json!({ "records": [] }));
+    assert_eq!(outcome.code, 0);
+    assert_eq!(outcome.output.as_deref(), Some("\"None\""));
+}
+
+#[test]
+fn test_transform_fn_macro_arguments() {
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    struct Payment {
+        amount: Decimal,
+    }
+
+    #[transform_fn(name = "transform_payments", record_type = "Payment", validate = true)]
+    pub fn transform(
+        transform: Transform<Payment, EmptyJoins, ()>,
+    ) -> FnResult<TransformResponse<()>> {
+        let record = &transform.records[0];
+        let mut lines = LineInput::transfer(
+            ResourceSelector::Id(Uuid::nil()),
+            ResourceSelector::Id(Uuid::max()),
+            record.record.amount,
+            None,
+            vec![],
+        );
+        // Negative payments are posted unbalanced, to fail validation
+        if record.record.amount.is_sign_negative() {
+            lines.pop();
+        }
+        Ok(TransformResponse::EntryInput(EntryInput::new(
+            Effective::Date(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()),
+            record.source_key.clone(),
+            "Payment".to_string(),
+            lines,
+        )))
+    }
+
+    unsafe extern "C" {
+        fn transform_payments() -> i32;
+    }
+
+    let record = |record_type: &str, amount: &str| {
+        json!({
+            "records": [{
+                "source_key": "pay-1",
+                "record_type": record_type,
+                "sys_period_start": null,
+                "sys_period_end": null,
+                "record": { "amount": amount },
+                "metadata": null,
+                "joins": {},
+            }]
+        })
+    };
+
+    let outcome = common::call(transform_payments, "transform", record("Payment", "12.50"));
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
+    let output: serde_json::Value = serde_json::from_str(&outcome.output.unwrap()).unwrap();
+    assert_eq!(output["EntryInput"]["source_key"], "pay-1");
+    assert_eq!(outcome.logs, vec!["info: Entry pay-1 with 2 lines"]);
+
+    // Records of other types never reach the handler
+    let outcome = common::call(transform, "transform", record("Refund", "12.50"));
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
+    assert_eq!(outcome.output.as_deref(), Some("\"None\""));
+
+    let outcome = common::call(transform, "transform", record("Payment", "-3"));
+    assert_eq!(outcome.code, 2);
+    assert_eq!(
+        outcome.logs,
+        vec!["warn: Entry pay-1 does not balance: debits are 0 and credits are 3"]
+    );
+}
+
+#[test]
+fn test_listener_fn_macro() {
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    struct Payment {
+        amount: Decimal,
+    }
+
+    // Posts the difference when a payment's amount is corrected
+    #[listener_fn(record_type = "Payment", validate = true)]
+    pub fn listen_payments(change: RecordChange<Payment>) -> FnResult<TransformResponse<()>> {
+        let (RecordAction::Update, Some(old), Some(new), Some(changed)) = (
+            change.action,
+            &change.old,
+            &change.new,
+            change.sys_period_start,
+        ) else {
+            return Ok(TransformResponse::None);
+        };
+        Ok(TransformResponse::EntryInput(EntryInput::new(
+            Effective::DateTime(changed),
+            format!("{}-correction", change.source_key),
+            "Correction".to_string(),
+            LineInput::transfer(
+                ResourceSelector::Id(Uuid::nil()),
+                ResourceSelector::Id(Uuid::max()),
+                new.amount - old.amount,
+                None,
+                vec![],
+            ),
+        )))
+    }
+
+    let change = |record_type: &str, action: &str| {
+        json!({
+            "action": action,
+            "source_key": "pay-1",
+            "record_type": record_type,
+            "old": { "amount": "10" },
+            "new": { "amount": "12.50" },
+            "sys_period_start": "2024-03-01T00:00:00Z",
+            "sys_period_end": null,
+        })
+    };
+
+    let outcome = common::call(listen_payments, "listener", change("Payment", "UPDATE"));
+    assert_eq!(outcome.code, 0, "{:?}", outcome.error);
+    let output: serde_json::Value = serde_json::from_str(&outcome.output.unwrap()).unwrap();
+    assert_eq!(output["EntryInput"]["source_key"], "pay-1-correction");
+    assert_eq!(output["EntryInput"]["lines"][0]["debit"], "2.50");
+    assert_eq!(
+        outcome.logs,
+        vec!["info: Entry pay-1-correction with 2 lines"]
+    );
+
+    let outcome = common::call(listen_payments, "listener", change("Payment", "INSERT"));
+    assert_eq!(outcome.output.as_deref(), Some("\"None\""));
+
+    let outcome = common::call(listen_payments, "listener", change("Refund", "UPDATE"));
+    assert_eq!(outcome.output.as_deref(), Some("\"None\""));
+
+    let outcome = common::call(listen_payments, "listener", change("Payment", "UPSERT"));
+    assert_eq!(outcome.code, 2);
+}
+
+#[test]
+fn test_extract_fn_macro_reports_invalid_input() {
+    #[extract_fn]
+    pub fn extract_invalid_input(_cron: Cron) -> FnResult<Option<ExtractResponse>> {
+        Ok(None)
+    }
+
+    let outcome = common::call(
+        extract_invalid_input,
+        "cron",
+        json!({ "from": "yesterday" }),
+    );
+    assert_eq!(outcome.code, 2);
+    let error: PluginError = serde_json::from_str(&outcome.error.unwrap()).unwrap();
+    assert!(matches!(error, PluginError::InvalidInput { .. }));
+}
+
+#[test]
+fn test_extract_fn_macro_reports_plugin_errors() {
+    #[extract_fn]
+    pub fn extract_failing(cron: Cron) -> FnResult<Option<ExtractResponse>> {
+        if cron.first {
+            let error: anyhow::Error = PluginError::rate_limited("Slow down", Some(30)).into();
+            Err(error.context("Listing transactions"))?;
+        }
+        Err(anyhow::anyhow!("Unexpected response").into())
+    }
+
+    let cron = |first: bool| {
+        json!({
+            "from": "2024-01-01T00:00:00Z",
+            "until": "2024-01-02T00:00:00Z",
+            "first": first,
+        })
+    };
+
+    let outcome = common::call(extract_failing, "cron", cron(true));
+    assert_eq!(outcome.code, 5);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&outcome.error.unwrap()).unwrap(),
+        json!({
+            "code": "rate_limited",
+            "message": "Listing transactions: Slow down",
+            "retry_after": 30,
+        })
+    );
+
+    let outcome = common::call(extract_failing, "cron", cron(false));
+    assert_eq!(outcome.code, 1);
+    assert_eq!(
+        outcome.error.as_deref(),
+        Some(r#"{"code":"internal","message":"Unexpected response"}"#)
+    );
+
+    #[extract_fn]
+    pub fn extract_with_status(_cron: Cron) -> FnResult<Option<ExtractResponse>> {
+        Err(WithReturnCode::new(anyhow::anyhow!("Account closed"), 42))
+    }
+
+    let outcome = common::call(extract_with_status, "cron", cron(false));
+    assert_eq!(outcome.code, 42);
+    assert_eq!(
+        outcome.error.as_deref(),
+        Some(r#"{"code":"internal","message":"Account closed"}"#)
+    );
+}
+
+#[test]
+fn test_extract_fn_macro_reports_panics() {
+    #[extract_fn]
+    pub fn extract_panicking(cron: Cron) -> FnResult<Option<ExtractResponse>> {
+        let rows: Vec<&str> = Vec::new();
+        if cron.first {
+            panic!("Row {} is missing", rows.len());
+        }
+        Ok(None)
+    }
+
+    let cron = json!({
+        "from": "2024-01-01T00:00:00Z",
+        "until": "2024-01-02T00:00:00Z",
+        "first": true,
+    });
+    let outcome = common::call(extract_panicking, "cron", cron);
+    assert_eq!(outcome.code, PluginError::PANIC_EXIT_CODE);
+    let error: serde_json::Value = serde_json::from_str(&outcome.error.unwrap()).unwrap();
+    assert_eq!(error["code"], "panic");
+    assert_eq!(error["message"], "Row 0 is missing");
+    assert!(
+        error["location"]
+            .as_str()
+            .unwrap()
+            .starts_with("tests/test_macro.rs:")
+    );
 }